@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 pub const GREETINGS: &str = "
     \rWelcome to rsniffer!
@@ -67,25 +67,356 @@ pub struct Args {
     #[clap(short, long)]
     pub list_devices: bool,
 
-    /// Sets output file
-    #[arg(short, long, default_value="rsniffer_report.txt")]
+    /// Sets output file ("-" for the standard output).
+    /// The report is replaced atomically at each interval, so that readers never see a truncated file.
+    /// [default: rsniffer_report.txt, unless the report is only stored into a database]
+    #[arg(short, long)]
     pub output: Option<String>,
 
-    /// Sorts captured traffic accorting to specified criteria.
-    /// The criteria must be specified as XY, where:
+    /// Sets the output format:
+    ///  - text     a header with the configuration of the capture followed by an ASCII table
+    ///  - csv      a header row followed by one row per record, with raw byte counts and ISO 8601 timestamps
+    ///  - json     a single document with the configuration of the capture and an array of records
+    ///  - ndjson   one JSON object per line and per record, appended to the output file at each interval
+    ///  - html     a self-contained page with charts and a sortable and filterable table, that works offline
+    ///  - markdown a bullet list with the configuration followed by a GitHub-flavoured table (alias: md)
+    /// 
+    /// In machine readable formats, the configuration is written along with the records
+    /// (as comment lines starting with '#' in CSV), unless --sidecar is given.
+    /// 
+    /// [default: text]
+    #[arg(long, verbatim_doc_comment)]
+    pub format: Option<String>,
+
+    /// Writes the configuration of the capture to a separate file, named after the output file with the ".meta.csv" suffix.
+    /// Only applies to machine readable formats.
+    #[arg(long, verbatim_doc_comment)]
+    pub sidecar: bool,
+
+    /// Sorts captured traffic according to specified criteria.
+    /// The criteria must be specified as a comma separated list of field[:asc|:desc], where field is one of
+    /// src_ip, dst_ip, src_port, dst_port, protocols, bytes, packets, first_ts, last_ts,
+    /// duration, avg_bps, avg_pps, cur_bps, cur_pps, frame_bytes, captured_bytes, ip_bytes, payload_bytes.
+    /// Keys are applied in the given order, and remaining ties are broken by the fields identifying the record.
+    /// 
+    /// The legacy XY syntax is still accepted, where:
     ///  - X is a number identifying the field, from 0 (SRC_IP) to 8 (LAST TIMESTAMP)
     ///  - Y is 'G' (Greater to Lower) or 'L' (Lower to Greater)
     /// 
     /// Examples:
-    ///     rsniffer -s 6G      sorting by number of packets, greater to lower
-    ///     rsniffer -s 8G      sorting by last timestamp, greater to lower
-    ///     rsniffer -s 0L      sorting by source ip, lower to greater
+    ///     rsniffer -s bytes:desc,dst_ip:asc   sorting by bytes, greater to lower, then by destination ip
+    ///     rsniffer -s last_ts:desc            sorting by last timestamp, greater to lower
+    ///     rsniffer -s 6G                      sorting by number of packets, greater to lower
     #[arg(short, long, verbatim_doc_comment)]
     pub sort: Option<String>,
 
+    /// Adds the specified columns to the report, given as a comma separated list. Available columns:
+    ///  - duration             time elapsed between the first and the last packet
+    ///  - avg_bps, avg_pps     average bits and packets per second over the lifetime of the record
+    ///  - cur_bps, cur_pps     bits and packets per second over the last interval
+    ///  - frame_bytes, captured_bytes, ip_bytes, payload_bytes     bytes counted with each of the lengths of --bytes
+    ///  - interface            interfaces the packets have been captured on (several ones in pcapng captures)
+    ///  - vlan                 802.1Q/802.1ad VLAN IDs, the outer one first when tags are stacked (e.g. 100.20)
+    ///  - ext_headers          IPv6 extension headers found before the transport layer header (e.g. HOPOPT, IPv6-Frag)
+    ///  - src_mac, dst_mac     Ethernet addresses of the frames
+    ///  - src_vendor, dst_vendor   vendors of the network interfaces, resolved from their MAC addresses (see --oui)
+    /// 
+    /// The keys of the records (see --aggregate), their bytes, packets, first_ts and last_ts are always listed.
+    /// 
+    /// Example:
+    ///     rsniffer -c duration,cur_bps -s cur_bps:desc
+    #[arg(short, long, verbatim_doc_comment)]
+    pub columns: Option<String>,
+
+    /// Resolves the vendors of the network interfaces with the given OUI database, either the manuf file
+    /// shipped with Wireshark or the oui.txt registry published by the IEEE. Unless given, the usual places
+    /// (e.g. /usr/share/wireshark/manuf or /usr/share/ieee-data/oui.txt) are searched when vendors are requested.
+    /// 
+    /// Example:
+    ///     rsniffer -c src_mac,src_vendor --oui /usr/share/wireshark/manuf
+    #[arg(long, value_name = "FILE", verbatim_doc_comment)]
+    pub oui: Option<String>,
+
+    /// Sets the length counted by the BYTES column:
+    ///  - frame        original length of the frame on the wire (matches interface counters)
+    ///  - captured     length of the captured part of the frame
+    ///  - ip           total length of the IP packet
+    ///  - payload      length of the transport layer payload
+    /// 
+    /// [default: payload]
+    #[arg(short, long, verbatim_doc_comment)]
+    pub bytes: Option<String>,
+
+    /// Aggregates captured traffic according to the specified fields, given as a comma separated list.
+    /// Available fields: src_ip, dst_ip, src_port, dst_port, protocol, src_subnet, dst_subnet, src_group, dst_group, interface, vlan.
+    /// Subnets are /24 for IPv4 and /64 for IPv6, while groups are defined with --groups.
+    /// 
+    /// [default: src_ip,dst_ip,src_port,dst_port]
+    /// 
+    /// Examples:
+    ///     rsniffer -a src_ip                  top talkers by host
+    ///     rsniffer -a dst_port,protocol       traffic per destination service
+    #[arg(short, long, verbatim_doc_comment)]
+    pub aggregate: Option<String>,
+
+    /// Defines the groups used when aggregating by src_group or dst_group, as a comma separated list of name=CIDR.
+    /// Addresses not belonging to any group are reported as "other".
+    /// 
+    /// Example:
+    ///     rsniffer -a src_group --groups "lan=192.168.1.0/24,vpn=10.8.0.0/16"
+    #[arg(long, verbatim_doc_comment)]
+    pub groups: Option<String>,
+
+    /// Writes only the top N records according to the sorting criteria, followed by a row summing up all the others.
+    /// 
+    /// Example:
+    ///     rsniffer -s bytes:desc -n 10        writes the 10 records with the most transmitted bytes
+    #[arg(short = 'n', long, verbatim_doc_comment)]
+    pub limit: Option<usize>,
+
+    /// Removes from the report the records for which no packet has been captured for the given time (in seconds).
+    #[arg(long)]
+    pub idle_timeout: Option<u64>,
+
+    /// Removes from the report the records lasting for the given time (in seconds), even if still active.
+    /// Further packets are accounted in a new record.
+    #[arg(long, verbatim_doc_comment)]
+    pub active_timeout: Option<u64>,
+
+    /// Sets the maximum number of records tracked by the report. When the limit is reached,
+    /// records are evicted according to the policy set with --eviction.
+    #[arg(long, verbatim_doc_comment, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub max_flows: Option<usize>,
+
+    /// Sets the eviction policy used when the maximum number of records is reached:
+    ///  - lru          evicts the records idle for the longest time
+    ///  - oldest       evicts the records seen first
+    ///  - smallest     evicts the records with the fewest transmitted bytes
+    /// 
+    /// [default: lru]
+    #[arg(long, verbatim_doc_comment)]
+    pub eviction: Option<String>,
+
+    /// Appends the records removed from the report (because expired or evicted) to the given file.
+    #[arg(long)]
+    pub closed_flows: Option<String>,
+
+    /// Lists in each report only the records active during the last interval,
+    /// with bytes and packets counted over that interval rather than since the start of the capture.
+    #[arg(long, verbatim_doc_comment)]
+    pub delta: bool,
+
+    /// Appends the counters of each interval (bytes, packets, new and active records) to the given file, in CSV format.
+    #[arg(long)]
+    pub timeseries: Option<String>,
+
+    /// Exports flows over UDP to the given collector, when they expire and when the capture is stopped.
+    /// Flows only expire during the capture when --idle-timeout or --active-timeout are set, which remove
+    /// them from the report as well.
+    /// 
+    /// Example:
+    ///     rsniffer --collector 127.0.0.1:2055 --flow-version ipfix --idle-timeout 15 --active-timeout 1800
+    #[arg(long, verbatim_doc_comment)]
+    pub collector: Option<String>,
+
+    /// Sets the version of the protocol used to export flows:
+    ///  - v5       NetFlow v5, IPv4 flows only
+    ///  - v9       NetFlow v9
+    ///  - ipfix    IPFIX
+    /// 
+    /// [default: v9]
+    #[arg(long, verbatim_doc_comment)]
+    pub flow_version: Option<String>,
+
+    /// Serves metrics in the Prometheus text format at http://ADDR/metrics, e.g. packets and bytes by protocol,
+    /// counters of the top records, parser errors, packets dropped by pcap and the state of the sniffer.
+    /// 
+    /// Example:
+    ///     rsniffer --metrics 127.0.0.1:9184
+    #[arg(long, value_name = "ADDR", verbatim_doc_comment)]
+    pub metrics: Option<String>,
+
+    /// Stores the records of the report into the given SQLite database at each interval, along with the totals
+    /// of the interval and the configuration of the capture, so that past captures can be queried with SQL.
+    /// All the records updated during the interval are stored, whatever --limit. The report is then only written
+    /// to a file if --output is given.
+    #[arg(long, verbatim_doc_comment)]
+    pub database: Option<String>,
+
+    /// Saves the captured packets to the given file, in pcapng format, recording the name of the device
+    /// and the BPF filter in the interface description. The file can be read back with the query, diff
+    /// and convert commands, or with any pcapng capable tool.
+    #[arg(long, value_name = "FILE", verbatim_doc_comment)]
+    pub save: Option<String>,
+
     /// Sets the time interval (in seconds) after which an updated version of the report will be generated.
     /// 
     /// [default: 3]
     #[arg(short, long)]
-    pub time_interval: Option<u64>
-}
\ No newline at end of file
+    pub time_interval: Option<u64>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>
+}
+
+/// Commands working offline, on stored reports or captures, rather than capturing traffic
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Queries the records stored by a report in text, Markdown, CSV, JSON, NDJSON or SQLite format,
+    /// or the packets of a pcap or pcapng capture.
+    /// 
+    /// Example:
+    ///     rsniffer query rsniffer.db --where "dst_port=22,last_ts>=2022-11-07" -a src_ip -s bytes:desc -n 10
+    #[command(verbatim_doc_comment)]
+    Query(QueryArgs),
+
+    /// Compares two reports (text, Markdown, CSV, JSON, NDJSON or SQLite) or captures (pcap or pcapng), listing the records
+    /// that are new, removed or changed in the second one, with their byte and packet deltas.
+    /// 
+    /// Example:
+    ///     rsniffer diff before.pcap after.pcap -a dst_ip,dst_port -s bytes_delta:desc
+    #[command(verbatim_doc_comment)]
+    Diff(DiffArgs),
+
+    /// Converts a pcap or pcapng capture, or a report in text, Markdown, CSV, JSON, NDJSON or SQLite format, to another format.
+    /// Unless given, the output format is guessed from the extension of the output file.
+    /// 
+    /// Example:
+    ///     rsniffer convert trace.pcapng -o trace.html
+    #[command(verbatim_doc_comment)]
+    Convert(ConvertArgs)
+}
+
+#[derive(clap::Args, Debug)]
+pub struct QueryArgs {
+    /// Report the records are read from
+    pub input: String,
+
+    /// Selects the records meeting all the given conditions, each one given as field, operator (=, !=, >, >=, < or <=) and value.
+    /// Addresses can be compared with networks in CIDR notation, and timestamps are given as seconds since epoch,
+    /// RFC 3339, "YYYY-MM-DD hh:mm:ss" or "YYYY-MM-DD" (local time).
+    /// 
+    /// Example:
+    ///     rsniffer query report.csv --where "src_ip=10.0.0.0/8,dst_port=443,bytes>1000000"
+    #[arg(long = "where", value_name = "CONDITIONS", verbatim_doc_comment)]
+    pub conditions: Option<String>,
+
+    /// Sets the fields that identify a record (see rsniffer --help).
+    #[arg(short, long)]
+    pub aggregate: Option<String>,
+
+    /// Defines the groups used by the src_group and dst_group aggregation criteria, as name=CIDR.
+    #[arg(long)]
+    pub groups: Option<String>,
+
+    /// Sets the sorting criteria (see rsniffer --help).
+    #[arg(short, long)]
+    pub sort: Option<String>,
+
+    /// Writes only the top N records according to the sorting criteria.
+    #[arg(short = 'n', long)]
+    pub limit: Option<usize>,
+
+    /// Adds the given columns to the result (see rsniffer --help).
+    #[arg(short, long)]
+    pub columns: Option<String>,
+
+    /// Resolves the vendors of the network interfaces with the given OUI database (see rsniffer --help).
+    #[arg(long, value_name = "FILE")]
+    pub oui: Option<String>,
+
+    /// Sets the length used to count the bytes of the packets of captures (see rsniffer --help).
+    #[arg(long)]
+    pub bytes: Option<String>,
+
+    /// Sets the output format (see rsniffer --help). [default: text]
+    #[arg(long)]
+    pub format: Option<String>,
+
+    /// Writes the result to the given file rather than to the standard output.
+    #[arg(short, long)]
+    pub output: Option<String>
+}
+
+#[derive(clap::Args, Debug)]
+pub struct DiffArgs {
+    /// Old report or capture
+    pub old: String,
+
+    /// New report or capture
+    pub new: String,
+
+    /// Sets the fields that identify a record (see rsniffer --help).
+    #[arg(short, long)]
+    pub aggregate: Option<String>,
+
+    /// Defines the groups used by the src_group and dst_group aggregation criteria, as name=CIDR.
+    #[arg(long)]
+    pub groups: Option<String>,
+
+    /// Sets the sorting criteria (see rsniffer --help). Records can be sorted by change, bytes_delta and packets_delta as well.
+    #[arg(short, long)]
+    pub sort: Option<String>,
+
+    /// Writes only the top N records according to the sorting criteria.
+    #[arg(short = 'n', long)]
+    pub limit: Option<usize>,
+
+    /// Adds the given columns to the result (see rsniffer --help).
+    #[arg(short, long)]
+    pub columns: Option<String>,
+
+    /// Resolves the vendors of the network interfaces with the given OUI database (see rsniffer --help).
+    #[arg(long, value_name = "FILE")]
+    pub oui: Option<String>,
+
+    /// Sets the length used to count the bytes of the packets of captures (see rsniffer --help).
+    #[arg(long)]
+    pub bytes: Option<String>,
+
+    /// Sets the output format (see rsniffer --help). [default: text]
+    #[arg(long)]
+    pub format: Option<String>,
+
+    /// Writes the result to the given file rather than to the standard output.
+    #[arg(short, long)]
+    pub output: Option<String>
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ConvertArgs {
+    /// Capture or report to convert
+    pub input: String,
+
+    /// Sets the fields that identify a record (see rsniffer --help).
+    #[arg(short, long)]
+    pub aggregate: Option<String>,
+
+    /// Defines the groups used by the src_group and dst_group aggregation criteria, as name=CIDR.
+    #[arg(long)]
+    pub groups: Option<String>,
+
+    /// Sets the sorting criteria (see rsniffer --help).
+    #[arg(short, long)]
+    pub sort: Option<String>,
+
+    /// Adds the given columns to the result (see rsniffer --help).
+    #[arg(short, long)]
+    pub columns: Option<String>,
+
+    /// Resolves the vendors of the network interfaces with the given OUI database (see rsniffer --help).
+    #[arg(long, value_name = "FILE")]
+    pub oui: Option<String>,
+
+    /// Sets the length used to count the bytes of the packets of captures (see rsniffer --help).
+    #[arg(long)]
+    pub bytes: Option<String>,
+
+    /// Sets the output format (see rsniffer --help). [default: guessed from the output file, or text]
+    #[arg(long)]
+    pub format: Option<String>,
+
+    /// Writes the result to the given file rather than to the standard output.
+    #[arg(short, long)]
+    pub output: Option<String>
+}
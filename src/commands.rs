@@ -0,0 +1,66 @@
+use crate::args::{Command, QueryArgs, DiffArgs, ConvertArgs};
+use snifferlib::{Query, Diff, OutputFormat, SnifferError};
+use std::{fs::File, io::{self, Write}, path::Path, process};
+
+/// Runs a command working offline and exits, with a non-zero status if something went wrong
+pub fn run(command: Command) {
+    let res = match command {
+        Command::Query(args) => query(args),
+        Command::Diff(args) => diff(args),
+        Command::Convert(args) => convert(args)
+    };
+
+    if let Err(e) = res {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn query(args: QueryArgs) -> Result<(), SnifferError> {
+    let query = Query::new(args.input).filter(args.conditions).aggregate(args.aggregate).groups(args.groups)
+                        .sort(args.sort).limit(args.limit).columns(args.columns).oui(args.oui).bytes(args.bytes)
+                        .format(args.format);
+
+    let mut out = open_output(&args.output)?;
+    query.run(&mut out)
+}
+
+// Converting is querying without any condition nor limit
+fn convert(args: ConvertArgs) -> Result<(), SnifferError> {
+    let format = args.format.or_else(|| args.output.as_deref().and_then(guess_format));
+    let query = Query::new(args.input).aggregate(args.aggregate).groups(args.groups).sort(args.sort)
+                        .columns(args.columns).oui(args.oui).bytes(args.bytes).format(format);
+
+    let mut out = open_output(&args.output)?;
+    query.run(&mut out)
+}
+
+// Guesses the output format from the extension of the given file
+fn guess_format(path: &str) -> Option<String> {
+    let format = match Path::new(path).extension()?.to_str()? {
+        "txt" => OutputFormat::Text,
+        "htm" => OutputFormat::Html,
+        ext => OutputFormat::from_name(ext)?
+    };
+
+    Some(String::from(format.name()))
+}
+
+fn diff(args: DiffArgs) -> Result<(), SnifferError> {
+    let diff = Diff::new(args.old, args.new).aggregate(args.aggregate).groups(args.groups).sort(args.sort)
+                   .limit(args.limit).columns(args.columns).oui(args.oui).bytes(args.bytes).format(args.format);
+
+    let mut out = open_output(&args.output)?;
+    diff.run(&mut out)
+}
+
+// Results are written to the given file, if any, or to the standard output
+fn open_output(path: &Option<String>) -> Result<Box<dyn Write>, SnifferError> {
+    match path {
+        Some(p) => match File::create(p) {
+            Ok(file) => Ok(Box::new(file)),
+            Err(e) => Err(SnifferError::new(format!("Couldn't create {}. {}", p, e)))
+        },
+        None => Ok(Box::new(io::stdout()))
+    }
+}
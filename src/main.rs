@@ -14,6 +14,7 @@ use crossterm::{execute, Result};
 use crate::args::GREETINGS;
 
 mod args;
+mod commands;
 
 enum AppState {
     Running,
@@ -23,7 +24,13 @@ enum AppState {
 
 #[doc(hidden)]
 fn main() {
-    let args = Args::parse();
+    let mut args = Args::parse();
+
+    // Offline commands do not need the terminal
+    if let Some(command) = args.command.take() {
+        commands::run(command);
+        return;
+    }
 
     setup_terminal();
     
@@ -93,7 +100,38 @@ fn main() {
         None => None
     };
 
-    let sniffer = match Sniffer::builder().device(String::from(&device)).out(out).filter(filter).sort(sort).interval(interval).capture() {
+    let aggregation = args.aggregate.clone();
+    let groups = args.groups.clone();
+    let limit = args.limit;
+    let eviction = args.eviction.clone();
+    let closed_flows = args.closed_flows.clone();
+    let timeseries = args.timeseries.clone();
+    let columns = args.columns.clone();
+    let bytes = args.bytes.clone();
+    let format = args.format.clone();
+    let collector = args.collector.clone();
+    let flow_version = args.flow_version.clone();
+    let metrics = args.metrics.clone();
+    let database = args.database.clone();
+    let save = args.save.clone();
+    let oui = args.oui.clone();
+
+    let mut builder = Sniffer::builder();
+
+    // The terminal is in raw mode, so the report needs carriage returns to be printed properly
+    if out.as_deref() == Some("-") {
+        builder = builder.writer(Box::new(RawStdout));
+    }
+
+    let sniffer = match builder.device(String::from(&device)).out(out).filter(filter).sort(sort)
+                        .aggregate(aggregation).groups(groups).limit(limit)
+                        .idle_timeout(args.idle_timeout).active_timeout(args.active_timeout)
+                        .max_flows(args.max_flows).eviction(eviction).closed_flows(closed_flows)
+                        .delta(args.delta).timeseries(timeseries).columns(columns).oui(oui).bytes(bytes)
+                        .format(format).sidecar(args.sidecar)
+                        .collector(collector).flow_version(flow_version).metrics(metrics)
+                        .database(database).save(save)
+                        .interval(interval).capture() {
         Ok(s) => s,
         Err(e) => {
             err_and_clean(e.to_string());
@@ -114,6 +152,27 @@ fn main() {
 }
 
 
+/// Standard output for the terminal in raw mode, where each new line needs a carriage return
+#[doc(hidden)]
+struct RawStdout;
+
+impl Write for RawStdout {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut out = io::stdout().lock();
+        for line in buf.split_inclusive(|b| *b == b'\n') {
+            out.write_all(line)?;
+            if line.ends_with(b"\n") {
+                out.write_all(b"\r")?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
 #[doc(hidden)]
 fn show_capture() -> Sender<AppState> {
     let (tx, rx) = mpsc::channel();
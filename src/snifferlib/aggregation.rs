@@ -0,0 +1,317 @@
+use super::report::{TrafficDetail, format_list};
+use super::field::Field;
+use ipnetwork::IpNetwork;
+use std::net::IpAddr;
+
+// prefix lengths used when aggregating by subnet
+const V4_SUBNET_PREFIX: u8 = 24;
+const V6_SUBNET_PREFIX: u8 = 64;
+
+// label used for addresses not belonging to any of the defined groups
+const NO_GROUP: &str = "other";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dimension {
+    SrcHost,
+    DstHost,
+    SrcPort,
+    DstPort,
+    Protocol,
+    SrcSubnet,
+    DstSubnet,
+    SrcGroup,
+    DstGroup,
+    Interface,
+    Vlan
+}
+
+impl Dimension {
+    pub fn from_name(name: &str) -> Option<Dimension> {
+        match name.trim() {
+            "src_ip" => Some(Dimension::SrcHost),
+            "dst_ip" => Some(Dimension::DstHost),
+            "src_port" => Some(Dimension::SrcPort),
+            "dst_port" => Some(Dimension::DstPort),
+            "protocol" => Some(Dimension::Protocol),
+            "src_subnet" => Some(Dimension::SrcSubnet),
+            "dst_subnet" => Some(Dimension::DstSubnet),
+            "src_group" => Some(Dimension::SrcGroup),
+            "dst_group" => Some(Dimension::DstGroup),
+            "interface" => Some(Dimension::Interface),
+            "vlan" => Some(Dimension::Vlan),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Dimension::SrcHost => "src_ip",
+            Dimension::DstHost => "dst_ip",
+            Dimension::SrcPort => "src_port",
+            Dimension::DstPort => "dst_port",
+            Dimension::Protocol => "protocol",
+            Dimension::SrcSubnet => "src_subnet",
+            Dimension::DstSubnet => "dst_subnet",
+            Dimension::SrcGroup => "src_group",
+            Dimension::DstGroup => "dst_group",
+            Dimension::Interface => "interface",
+            Dimension::Vlan => "vlan"
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Dimension::SrcHost => "SRC_IP",
+            Dimension::DstHost => "DST_IP",
+            Dimension::SrcPort => "SRC_PORT",
+            Dimension::DstPort => "DST_PORT",
+            Dimension::Protocol => "PROTOCOLS",
+            Dimension::SrcSubnet => "SRC_SUBNET",
+            Dimension::DstSubnet => "DST_SUBNET",
+            Dimension::SrcGroup => "SRC_GROUP",
+            Dimension::DstGroup => "DST_GROUP",
+            Dimension::Interface => "INTERFACE",
+            Dimension::Vlan => "VLAN"
+        }
+    }
+
+    /// Returns the field holding the value of this dimension once applied
+    pub fn field(&self) -> Field {
+        match self {
+            Dimension::SrcHost | Dimension::SrcSubnet | Dimension::SrcGroup => Field::SrcIp,
+            Dimension::DstHost | Dimension::DstSubnet | Dimension::DstGroup => Field::DstIp,
+            Dimension::SrcPort => Field::SrcPort,
+            Dimension::DstPort => Field::DstPort,
+            Dimension::Protocol => Field::Protocols,
+            Dimension::Interface => Field::Interface,
+            Dimension::Vlan => Field::Vlan
+        }
+    }
+
+    pub fn value(&self, detail: &TrafficDetail) -> String {
+        match self {
+            Dimension::SrcHost | Dimension::SrcSubnet | Dimension::SrcGroup => String::from(&detail.src_ip),
+            Dimension::DstHost | Dimension::DstSubnet | Dimension::DstGroup => String::from(&detail.dst_ip),
+            Dimension::SrcPort => String::from(&detail.src_port),
+            Dimension::DstPort => String::from(&detail.dst_port),
+            Dimension::Protocol => format_list(&detail.protocols),
+            Dimension::Interface => format_list(&detail.interface),
+            Dimension::Vlan => format_list(&detail.vlan)
+        }
+    }
+}
+
+/// Defines which fields of a TrafficDetail identify a record of the report
+pub struct Aggregation {
+    dims: Vec<Dimension>,
+    groups: Vec<(String, IpNetwork)>
+}
+
+impl Default for Aggregation {
+    fn default() -> Self {
+        Aggregation::new(vec![Dimension::SrcHost, Dimension::DstHost, Dimension::SrcPort, Dimension::DstPort])
+    }
+}
+
+impl Aggregation {
+    pub fn new(dims: Vec<Dimension>) -> Self {
+        Self {
+            dims,
+            groups: vec![]
+        }
+    }
+
+    /// Parses a comma separated list of dimensions, e.g. "src_ip,dst_port"
+    pub fn parse(spec: &str) -> Option<Aggregation> {
+        let mut dims = vec![];
+
+        for name in spec.split(',') {
+            let dim = Dimension::from_name(name)?;
+            if !dims.contains(&dim) {
+                dims.push(dim);
+            }
+        }
+
+        if dims.is_empty() { return None; }
+        Some(Aggregation::new(dims))
+    }
+
+    /// Parses a comma separated list of groups, each one defined as name=CIDR,
+    /// e.g. "lan=192.168.1.0/24,dmz=10.0.0.0/8"
+    pub fn set_groups(&mut self, spec: &str) -> bool {
+        let mut groups = vec![];
+
+        for group in spec.split(',') {
+            let (name, cidr) = match group.split_once('=') {
+                Some(g) => g,
+                None => { return false; }
+            };
+
+            match cidr.trim().parse::<IpNetwork>() {
+                Ok(network) if !name.trim().is_empty() => groups.push((String::from(name.trim()), network)),
+                _ => { return false; }
+            }
+        }
+
+        self.groups = groups;
+        true
+    }
+
+    pub fn dimensions(&self) -> &Vec<Dimension> {
+        &self.dims
+    }
+
+    pub fn has(&self, dim: Dimension) -> bool {
+        self.dims.contains(&dim)
+    }
+
+    /// Rewrites the fields of the given detail according to the chosen dimensions.
+    /// Addresses are replaced by their subnet or group, while fields that are not
+    /// part of the aggregation key are cleared.
+    pub fn apply(&self, detail: &mut TrafficDetail) {
+        let src = self.address(&detail.src_ip, Dimension::SrcHost, Dimension::SrcSubnet, Dimension::SrcGroup);
+        let dst = self.address(&detail.dst_ip, Dimension::DstHost, Dimension::DstSubnet, Dimension::DstGroup);
+        detail.src_ip = src;
+        detail.dst_ip = dst;
+
+        if !self.has(Dimension::SrcPort) { detail.src_port.clear(); }
+        if !self.has(Dimension::DstPort) { detail.dst_port.clear(); }
+    }
+
+    pub fn key(&self, detail: &TrafficDetail) -> String {
+        let values: Vec<String> = self.dims.iter().map(|d| d.value(detail)).collect();
+        values.join(":")
+    }
+
+    pub fn description(&self) -> String {
+        let names: Vec<&str> = self.dims.iter().map(|d| d.name()).collect();
+        let mut res = names.join(", ");
+
+        if !self.groups.is_empty() {
+            let groups: Vec<String> = self.groups.iter().map(|g| format!("{}={}", g.0, g.1)).collect();
+            res.push_str(&format!(" (groups: {})", groups.join(", ")));
+        }
+
+        res
+    }
+
+    fn address(&self, ip: &str, host: Dimension, subnet: Dimension, group: Dimension) -> String {
+        let addr = match ip.parse::<IpAddr>() {
+            Ok(a) => a,
+            // Already aggregated (e.g. read from a report)
+            Err(_) if self.has(host) || self.has(subnet) || self.has(group) => { return String::from(ip); },
+            Err(_) => { return String::new(); }
+        };
+
+        if self.has(host) {
+            String::from(ip)
+        } else if self.has(subnet) {
+            let prefix = if addr.is_ipv4() { V4_SUBNET_PREFIX } else { V6_SUBNET_PREFIX };
+            match IpNetwork::new(addr, prefix) {
+                Ok(network) => format!("{}/{}", network.network(), prefix),
+                Err(_) => String::from(ip)
+            }
+        } else if self.has(group) {
+            match self.groups.iter().find(|g| g.1.contains(addr)) {
+                Some(g) => String::from(&g.0),
+                None => String::from(NO_GROUP)
+            }
+        } else {
+            String::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::TrafficReport;
+    use std::collections::BTreeSet;
+
+    fn detail(src_ip: &str, dst_ip: &str, src_port: &str, protocol: &str) -> TrafficDetail {
+        let mut detail = TrafficDetail::new();
+        detail.src_ip = String::from(src_ip);
+        detail.dst_ip = String::from(dst_ip);
+        detail.src_port = String::from(src_port);
+        detail.dst_port = String::from("443");
+        detail.protocols = BTreeSet::from([String::from(protocol)]);
+        detail.payload_bytes = 100;
+        detail
+    }
+
+    #[test]
+    fn dimensions_are_parsed_once_each() {
+        let aggregation = Aggregation::parse("src_ip, dst_port,src_ip").unwrap();
+        assert_eq!(aggregation.dimensions(), &vec![Dimension::SrcHost, Dimension::DstPort]);
+        assert_eq!(aggregation.description(), "src_ip, dst_port");
+
+        assert!(Aggregation::parse("src_ip,host").is_none());
+        assert!(Aggregation::parse("").is_none());
+    }
+
+    #[test]
+    fn fields_out_of_the_key_are_cleared() {
+        let aggregation = Aggregation::parse("src_ip,protocol").unwrap();
+        let mut d = detail("10.0.0.1", "10.0.0.2", "50000", "TCP");
+        aggregation.apply(&mut d);
+
+        assert_eq!((d.src_ip.as_str(), d.dst_ip.as_str(), d.src_port.as_str(), d.dst_port.as_str()),
+                ("10.0.0.1", "", "", ""));
+        assert_eq!(aggregation.key(&d), "10.0.0.1:TCP");
+    }
+
+    #[test]
+    fn addresses_are_replaced_by_their_subnet() {
+        let aggregation = Aggregation::parse("src_subnet,dst_subnet").unwrap();
+        let mut d = detail("192.168.1.27", "2001:db8:1:2:3:4:5:6", "50000", "TCP");
+        aggregation.apply(&mut d);
+
+        assert_eq!(d.src_ip, "192.168.1.0/24");
+        assert_eq!(d.dst_ip, "2001:db8:1:2::/64");
+
+        // Subnets read back from a report are kept as they are
+        aggregation.apply(&mut d);
+        assert_eq!(d.src_ip, "192.168.1.0/24");
+    }
+
+    #[test]
+    fn addresses_are_replaced_by_their_group() {
+        let mut aggregation = Aggregation::parse("src_group,dst_ip").unwrap();
+        assert!(aggregation.set_groups("lan=192.168.1.0/24, dmz=10.0.0.0/8,all=0.0.0.0/0"));
+        assert_eq!(aggregation.description(), "src_group, dst_ip (groups: lan=192.168.1.0/24, dmz=10.0.0.0/8, \
+                all=0.0.0.0/0)");
+
+        // The first group containing the address wins
+        let mut d = detail("10.1.2.3", "8.8.8.8", "50000", "UDP");
+        aggregation.apply(&mut d);
+        assert_eq!((d.src_ip.as_str(), d.dst_ip.as_str()), ("dmz", "8.8.8.8"));
+
+        let mut d = detail("fe80::1", "8.8.8.8", "50000", "UDP");
+        aggregation.apply(&mut d);
+        assert_eq!(d.src_ip, NO_GROUP);
+
+        assert!(!aggregation.set_groups("lan"));
+        assert!(!aggregation.set_groups("lan=192.168.1.0/33"));
+        assert!(!aggregation.set_groups("=192.168.1.0/24"));
+    }
+
+    #[test]
+    fn records_sharing_a_key_are_summed_up() {
+        let mut report = TrafficReport::new();
+        report.set_aggregation(Aggregation::parse("src_subnet,protocol").unwrap());
+        report.new_detail(detail("10.0.0.1", "10.0.1.1", "1000", "TCP"));
+        report.new_detail(detail("10.0.0.2", "10.0.1.2", "2000", "TCP"));
+        report.new_detail(detail("10.0.0.3", "10.0.1.3", "3000", "UDP"));
+        report.new_detail(detail("10.0.9.1", "10.0.1.1", "1000", "TCP"));
+
+        let mut records: Vec<(String, String, usize, usize)> = report.snapshot().records.iter()
+                .map(|r| (String::from(&r.src_ip), format_list(&r.protocols), r.npackets, r.bytes))
+                .collect();
+        records.sort();
+        assert_eq!(records, vec![
+            (String::from("10.0.0.0/24"), String::from("TCP"), 2, 200),
+            (String::from("10.0.0.0/24"), String::from("UDP"), 1, 100),
+            (String::from("10.0.9.0/24"), String::from("TCP"), 1, 100)
+        ]);
+    }
+}
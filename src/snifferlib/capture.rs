@@ -1,10 +1,21 @@
+use super::pcapng::LINKTYPE_ETHERNET;
 use pcap::{Capture, Device, Active, Packet, Error};
 
 pub struct CaptureWrapper {
     device: String,
     filter: Option<String>,
     acapture: Option<Capture<Active>>,
-    running: bool
+    running: bool,
+    // statistics of the previous captures, as they restart from zero when resumed
+    stats: CaptureStats
+}
+
+/// Packets received and dropped since the capture was first started
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CaptureStats {
+    pub received: u64,
+    pub dropped: u64,
+    pub if_dropped: u64
 }
 
 impl CaptureWrapper {
@@ -13,7 +24,8 @@ impl CaptureWrapper {
             device: Self::sanitize_device(dev),
             filter: filter,
             acapture: None,
-            running: false
+            running: false,
+            stats: CaptureStats::default()
         }
     }
     
@@ -53,6 +65,7 @@ impl CaptureWrapper {
             return;
         }
 
+        self.stats = self.stats();
         self.running = false;
         match &self.acapture {
             Some(cap) => {
@@ -71,6 +84,31 @@ impl CaptureWrapper {
         }
     }
 
+    /// Returns the statistics of the capture, cumulated over pauses
+    pub fn stats(&mut self) -> CaptureStats {
+        let mut stats = self.stats;
+
+        if let Some(Ok(s)) = self.acapture.as_mut().map(|c| c.stats()) {
+            stats.received += s.received as u64;
+            stats.dropped += s.dropped as u64;
+            stats.if_dropped += s.if_dropped as u64;
+        }
+
+        stats
+    }
+
+    /// Returns the link-layer header type of the capture
+    pub fn datalink(&self) -> u16 {
+        match &self.acapture {
+            Some(cap) => cap.get_datalink().0 as u16,
+            None => LINKTYPE_ETHERNET
+        }
+    }
+
+    pub fn device(&self) -> String {
+        String::from(&self.device)
+    }
+
     pub fn active(&self) -> bool {
         self.running
     }
@@ -0,0 +1,229 @@
+use super::field::Field;
+use super::report::{TrafficDetail, format_list};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use ipnetwork::IpNetwork;
+use std::{cmp::Ordering, net::IpAddr};
+
+// operators, longest first so that ">=" is not mistaken for ">"
+const OPERATORS: [(&str, Operator); 6] = [(">=", Operator::Ge), ("<=", Operator::Le), ("!=", Operator::Ne),
+                                          ("=", Operator::Eq), (">", Operator::Gt), ("<", Operator::Lt)];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le
+}
+
+impl Operator {
+    fn holds(&self, ord: Ordering) -> bool {
+        match self {
+            Operator::Eq => ord == Ordering::Equal,
+            Operator::Ne => ord != Ordering::Equal,
+            Operator::Gt => ord == Ordering::Greater,
+            Operator::Ge => ord != Ordering::Less,
+            Operator::Lt => ord == Ordering::Less,
+            Operator::Le => ord != Ordering::Greater
+        }
+    }
+}
+
+/// A condition on a field of a record, e.g. "dst_port=22" or "last_ts>=2022-11-07"
+struct Condition {
+    field: Field,
+    operator: Operator,
+    value: String
+}
+
+impl Condition {
+    fn parse(spec: &str) -> Option<Condition> {
+        let (pos, symbol, operator) = OPERATORS.iter()
+                .filter_map(|(s, o)| spec.find(s).map(|p| (p, *s, *o)))
+                .min_by_key(|c| c.0)?;
+
+        let field = Field::from_name(&spec[..pos])?;
+        let value = String::from(spec[pos + symbol.len()..].trim());
+        if value.is_empty() { return None; }
+
+        // Timestamps are checked once for all
+        if matches!(field, Field::FirstTs | Field::LastTs) && parse_time(&value).is_none() {
+            return None;
+        }
+
+        Some(Condition { field, operator, value })
+    }
+
+    fn matches(&self, detail: &TrafficDetail) -> bool {
+        match self.field {
+            Field::SrcIp => self.matches_ip(&detail.src_ip),
+            Field::DstIp => self.matches_ip(&detail.dst_ip),
+            Field::FirstTs => self.matches_time(detail.first_ts),
+            Field::LastTs => self.matches_time(detail.last_ts),
+            Field::Protocols => {
+                let listed = detail.protocols.iter().any(|p| p.eq_ignore_ascii_case(&self.value));
+                match self.operator {
+                    Operator::Eq => listed,
+                    Operator::Ne => !listed,
+                    _ => self.operator.holds(format_list(&detail.protocols).as_str().cmp(self.value.as_str()))
+                }
+            },
+            field => {
+                let value = field.raw(detail, 0.0);
+                match (value.parse::<f64>(), self.value.parse::<f64>()) {
+                    (Ok(a), Ok(b)) => self.operator.holds(a.total_cmp(&b)),
+                    _ => self.operator.holds(value.as_str().cmp(self.value.as_str()))
+                }
+            }
+        }
+    }
+
+    // Addresses can be compared with a network given in CIDR notation, e.g. "src_ip=10.0.0.0/8"
+    fn matches_ip(&self, ip: &str) -> bool {
+        if let (Ok(network), Ok(addr)) = (self.value.parse::<IpNetwork>(), ip.parse::<IpAddr>()) {
+            match self.operator {
+                Operator::Eq => { return network.contains(addr); },
+                Operator::Ne => { return !network.contains(addr); },
+                _ => {}
+            }
+        }
+
+        match (ip.parse::<IpAddr>(), self.value.parse::<IpAddr>()) {
+            (Ok(a), Ok(b)) => self.operator.holds(a.cmp(&b)),
+            _ => self.operator.holds(ip.cmp(self.value.as_str()))
+        }
+    }
+
+    fn matches_time(&self, ts: i64) -> bool {
+        match parse_time(&self.value) {
+            Some(t) => self.operator.holds(ts.cmp(&t)),
+            None => false
+        }
+    }
+}
+
+/// Conditions a record must meet to be selected, all of them
+#[derive(Default)]
+pub struct Conditions {
+    conditions: Vec<Condition>
+}
+
+impl Conditions {
+    /// Parses a comma separated list of conditions, each one given as field, operator
+    /// (=, !=, >, >=, < or <=) and value, e.g. "dst_port=22,last_ts>=2022-11-07"
+    pub fn parse(spec: &str) -> Option<Conditions> {
+        let conditions: Option<Vec<Condition>> = spec.split(',').map(|c| Condition::parse(c.trim())).collect();
+        conditions.map(|c| Conditions { conditions: c })
+    }
+
+    pub fn matches(&self, detail: &TrafficDetail) -> bool {
+        self.conditions.iter().all(|c| c.matches(detail))
+    }
+}
+
+/// Parses a local time given as seconds since epoch, RFC 3339, "%Y-%m-%d %H:%M:%S" or "%Y-%m-%d"
+pub fn parse_time(value: &str) -> Option<i64> {
+    let value = value.trim();
+
+    if let Ok(ts) = value.parse::<i64>() {
+        return Some(ts);
+    }
+
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Some(dt.timestamp());
+    }
+
+    let naive = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").ok()
+            .or_else(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok().and_then(|d| d.and_hms_opt(0, 0, 0)))?;
+    Local.from_local_datetime(&naive).earliest().map(|dt| dt.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    fn detail() -> TrafficDetail {
+        let mut detail = TrafficDetail::new();
+        detail.src_ip = String::from("10.1.2.3");
+        detail.dst_ip = String::from("10.0.0.2");
+        detail.src_port = String::from("52583");
+        detail.dst_port = String::from("22");
+        detail.protocols = BTreeSet::from([String::from("TCP"), String::from("UDP")]);
+        detail.bytes = 1500;
+        detail.npackets = 3;
+        detail.first_ts = 1_667_811_600;
+        detail.last_ts = 1_667_811_660;
+        detail
+    }
+
+    fn matches(spec: &str) -> bool {
+        Conditions::parse(spec).unwrap().matches(&detail())
+    }
+
+    #[test]
+    fn invalid_conditions_are_rejected() {
+        for spec in ["dst_port", "port=22", "dst_port=", "last_ts>=yesterday", "dst_port=22,", "=22"] {
+            assert!(Conditions::parse(spec).is_none(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn longest_operator_is_used() {
+        assert!(matches("dst_port>=22"));
+        assert!(matches("dst_port<=22"));
+        assert!(!matches("dst_port>22"));
+        assert!(!matches("dst_port!=22"));
+        assert!(matches(" dst_port = 22 "));
+    }
+
+    #[test]
+    fn numbers_are_compared_as_such() {
+        assert!(matches("bytes>999"));
+        assert!(matches("packets<10"));
+        assert!(matches("src_port>6000"));
+    }
+
+    #[test]
+    fn addresses_are_compared_with_networks() {
+        assert!(matches("src_ip=10.0.0.0/8"));
+        assert!(!matches("src_ip=10.0.0.0/16"));
+        assert!(matches("dst_ip!=192.168.0.0/16"));
+        assert!(matches("dst_ip=10.0.0.2"));
+
+        // Addresses are ordered numerically, not as text
+        assert!(matches("dst_ip>9.255.255.255"));
+    }
+
+    #[test]
+    fn protocols_are_matched_within_the_list() {
+        assert!(matches("protocols=tcp"));
+        assert!(matches("protocols=UDP"));
+        assert!(!matches("protocols=ICMP"));
+        assert!(matches("protocols!=ICMP"));
+    }
+
+    #[test]
+    fn timestamps_are_compared_as_times() {
+        assert!(matches("first_ts>=2022-11-07T09:00:00+00:00"));
+        assert!(!matches("last_ts<2022-11-07T09:01:00+00:00"));
+        assert!(matches("last_ts<=1667811660"));
+    }
+
+    #[test]
+    fn all_conditions_must_hold() {
+        assert!(matches("dst_port=22,src_ip=10.0.0.0/8,protocols=TCP"));
+        assert!(!matches("dst_port=22,protocols=ICMP"));
+    }
+
+    #[test]
+    fn times_are_parsed_in_several_formats() {
+        assert_eq!(parse_time("1667811600"), Some(1_667_811_600));
+        assert_eq!(parse_time("2022-11-07T09:00:00Z"), Some(1_667_811_600));
+        assert_eq!(parse_time("2022-11-07T10:00:00+01:00"), Some(1_667_811_600));
+        assert_eq!(parse_time("2022-11-07 09:00:00"), parse_time("2022-11-07").map(|t| t + 9 * 3600));
+        assert_eq!(parse_time("07/11/2022"), None);
+    }
+}
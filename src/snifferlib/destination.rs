@@ -0,0 +1,97 @@
+use std::{fs::{self, File, OpenOptions}, io::{self, Error, Write}, path::Path, process};
+
+/// Where a rendered report is written
+pub enum Destination {
+    /// A file, replaced atomically at each interval (or appended to, for streaming formats)
+    File(String),
+    Stdout,
+    Writer(Box<dyn Write + Send>)
+}
+
+impl Destination {
+    /// Writes the given content, replacing the previous one unless append is true
+    pub fn write(&mut self, content: &[u8], append: bool) -> Result<(), Error> {
+        match self {
+            Destination::File(path) if append => {
+                // Snapshots are appended whole, but readers tailing the file may still see
+                // part of the last one while it is being written
+                OpenOptions::new().create(true).append(true).open(path.as_str())?.write_all(content)
+            },
+            Destination::File(path) => replace(path, content),
+            Destination::Stdout => {
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(content)?;
+                stdout.flush()
+            },
+            Destination::Writer(writer) => {
+                writer.write_all(content)?;
+                writer.flush()
+            }
+        }
+    }
+}
+
+/// Replaces the content of the given file atomically: the content is written to a temporary
+/// file in the same directory, which is then renamed over the target, so that readers see
+/// either the previous content or the new one, but never a truncated file
+pub fn replace(path: &str, content: &[u8]) -> Result<(), Error> {
+    let target = Path::new(path);
+    let name = match target.file_name() {
+        Some(n) => n.to_string_lossy(),
+        None => { return Err(Error::other(format!("{} is not a file.", path))); }
+    };
+    let tmp = target.with_file_name(format!(".{}.{}.tmp", name, process::id()));
+
+    let res = File::create(&tmp).and_then(|mut file| {
+        file.write_all(content)?;
+        file.sync_all()
+    }).and_then(|_| fs::rename(&tmp, target));
+
+    if res.is_err() {
+        fs::remove_file(&tmp).ok();
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Files of the given directory, the temporary ones included
+    fn files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir).unwrap()
+                .map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn files_are_replaced_whole() {
+        let dir = std::env::temp_dir().join(format!("rsniffer-destination-{}", process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("report.txt");
+        let path = path.to_str().unwrap();
+
+        let mut destination = Destination::File(String::from(path));
+        destination.write(b"first report, longer than the next one\n", false).unwrap();
+        destination.write(b"second report\n", false).unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), "second report\n");
+        assert_eq!(files(&dir), vec!["report.txt"]);
+
+        // Streaming formats are appended to
+        destination.write(b"third report\n", true).unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), "second report\nthird report\n");
+
+        // A failed replacement leaves neither the temporary file nor a truncated target
+        let target = dir.join("directory");
+        fs::create_dir(&target).unwrap();
+        fs::write(target.join("keep"), b"").unwrap();
+        assert!(replace(target.to_str().unwrap(), b"report\n").is_err());
+        assert_eq!(files(&dir), vec!["directory", "report.txt"]);
+
+        assert!(replace("/", b"report\n").is_err());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
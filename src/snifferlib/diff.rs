@@ -0,0 +1,561 @@
+use super::{set_layout, set_oui, parse_format, SnifferError};
+use super::condition::Conditions;
+use super::field::parse_fields;
+use super::input::load_into;
+use super::output::{self, Column, Row, Snapshot};
+use super::report::{TrafficDetail, TrafficReport, ByteCount, format_bytes, format_list};
+use super::sorting::Sorting;
+use std::{cmp::Ordering, collections::HashMap, io::Write};
+
+// columns describing the changes, listed before the additional ones
+const DIFF_FIELDS: [DiffField; 3] = [DiffField::Change, DiffField::BytesDelta, DiffField::PacketsDelta];
+
+/// How a record changed from a report to another one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+    /// The record is only found in the new report
+    New,
+    /// The record is only found in the old report
+    Removed,
+    /// The record is found in both reports, with different counters
+    Changed
+}
+
+impl Change {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Change::New => "new",
+            Change::Removed => "removed",
+            Change::Changed => "changed"
+        }
+    }
+}
+
+/// A field describing how a record changed, only found in comparisons
+/// (e.g. to sort the result or as a column of it)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiffField {
+    Change,
+    BytesDelta,
+    PacketsDelta
+}
+
+impl DiffField {
+    pub fn from_name(name: &str) -> Option<DiffField> {
+        match name.trim() {
+            "change" => Some(DiffField::Change),
+            "bytes_delta" => Some(DiffField::BytesDelta),
+            "packets_delta" => Some(DiffField::PacketsDelta),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DiffField::Change => "change",
+            DiffField::BytesDelta => "bytes_delta",
+            DiffField::PacketsDelta => "packets_delta"
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            DiffField::Change => "CHANGE",
+            DiffField::BytesDelta => "BYTES DELTA",
+            DiffField::PacketsDelta => "PACKETS DELTA"
+        }
+    }
+
+    /// Tells whether the raw value of this field is a number
+    pub fn numeric(&self) -> bool {
+        *self != DiffField::Change
+    }
+
+    /// Returns the printable value of this field
+    pub fn value(&self, row: &DiffRow) -> String {
+        match self {
+            DiffField::Change => row.change.map(|c| String::from(c.name())).unwrap_or_default(),
+            DiffField::BytesDelta => format_delta(row.bytes_delta),
+            DiffField::PacketsDelta => format!("{:+}", row.packets_delta)
+        }
+    }
+
+    /// Returns the value of this field as written in machine readable reports
+    pub fn raw(&self, row: &DiffRow) -> String {
+        match self {
+            DiffField::Change => self.value(row),
+            DiffField::BytesDelta => row.bytes_delta.to_string(),
+            DiffField::PacketsDelta => row.packets_delta.to_string()
+        }
+    }
+
+    pub fn compare(&self, a: &DiffRow, b: &DiffRow) -> Ordering {
+        match self {
+            DiffField::Change => a.change.cmp(&b.change),
+            DiffField::BytesDelta => a.bytes_delta.cmp(&b.bytes_delta),
+            DiffField::PacketsDelta => a.packets_delta.cmp(&b.packets_delta)
+        }
+    }
+}
+
+/// A row of a comparison: a record that changed from a report to another one, or a summary of such records
+#[derive(Debug, Clone)]
+pub struct DiffRow {
+    /// Record of the new report (or of the old one, if removed)
+    pub detail: TrafficDetail,
+    /// How the record changed, None for the rows summing up several records
+    pub change: Option<Change>,
+    pub bytes_delta: i64,
+    pub packets_delta: i64
+}
+
+impl DiffRow {
+    /// Sums up the given rows: counters are the ones of the new report (the records removed
+    /// only count in the deltas), so that the deltas are the new counters minus the old ones
+    fn summary<'a>(rows: impl Iterator<Item = &'a DiffRow> + Clone) -> DiffRow {
+        DiffRow {
+            detail: TrafficReport::summary(rows.clone().filter(|r| r.change != Some(Change::Removed)).map(|r| &r.detail)),
+            change: None,
+            bytes_delta: rows.clone().map(|r| r.bytes_delta).sum(),
+            packets_delta: rows.map(|r| r.packets_delta).sum()
+        }
+    }
+}
+
+impl Row for DiffRow {
+    fn detail(&self) -> &TrafficDetail {
+        &self.detail
+    }
+
+    fn value(&self, column: &Column, elapsed: f64) -> String {
+        match column {
+            Column::Diff(field) => field.value(self),
+            _ => column.value(&self.detail, elapsed)
+        }
+    }
+
+    fn raw(&self, column: &Column, elapsed: f64) -> String {
+        match column {
+            Column::Diff(field) => field.raw(self),
+            _ => column.raw(&self.detail, elapsed)
+        }
+    }
+}
+
+// Sorting criteria of a comparison, each key being either a change or a field of the records
+enum DiffKey {
+    Change(DiffField, bool),
+    Field(Sorting)
+}
+
+impl DiffKey {
+    fn parse(spec: &str) -> Option<DiffKey> {
+        let (name, direction) = spec.split_once(':').unwrap_or((spec, "asc"));
+        match DiffField::from_name(name) {
+            Some(field) => match direction.trim() {
+                "asc" => Some(DiffKey::Change(field, false)),
+                "desc" => Some(DiffKey::Change(field, true)),
+                _ => None
+            },
+            None => Sorting::parse(spec).map(DiffKey::Field)
+        }
+    }
+
+    fn compare(&self, a: &DiffRow, b: &DiffRow) -> Ordering {
+        match self {
+            DiffKey::Change(field, descending) => {
+                let ord = field.compare(a, b);
+                if *descending { ord.reverse() } else { ord }
+            },
+            DiffKey::Field(sorting) => sorting.compare(&a.detail, &b.detail)
+        }
+    }
+
+    fn description(&self) -> String {
+        match self {
+            DiffKey::Change(field, descending) => format!("{}:{}", field.name(), if *descending { "desc" } else { "asc" }),
+            DiffKey::Field(sorting) => sorting.description()
+        }
+    }
+}
+
+/// Compares two sets of records, matching them by all the fields that may identify a record
+/// (addresses, ports, protocols, interfaces, VLANs and MAC addresses), and returns the records
+/// that are new, removed or whose bytes or packets changed. Each of them holds the kind of change
+/// and the byte and packet deltas, along with the record of the new report (or of the old one, if
+/// removed). Records found unchanged are left out, while records of the same set sharing all these
+/// fields are summed up.
+///
+/// #Example
+/// ```
+/// use snifferlib::{diff, Change, TrafficDetail};
+///
+/// let mut before = TrafficDetail::new();
+/// before.dst_ip = String::from("10.0.0.1");
+/// before.bytes = 100;
+///
+/// let mut after = before.clone();
+/// after.bytes = 250;
+///
+/// let changes = diff(&[before], &[after]);
+/// assert_eq!(changes[0].change, Some(Change::Changed));
+/// assert_eq!(changes[0].bytes_delta, 150);
+/// ```
+pub fn diff(old: &[TrafficDetail], new: &[TrafficDetail]) -> Vec<DiffRow> {
+    diff_by(old, new, record_key)
+}
+
+/// Compares two sets of records as [`diff`] does, matching them by the key returned by the given
+/// function (e.g. the destination address only)
+///
+/// #Example
+/// ```
+/// use snifferlib::{diff_by, Change, TrafficDetail};
+///
+/// let mut before = TrafficDetail::new();
+/// before.dst_ip = String::from("10.0.0.1");
+/// before.src_port = String::from("50000");
+/// before.bytes = 100;
+///
+/// let mut after = before.clone();
+/// after.src_port = String::from("50001");
+///
+/// let changes = diff_by(&[before], &[after], |d| d.dst_ip.clone());
+/// assert!(changes.is_empty());
+/// ```
+pub fn diff_by(old: &[TrafficDetail], new: &[TrafficDetail], key: impl Fn(&TrafficDetail) -> String) -> Vec<DiffRow> {
+    let by_key = |records: &[TrafficDetail]| -> HashMap<String, TrafficDetail> {
+        let mut by_key: HashMap<String, TrafficDetail> = HashMap::new();
+        for detail in records {
+            by_key.entry(key(detail))
+                  .and_modify(|d| d.merge(detail))
+                  .or_insert_with(|| detail.clone());
+        }
+        by_key
+    };
+
+    compare(by_key(old), by_key(new)).into_values().collect()
+}
+
+// Identifies a record by all the fields that may be part of its key
+fn record_key(d: &TrafficDetail) -> String {
+    [d.src_ip.clone(), d.dst_ip.clone(), d.src_port.clone(), d.dst_port.clone(), format_list(&d.protocols),
+     format_list(&d.interface), format_list(&d.vlan), format_list(&d.src_mac), format_list(&d.dst_mac)].join("|")
+}
+
+// Compares two sets of records identified by the given keys
+fn compare(mut old: HashMap<String, TrafficDetail>, new: HashMap<String, TrafficDetail>) -> HashMap<String, DiffRow> {
+    let mut changes = HashMap::new();
+
+    for (key, detail) in new {
+        let (change, bytes, packets) = match old.remove(&key) {
+            None => (Change::New, 0, 0),
+            Some(o) if o.bytes != detail.bytes || o.npackets != detail.npackets => (Change::Changed, o.bytes, o.npackets),
+            Some(_) => { continue; }
+        };
+
+        let bytes_delta = detail.bytes as i64 - bytes as i64;
+        let packets_delta = detail.npackets as i64 - packets as i64;
+        changes.insert(key, DiffRow { detail, change: Some(change), bytes_delta, packets_delta });
+    }
+
+    for (key, detail) in old {
+        let bytes_delta = -(detail.bytes as i64);
+        let packets_delta = -(detail.npackets as i64);
+        changes.insert(key, DiffRow { detail, change: Some(Change::Removed), bytes_delta, packets_delta });
+    }
+
+    changes
+}
+
+// Formats a signed number of bytes, e.g. "+12 KB" or "-300 B"
+fn format_delta(delta: i64) -> String {
+    let sign = if delta < 0 { '-' } else { '+' };
+    format!("{}{}", sign, format_bytes(delta.unsigned_abs() as usize).trim_start())
+}
+
+/// Builder used to compare two reports (in text, Markdown, CSV, JSON, NDJSON or SQLite format) or captures (in
+/// pcap or pcapng format), listing the records that appeared, disappeared or changed volume from the
+/// old one to the new one, with their byte and packet deltas, in any of the output formats.
+/// Both sides are aggregated in the same way before being compared.
+///
+/// The OTHERS and TOTAL rows sum up the counters of the new records and of the changed ones (as found
+/// in the new report), while their deltas are the new counters minus the old ones, removed records included.
+///
+/// #Example
+/// ```no_run
+/// use snifferlib::Diff;
+/// use std::io;
+///
+/// // Destinations whose traffic changed after a deployment, biggest increase first
+/// let res = Diff::new(String::from("before.pcap"), String::from("after.pcap"))
+///                 .aggregate(Some(String::from("dst_ip,dst_port")))
+///                 .sort(Some(String::from("bytes_delta:desc")))
+///                 .run(&mut io::stdout());
+/// assert!(res.is_ok());
+/// ```
+pub struct Diff {
+    old: String,
+    new: String,
+    sorting: Option<String>,
+    aggregation: Option<String>,
+    groups: Option<String>,
+    limit: Option<usize>,
+    columns: Option<String>,
+    oui: Option<String>,
+    bytes: Option<String>,
+    format: Option<String>
+}
+
+impl Diff {
+    /// Returns a comparison between the traffic stored in the given files
+    pub fn new(old: String, new: String) -> Diff {
+        Diff {
+            old,
+            new,
+            sorting: None,
+            aggregation: None,
+            groups: None,
+            limit: None,
+            columns: None,
+            oui: None,
+            bytes: None,
+            format: None
+        }
+    }
+
+    /// Sets the sorting criteria, and returns the comparison by value (see [`super::SnifferBuilder::sort`]).
+    /// Besides the usual fields, records can be sorted by change, bytes_delta and packets_delta.
+    pub fn sort(mut self, sorting: Option<String>) -> Diff {
+        self.sorting = sorting;
+        self
+    }
+
+    /// Sets the aggregation criteria, and returns the comparison by value (see [`super::SnifferBuilder::aggregate`])
+    pub fn aggregate(mut self, aggregation: Option<String>) -> Diff {
+        self.aggregation = aggregation;
+        self
+    }
+
+    /// Sets the groups used by the src_group and dst_group aggregation criteria, and returns the comparison
+    /// by value (see [`super::SnifferBuilder::groups`])
+    pub fn groups(mut self, groups: Option<String>) -> Diff {
+        self.groups = groups;
+        self
+    }
+
+    /// Sets the maximum number of records written, and returns the comparison by value
+    pub fn limit(mut self, limit: Option<usize>) -> Diff {
+        self.limit = limit;
+        self
+    }
+
+    /// Sets the additional columns, and returns the comparison by value (see [`super::SnifferBuilder::columns`])
+    pub fn columns(mut self, columns: Option<String>) -> Diff {
+        self.columns = columns;
+        self
+    }
+
+    /// Sets the OUI database used to resolve vendors, and returns the comparison by value
+    /// (see [`super::SnifferBuilder::oui`])
+    pub fn oui(mut self, path: Option<String>) -> Diff {
+        self.oui = path;
+        self
+    }
+
+    /// Sets the length used to count the bytes of the packets of captures, and returns the comparison
+    /// by value (see [`super::SnifferBuilder::bytes`]). Records read from reports keep their counters.
+    pub fn bytes(mut self, bytes: Option<String>) -> Diff {
+        self.bytes = bytes;
+        self
+    }
+
+    /// Sets the output format, and returns the comparison by value (see [`super::SnifferBuilder::format`])
+    pub fn format(mut self, format: Option<String>) -> Diff {
+        self.format = format;
+        self
+    }
+
+    /// Runs the comparison and writes its result to the given destination
+    pub fn run(self, out: &mut dyn Write) -> Result<(), SnifferError> {
+        let format = parse_format(&self.format)?;
+        let sorting = match &self.sorting {
+            Some(spec) => match spec.split(',').map(|k| DiffKey::parse(k.trim())).collect::<Option<Vec<DiffKey>>>() {
+                Some(keys) => keys,
+                None => { return Err(SnifferError::new(String::from("Invalid sorting criteria."))); }
+            },
+            None => vec![]
+        };
+
+        let old = self.load(&self.old)?.take_records();
+        let mut report = self.load(&self.new)?;
+
+        // Only the records are compared, the traffic not carrying IP is left out
+        let mut rows: Vec<(String, DiffRow)> = compare(old, report.take_records()).into_iter().collect();
+        rows.sort_by(|a, b| sorting.iter()
+                .map(|k| k.compare(&a.1, &b.1))
+                .find(|o| *o != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.0.cmp(&b.0)));
+        report.clear_layer2();
+        report.set_source(Some(format!("{} (old), {} (new)", self.old, self.new)));
+
+        // The columns describing the changes come right after the counters
+        let base = report.snapshot();
+        let extra = self.columns.as_deref().and_then(parse_fields).map(|c| c.len()).unwrap_or(0);
+        let mut columns = base.columns;
+        columns.splice(columns.len() - extra..columns.len() - extra, DIFF_FIELDS.iter().map(|f| Column::Diff(*f)));
+
+        let mut metadata = base.metadata;
+        if let Some(entry) = metadata.iter_mut().find(|e| e.name == "sorting") {
+            if !sorting.is_empty() {
+                entry.value = sorting.iter().map(|k| k.description()).collect::<Vec<String>>().join(", ");
+            }
+        }
+
+        // Rows beyond the limit are summed up in a single one
+        let limit = self.limit.unwrap_or(rows.len());
+        let others = if rows.len() > limit {
+            Some((rows.len() - limit, DiffRow::summary(rows.iter().skip(limit).map(|r| &r.1))))
+        } else {
+            None
+        };
+
+        let snapshot = Snapshot {
+            metadata,
+            timestamp: base.timestamp,
+            columns,
+            nkeys: base.nkeys,
+            delta: false,
+            records: rows.iter().take(limit).map(|r| r.1.clone()).collect(),
+            active: vec![],
+            others,
+            total: (rows.len(), DiffRow::summary(rows.iter().map(|r| &r.1))),
+            layer2: base.layer2,
+            elapsed: base.elapsed
+        };
+
+        match output::write(&snapshot, format, true, out) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(SnifferError::new(format!("Couldn't write the result of the comparison. {}", e)))
+        }
+    }
+
+    // Loads the traffic stored in the given file into a report shaped as requested
+    fn load(&self, path: &str) -> Result<TrafficReport, SnifferError> {
+        let mut report = TrafficReport::new();
+        // Sorting and limit apply to the changes, once both sides are compared
+        set_layout(&mut report, &None, &self.aggregation, &self.groups, None, &self.columns)?;
+        set_oui(&mut report, &self.oui, &self.columns)?;
+
+        if let Some(bytes) = &self.bytes {
+            match ByteCount::from_name(bytes) {
+                Some(count) => report.set_byte_count(count),
+                None => { return Err(SnifferError::new(String::from("Invalid bytes length."))); }
+            }
+        }
+
+        match load_into(path, &mut report, &Conditions::default()) {
+            Ok(_) => Ok(report),
+            Err(e) => Err(SnifferError::new(format!("Couldn't read records from {}. {}", path, e)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::OutputFormat;
+    use std::collections::BTreeSet;
+
+    fn detail(interface: &str, vlan: &str, bytes: usize) -> TrafficDetail {
+        let mut detail = TrafficDetail::new();
+        detail.src_ip = String::from("10.0.0.1");
+        detail.dst_ip = String::from("10.0.0.2");
+        detail.protocols = BTreeSet::from([String::from("TCP")]);
+        detail.interface = BTreeSet::from([String::from(interface)]);
+        detail.vlan = BTreeSet::from([String::from(vlan)]);
+        detail.bytes = bytes;
+        detail.npackets = 1;
+        detail
+    }
+
+    fn changes(mut rows: Vec<DiffRow>) -> Vec<(String, String, Option<Change>, i64)> {
+        rows.sort_by_key(|r| (format_list(&r.detail.interface), format_list(&r.detail.vlan)));
+        rows.iter().map(|r| (format_list(&r.detail.interface), format_list(&r.detail.vlan), r.change, r.bytes_delta)).collect()
+    }
+
+    #[test]
+    fn records_differing_by_interface_or_vlan_are_compared_apart() {
+        let old = [detail("eth0", "100", 100), detail("eth1", "100", 100), detail("eth0", "200", 100)];
+        let new = [detail("eth0", "100", 100), detail("eth1", "100", 300), detail("eth1", "200", 50)];
+
+        assert_eq!(changes(diff(&old, &new)), vec![
+            (String::from("eth0"), String::from("200"), Some(Change::Removed), -100),
+            (String::from("eth1"), String::from("100"), Some(Change::Changed), 200),
+            (String::from("eth1"), String::from("200"), Some(Change::New), 50)
+        ]);
+    }
+
+    #[test]
+    fn records_sharing_a_key_are_summed_up() {
+        let old = [detail("eth0", "100", 100)];
+        let new = [detail("eth0", "100", 100), detail("eth1", "200", 300)];
+
+        let rows = diff_by(&old, &new, |d| d.dst_ip.clone());
+        assert_eq!(rows.len(), 1);
+        assert_eq!((rows[0].change, rows[0].bytes_delta, rows[0].packets_delta), (Some(Change::Changed), 300, 1));
+        assert_eq!(format_list(&rows[0].detail.interface), "eth0, eth1");
+    }
+    // Writes a report of the given destinations and bytes to a CSV file
+    fn write_report(name: &str, records: &[(&str, usize)]) -> String {
+        let mut report = TrafficReport::new();
+        for (dst_ip, payload_bytes) in records {
+            let mut d = detail("eth0", "100", 0);
+            d.dst_ip = String::from(*dst_ip);
+            d.payload_bytes = *payload_bytes;
+            report.new_detail(d);
+        }
+
+        let path = std::env::temp_dir().join(format!("rsniffer-diff-{}-{}.csv", name, std::process::id()));
+        let mut file = std::fs::File::create(&path).unwrap();
+        output::write(&report.snapshot(), OutputFormat::Csv, true, &mut file).unwrap();
+        String::from(path.to_str().unwrap())
+    }
+
+    #[test]
+    fn changes_are_sorted_limited_and_summed_up() {
+        let old = write_report("old", &[("10.0.0.2", 100), ("10.0.0.3", 500), ("10.0.0.4", 200)]);
+        let new = write_report("new", &[("10.0.0.2", 400), ("10.0.0.4", 200), ("10.0.0.5", 50)]);
+
+        let mut out = vec![];
+        let res = Diff::new(String::from(&old), String::from(&new))
+                .aggregate(Some(String::from("dst_ip")))
+                .sort(Some(String::from("bytes_delta:desc")))
+                .limit(Some(2))
+                .format(Some(String::from("json")))
+                .run(&mut out);
+        std::fs::remove_file(&old).ok();
+        std::fs::remove_file(&new).ok();
+        res.unwrap();
+
+        // Unchanged records are left out
+        let document: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let rows: Vec<(&str, &str, i64)> = document["records"].as_array().unwrap().iter()
+                .map(|r| (r["dst_ip"].as_str().unwrap(), r["change"].as_str().unwrap(), r["bytes_delta"].as_i64().unwrap()))
+                .collect();
+        assert_eq!(rows, vec![("10.0.0.2", "changed", 300), ("10.0.0.5", "new", 50)]);
+
+        // Removed records only count in the deltas
+        assert_eq!((document["others"]["records"].as_u64(), document["others"]["bytes_delta"].as_i64()), (Some(1), Some(-500)));
+        assert_eq!((document["total"]["records"].as_u64(), document["total"]["bytes"].as_u64(),
+                document["total"]["bytes_delta"].as_i64()), (Some(3), Some(450), Some(-150)));
+    }
+
+    #[test]
+    fn deltas_are_signed() {
+        assert_eq!(format_delta(-300), "-300 B");
+        assert_eq!(format_delta(0), "+0 B");
+        assert!(Diff::new(String::new(), String::new()).sort(Some(String::from("change:up"))).run(&mut vec![]).is_err());
+    }
+}
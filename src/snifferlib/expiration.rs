@@ -0,0 +1,220 @@
+use super::report::TrafficDetail;
+use std::collections::HashMap;
+
+/// Policy used to choose which records are evicted when the
+/// maximum number of tracked records is reached
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Eviction {
+    /// Evicts the records that have been idle for the longest time
+    Lru,
+    /// Evicts the oldest records
+    Oldest,
+    /// Evicts the records with the fewest transmitted bytes
+    Smallest
+}
+
+impl Eviction {
+    pub fn from_name(name: &str) -> Option<Eviction> {
+        match name.trim() {
+            "lru" => Some(Eviction::Lru),
+            "oldest" => Some(Eviction::Oldest),
+            "smallest" => Some(Eviction::Smallest),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Eviction::Lru => "lru",
+            Eviction::Oldest => "oldest",
+            Eviction::Smallest => "smallest"
+        }
+    }
+}
+
+/// Reason why a record has been removed from the report
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expiry {
+    Idle,
+    Active,
+    Evicted
+}
+
+impl Expiry {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Expiry::Idle => "idle",
+            Expiry::Active => "active",
+            Expiry::Evicted => "evicted"
+        }
+    }
+}
+
+/// NetFlow-style expiration criteria for the records of the report
+pub struct Expiration {
+    pub idle_timeout: Option<u64>,
+    pub active_timeout: Option<u64>,
+    pub max_records: Option<usize>,
+    pub eviction: Eviction
+}
+
+impl Default for Expiration {
+    fn default() -> Self {
+        Self {
+            idle_timeout: None,
+            active_timeout: None,
+            max_records: None,
+            eviction: Eviction::Lru
+        }
+    }
+}
+
+impl Expiration {
+    /// Tells whether the given record is expired at time now (seconds since epoch).
+    /// A record is idle when no packet has been seen for idle_timeout seconds, and it
+    /// is active-expired when it has lasted for active_timeout seconds or more.
+    pub fn expired(&self, detail: &TrafficDetail, now: i64) -> Option<Expiry> {
+        if let Some(idle) = self.idle_timeout {
+            if now - detail.last_ts >= idle as i64 {
+                return Some(Expiry::Idle);
+            }
+        }
+
+        if let Some(active) = self.active_timeout {
+            if now - detail.first_ts >= active as i64 {
+                return Some(Expiry::Active);
+            }
+        }
+
+        None
+    }
+
+    /// Tells whether a new record can be tracked without exceeding the limit
+    pub fn full(&self, nrecords: usize) -> bool {
+        match self.max_records {
+            Some(max) => nrecords >= max,
+            None => false
+        }
+    }
+
+    /// Returns the keys of the records to be evicted to make room for new ones.
+    /// A tenth of the records is evicted at once, so that the cost of choosing
+    /// them is not paid for every new record.
+    pub fn victims(&self, traffic: &HashMap<String, TrafficDetail>) -> Vec<String> {
+        let max = match self.max_records {
+            Some(m) => m,
+            None => { return vec![]; }
+        };
+
+        let mut candidates: Vec<_> = traffic.iter().collect();
+        match self.eviction {
+            Eviction::Lru => candidates.sort_by_key(|c| c.1.last_ts),
+            Eviction::Oldest => candidates.sort_by_key(|c| c.1.first_ts),
+            Eviction::Smallest => candidates.sort_by_key(|c| c.1.bytes)
+        }
+
+        let nvictims = (traffic.len() + 1).saturating_sub(max - max / 10);
+        candidates.iter().take(nvictims).map(|c| String::from(c.0)).collect()
+    }
+
+    pub fn description(&self) -> Option<String> {
+        let mut res = vec![];
+
+        if let Some(idle) = self.idle_timeout {
+            res.push(format!("idle timeout {}s", idle));
+        }
+
+        if let Some(active) = self.active_timeout {
+            res.push(format!("active timeout {}s", active));
+        }
+
+        if let Some(max) = self.max_records {
+            res.push(format!("max {} records ({} eviction)", max, self.eviction.name()));
+        }
+
+        if res.is_empty() { None } else { Some(res.join(", ")) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::TrafficReport;
+    use std::collections::BTreeSet;
+
+    fn detail(src_port: &str, first_ts: i64, last_ts: i64, payload_bytes: usize) -> TrafficDetail {
+        let mut detail = TrafficDetail::new();
+        detail.src_ip = String::from("10.0.0.1");
+        detail.dst_ip = String::from("10.0.0.2");
+        detail.src_port = String::from(src_port);
+        detail.dst_port = String::from("80");
+        detail.protocols = BTreeSet::from([String::from("TCP")]);
+        detail.bytes = payload_bytes;
+        detail.payload_bytes = payload_bytes;
+        detail.first_ts = first_ts;
+        detail.last_ts = last_ts;
+        detail
+    }
+
+    #[test]
+    fn records_expire_when_idle_or_active_for_too_long() {
+        let expiration = Expiration { idle_timeout: Some(30), active_timeout: Some(120), ..Default::default() };
+        let d = detail("1000", 1000, 1100, 100);
+
+        assert_eq!(expiration.expired(&d, 1110), None);
+        assert_eq!(expiration.expired(&d, 1120), Some(Expiry::Active));
+        assert_eq!(expiration.expired(&d, 1130), Some(Expiry::Idle));
+        assert_eq!(Expiration::default().expired(&d, 100_000), None);
+        assert_eq!(expiration.description().unwrap(), "idle timeout 30s, active timeout 120s");
+    }
+
+    #[test]
+    fn victims_are_chosen_by_the_eviction_policy() {
+        let traffic: HashMap<String, TrafficDetail> = [
+            ("a", detail("1000", 10, 50, 300)),
+            ("b", detail("2000", 20, 30, 100)),
+            ("c", detail("3000", 5, 60, 200))
+        ].into_iter().map(|(k, d)| (String::from(k), d)).collect();
+
+        for (eviction, victim) in [(Eviction::Lru, "b"), (Eviction::Oldest, "c"), (Eviction::Smallest, "b")] {
+            let expiration = Expiration { max_records: Some(3), eviction, ..Default::default() };
+            assert!(expiration.full(traffic.len()));
+            assert_eq!(expiration.victims(&traffic), vec![String::from(victim)], "{}", eviction.name());
+        }
+
+        // A tenth of the records are evicted at once
+        let traffic: HashMap<String, TrafficDetail> = (0..20)
+                .map(|i| (i.to_string(), detail(&i.to_string(), i, i, 100))).collect();
+        let expiration = Expiration { max_records: Some(20), ..Default::default() };
+        let mut victims = expiration.victims(&traffic);
+        victims.sort();
+        assert_eq!(victims, vec!["0", "1", "2"]);
+    }
+
+    #[test]
+    fn the_report_stays_within_its_bounds() {
+        let path = std::env::temp_dir().join(format!("rsniffer-expiration-{}.log", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::remove_file(path).ok();
+
+        let mut report = TrafficReport::new();
+        report.set_closed_path(Some(String::from(path)));
+        report.set_expiration(Expiration { idle_timeout: Some(60), max_records: Some(10), ..Default::default() });
+
+        for i in 0..25 {
+            report.new_detail(detail(&(1000 + i).to_string(), 1000 + i, 1000 + i, 100));
+            assert!(report.len() <= 10);
+        }
+
+        // The most recent records are kept, until they are idle too
+        report.expire(1080);
+        assert_eq!(report.len(), 4);
+        report.expire(1100);
+        assert_eq!(report.len(), 0);
+
+        let log = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).ok();
+        assert_eq!(log.lines().filter(|l| l.contains("[evicted]")).count(), 16);
+        assert_eq!(log.lines().filter(|l| l.contains("[idle]")).count(), 9);
+    }
+}
@@ -0,0 +1,317 @@
+use super::report::{TrafficDetail, format_bytes, format_iso_ts, format_list};
+use std::{cmp::Ordering, net::IpAddr};
+
+/// A field of a TrafficDetail that can be referred to by name (e.g. to sort the report or to add a column to it)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    SrcIp,
+    DstIp,
+    SrcPort,
+    DstPort,
+    Protocols,
+    Interface,
+    Vlan,
+    ExtHeaders,
+    SrcMac,
+    DstMac,
+    SrcVendor,
+    DstVendor,
+    Bytes,
+    Packets,
+    FirstTs,
+    LastTs,
+    Duration,
+    AvgBps,
+    AvgPps,
+    CurBps,
+    CurPps,
+    FrameBytes,
+    CapturedBytes,
+    IpBytes,
+    PayloadBytes
+}
+
+impl Field {
+    pub fn from_name(name: &str) -> Option<Field> {
+        match name.trim() {
+            "src_ip" => Some(Field::SrcIp),
+            "dst_ip" => Some(Field::DstIp),
+            "src_port" => Some(Field::SrcPort),
+            "dst_port" => Some(Field::DstPort),
+            "protocols" | "protocol" => Some(Field::Protocols),
+            "interface" => Some(Field::Interface),
+            "vlan" => Some(Field::Vlan),
+            "ext_headers" => Some(Field::ExtHeaders),
+            "src_mac" => Some(Field::SrcMac),
+            "dst_mac" => Some(Field::DstMac),
+            "src_vendor" => Some(Field::SrcVendor),
+            "dst_vendor" => Some(Field::DstVendor),
+            "bytes" => Some(Field::Bytes),
+            "packets" => Some(Field::Packets),
+            "first_ts" => Some(Field::FirstTs),
+            "last_ts" => Some(Field::LastTs),
+            "duration" => Some(Field::Duration),
+            "avg_bps" => Some(Field::AvgBps),
+            "avg_pps" => Some(Field::AvgPps),
+            "cur_bps" => Some(Field::CurBps),
+            "cur_pps" => Some(Field::CurPps),
+            "frame_bytes" => Some(Field::FrameBytes),
+            "captured_bytes" => Some(Field::CapturedBytes),
+            "ip_bytes" => Some(Field::IpBytes),
+            "payload_bytes" => Some(Field::PayloadBytes),
+            _ => None
+        }
+    }
+
+    /// Returns the field identified by the given index, following the order
+    /// of the columns of the default report (0 is SRC_IP, 8 is LAST TIMESTAMP)
+    pub fn from_index(index: u32) -> Option<Field> {
+        match index {
+            0 => Some(Field::SrcIp),
+            1 => Some(Field::DstIp),
+            2 => Some(Field::SrcPort),
+            3 => Some(Field::DstPort),
+            4 => Some(Field::Protocols),
+            5 => Some(Field::Bytes),
+            6 => Some(Field::Packets),
+            7 => Some(Field::FirstTs),
+            8 => Some(Field::LastTs),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Field::SrcIp => "src_ip",
+            Field::DstIp => "dst_ip",
+            Field::SrcPort => "src_port",
+            Field::DstPort => "dst_port",
+            Field::Protocols => "protocols",
+            Field::Interface => "interface",
+            Field::Vlan => "vlan",
+            Field::ExtHeaders => "ext_headers",
+            Field::SrcMac => "src_mac",
+            Field::DstMac => "dst_mac",
+            Field::SrcVendor => "src_vendor",
+            Field::DstVendor => "dst_vendor",
+            Field::Bytes => "bytes",
+            Field::Packets => "packets",
+            Field::FirstTs => "first_ts",
+            Field::LastTs => "last_ts",
+            Field::Duration => "duration",
+            Field::AvgBps => "avg_bps",
+            Field::AvgPps => "avg_pps",
+            Field::CurBps => "cur_bps",
+            Field::CurPps => "cur_pps",
+            Field::FrameBytes => "frame_bytes",
+            Field::CapturedBytes => "captured_bytes",
+            Field::IpBytes => "ip_bytes",
+            Field::PayloadBytes => "payload_bytes"
+        }
+    }
+
+    /// Returns the field whose column has the given title in text reports, e.g. "PACKETS #"
+    pub fn from_title(title: &str) -> Option<Field> {
+        match title.trim() {
+            "PACKETS #" => Some(Field::Packets),
+            "FIRST TIMESTAMP" => Some(Field::FirstTs),
+            "LAST TIMESTAMP" => Some(Field::LastTs),
+            t => Field::from_name(&t.to_lowercase().replace(' ', "_"))
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Field::SrcIp => "SRC_IP",
+            Field::DstIp => "DST_IP",
+            Field::SrcPort => "SRC_PORT",
+            Field::DstPort => "DST_PORT",
+            Field::Protocols => "PROTOCOLS",
+            Field::Interface => "INTERFACE",
+            Field::Vlan => "VLAN",
+            Field::ExtHeaders => "EXT HEADERS",
+            Field::SrcMac => "SRC MAC",
+            Field::DstMac => "DST MAC",
+            Field::SrcVendor => "SRC VENDOR",
+            Field::DstVendor => "DST VENDOR",
+            Field::Bytes => "BYTES",
+            Field::Packets => "PACKETS #",
+            Field::FirstTs => "FIRST TIMESTAMP",
+            Field::LastTs => "LAST TIMESTAMP",
+            Field::Duration => "DURATION",
+            Field::AvgBps => "AVG BPS",
+            Field::AvgPps => "AVG PPS",
+            Field::CurBps => "CUR BPS",
+            Field::CurPps => "CUR PPS",
+            Field::FrameBytes => "FRAME BYTES",
+            Field::CapturedBytes => "CAPTURED BYTES",
+            Field::IpBytes => "IP BYTES",
+            Field::PayloadBytes => "PAYLOAD BYTES"
+        }
+    }
+
+    /// Returns the printable value of this field. Current rates are computed
+    /// over the given time elapsed since the start of the interval (in seconds).
+    pub fn value(&self, detail: &TrafficDetail, elapsed: f64) -> String {
+        match self {
+            Field::SrcIp => String::from(&detail.src_ip),
+            Field::DstIp => String::from(&detail.dst_ip),
+            Field::SrcPort => String::from(&detail.src_port),
+            Field::DstPort => String::from(&detail.dst_port),
+            Field::Protocols => format_list(&detail.protocols),
+            Field::Interface => format_list(&detail.interface),
+            Field::Vlan => format_list(&detail.vlan),
+            Field::ExtHeaders => format_list(&detail.ext_headers),
+            Field::SrcMac => format_list(&detail.src_mac),
+            Field::DstMac => format_list(&detail.dst_mac),
+            Field::SrcVendor => format_list(&detail.src_vendor),
+            Field::DstVendor => format_list(&detail.dst_vendor),
+            Field::Bytes => detail.bytes(),
+            Field::Packets => detail.npackets.to_string(),
+            Field::FirstTs => detail.first_ts(),
+            Field::LastTs => detail.last_ts(),
+            Field::Duration => format_duration(detail.duration()),
+            Field::AvgBps => format_rate(detail.avg_bps(), "bps"),
+            Field::AvgPps => format_rate(detail.avg_pps(), "pps"),
+            Field::CurBps => format_rate(detail.cur_bps(elapsed), "bps"),
+            Field::CurPps => format_rate(detail.cur_pps(elapsed), "pps"),
+            Field::FrameBytes => format_bytes(detail.frame_bytes),
+            Field::CapturedBytes => format_bytes(detail.captured_bytes),
+            Field::IpBytes => format_bytes(detail.ip_bytes),
+            Field::PayloadBytes => format_bytes(detail.payload_bytes)
+        }
+    }
+
+    /// Tells whether the raw value of this field is a number
+    pub fn numeric(&self) -> bool {
+        !matches!(self, Field::SrcIp | Field::DstIp | Field::SrcPort | Field::DstPort |
+                        Field::Protocols | Field::Interface | Field::Vlan | Field::ExtHeaders | Field::SrcMac | Field::DstMac |
+                        Field::SrcVendor | Field::DstVendor | Field::FirstTs | Field::LastTs)
+    }
+
+    /// Returns the value of this field as written in machine readable reports: byte counts
+    /// are not rounded, timestamps follow ISO 8601, durations are in seconds and rates are
+    /// plain numbers.
+    pub fn raw(&self, detail: &TrafficDetail, elapsed: f64) -> String {
+        match self {
+            Field::Bytes => detail.bytes.to_string(),
+            Field::FirstTs => format_iso_ts(detail.first_ts),
+            Field::LastTs => format_iso_ts(detail.last_ts),
+            Field::Duration => detail.duration().to_string(),
+            Field::AvgBps => format!("{:.3}", detail.avg_bps()),
+            Field::AvgPps => format!("{:.3}", detail.avg_pps()),
+            Field::CurBps => format!("{:.3}", detail.cur_bps(elapsed)),
+            Field::CurPps => format!("{:.3}", detail.cur_pps(elapsed)),
+            Field::FrameBytes => detail.frame_bytes.to_string(),
+            Field::CapturedBytes => detail.captured_bytes.to_string(),
+            Field::IpBytes => detail.ip_bytes.to_string(),
+            Field::PayloadBytes => detail.payload_bytes.to_string(),
+            _ => self.value(detail, elapsed)
+        }
+    }
+
+    /// Compares two details by this field. Addresses and ports are compared
+    /// by value rather than alphabetically whenever possible.
+    pub fn compare(&self, a: &TrafficDetail, b: &TrafficDetail) -> Ordering {
+        match self {
+            Field::SrcIp => compare_ips(&a.src_ip, &b.src_ip),
+            Field::DstIp => compare_ips(&a.dst_ip, &b.dst_ip),
+            Field::SrcPort => compare_ports(&a.src_port, &b.src_port),
+            Field::DstPort => compare_ports(&a.dst_port, &b.dst_port),
+            Field::Protocols => a.protocols.cmp(&b.protocols),
+            Field::Interface => a.interface.cmp(&b.interface),
+            Field::Vlan => a.vlan.cmp(&b.vlan),
+            Field::ExtHeaders => a.ext_headers.cmp(&b.ext_headers),
+            Field::SrcMac => a.src_mac.cmp(&b.src_mac),
+            Field::DstMac => a.dst_mac.cmp(&b.dst_mac),
+            Field::SrcVendor => a.src_vendor.cmp(&b.src_vendor),
+            Field::DstVendor => a.dst_vendor.cmp(&b.dst_vendor),
+            Field::Bytes => a.bytes.cmp(&b.bytes),
+            Field::Packets => a.npackets.cmp(&b.npackets),
+            Field::FirstTs => a.first_ts.cmp(&b.first_ts),
+            Field::LastTs => a.last_ts.cmp(&b.last_ts),
+            Field::Duration => a.duration().cmp(&b.duration()),
+            Field::AvgBps => a.avg_bps().total_cmp(&b.avg_bps()),
+            Field::AvgPps => a.avg_pps().total_cmp(&b.avg_pps()),
+            Field::CurBps => a.interval_bytes.cmp(&b.interval_bytes),
+            Field::CurPps => a.interval_npackets.cmp(&b.interval_npackets),
+            Field::FrameBytes => a.frame_bytes.cmp(&b.frame_bytes),
+            Field::CapturedBytes => a.captured_bytes.cmp(&b.captured_bytes),
+            Field::IpBytes => a.ip_bytes.cmp(&b.ip_bytes),
+            Field::PayloadBytes => a.payload_bytes.cmp(&b.payload_bytes)
+        }
+    }
+}
+
+/// Parses a comma separated list of field names, e.g. "duration,avg_bps"
+pub fn parse_fields(spec: &str) -> Option<Vec<Field>> {
+    spec.split(',').map(Field::from_name).collect()
+}
+
+// Formats a duration (in seconds) as hh:mm:ss
+fn format_duration(secs: i64) -> String {
+    format!("{:02}:{:02}:{:02}", secs / 3600, (secs % 3600) / 60, secs % 60)
+}
+
+// Formats a rate using the most suitable metric prefix
+fn format_rate(rate: f64, unit: &str) -> String {
+    if rate >= 1e9 {
+        format!("{:.1} G{}", rate / 1e9, unit)
+    } else if rate >= 1e6 {
+        format!("{:.1} M{}", rate / 1e6, unit)
+    } else if rate >= 1e3 {
+        format!("{:.1} K{}", rate / 1e3, unit)
+    } else {
+        format!("{:.1} {}", rate, unit)
+    }
+}
+
+fn compare_ips(a: &str, b: &str) -> Ordering {
+    match (a.parse::<IpAddr>(), b.parse::<IpAddr>()) {
+        (Ok(ip_a), Ok(ip_b)) => ip_a.cmp(&ip_b),
+        _ => a.cmp(b)
+    }
+}
+
+fn compare_ports(a: &str, b: &str) -> Ordering {
+    match (a.parse::<u16>(), b.parse::<u16>()) {
+        (Ok(port_a), Ok(port_b)) => port_a.cmp(&port_b),
+        _ => a.cmp(b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_and_rates_are_formatted() {
+        let mut detail = TrafficDetail::new();
+        detail.bytes = 1_500_000;
+        detail.npackets = 1000;
+        detail.first_ts = 1_667_811_600;
+        detail.last_ts = 1_667_815_325;
+        detail.interval_bytes = 250;
+        detail.interval_npackets = 5;
+
+        let values: Vec<(String, String)> = [Field::Duration, Field::AvgBps, Field::AvgPps, Field::CurBps, Field::CurPps]
+                .iter().map(|f| (f.value(&detail, 4.0), f.raw(&detail, 4.0))).collect();
+        assert_eq!(values, vec![
+            (String::from("01:02:05"), String::from("3725")),
+            (String::from("3.2 Kbps"), String::from("3221.477")),
+            (String::from("0.3 pps"), String::from("0.268")),
+            (String::from("500.0 bps"), String::from("500.000")),
+            (String::from("1.2 pps"), String::from("1.250"))
+        ]);
+
+        // Records lasting less than a second are considered to last one second
+        detail.last_ts = detail.first_ts;
+        assert_eq!(Field::AvgBps.value(&detail, 4.0), "12.0 Mbps");
+    }
+
+    #[test]
+    fn added_columns_are_parsed() {
+        assert_eq!(parse_fields("duration,avg_bps,cur_pps"), Some(vec![Field::Duration, Field::AvgBps, Field::CurPps]));
+        assert_eq!(parse_fields("duration,speed"), None);
+    }
+}
@@ -0,0 +1,588 @@
+use super::report::{TrafficDetail, TrafficReport, format_list, parse_list};
+use super::condition::{Conditions, parse_time};
+use super::field::Field;
+use super::parser::{parse, parse_frame};
+use super::pcapng::{PcapngReader, LINKTYPE_ETHERNET};
+use pcap::Capture;
+use rusqlite::{Connection, OpenFlags};
+use serde_json::Value;
+use std::{collections::{BTreeSet, HashMap}, fs::{self, File}, io::{BufReader, Error, Read}};
+
+// first bytes of any SQLite database
+const SQLITE_MAGIC: &[u8] = b"SQLite format 3\0";
+
+// first line of text reports
+const TEXT_HEADER: &str = "This report was generated by rsniffer";
+
+// first characters of the row separating the titles of Markdown tables from their rows
+const MARKDOWN_SEPARATOR: &str = "|-";
+
+// labels of the rows summing up the records of text and Markdown reports
+const SUMMARY_LABELS: [&str; 2] = ["OTHERS (", "TOTAL ("];
+
+// first bytes of pcap files (microsecond and nanosecond resolution, both byte orders)
+const PCAP_MAGICS: [[u8; 4]; 4] = [[0xd4, 0xc3, 0xb2, 0xa1], [0xa1, 0xb2, 0xc3, 0xd4], [0x4d, 0x3c, 0xb2, 0xa1],
+                                   [0xa1, 0xb2, 0x3c, 0x4d]];
+
+// first bytes of pcapng files
+const PCAPNG_MAGIC: [u8; 4] = [0x0a, 0x0d, 0x0d, 0x0a];
+
+/// Adds to the report the traffic read from the given file, which is either a capture (pcap or pcapng)
+/// or a stored report (see [`load_records`]). The packets of a capture are parsed and counted as in a live
+/// capture, while the records of a report are added as they are. Only the packets or records meeting the
+/// conditions are added. Captures whose link-layer header type is not Ethernet are rejected, as are pcapng
+/// captures holding packets captured on such an interface.
+pub fn load_into(path: &str, report: &mut TrafficReport, conditions: &Conditions) -> Result<(), Error> {
+    let mut magic = [0u8; 4];
+    if File::open(path)?.read_exact(&mut magic).is_err() {
+        magic = [0u8; 4];
+    }
+
+    let mut add = |detail: TrafficDetail| {
+        if !detail.handled || conditions.matches(&detail) {
+            report.new_detail(detail);
+        }
+    };
+
+    if magic == PCAPNG_MAGIC {
+        // Packets are mapped to the interfaces they have been captured on
+        let mut reader = PcapngReader::new(BufReader::new(File::open(path)?));
+        while let Some(frame) = reader.next_frame()? {
+            let interface = reader.interface(frame.interface).ok_or_else(|| Error::other("Unknown interface."))?;
+            if interface.linktype != LINKTYPE_ETHERNET {
+                return Err(Error::other(format!("Unsupported link-layer header type {} on interface {} (only Ethernet \
+                        captures are handled).", interface.linktype, interface.label(frame.interface))));
+            }
+
+            let mut detail = parse_frame(interface.linktype, frame.ts, frame.len, &frame.data);
+            detail.interface = BTreeSet::from([interface.label(frame.interface)]);
+            add(detail);
+        }
+        return Ok(());
+    }
+
+    if PCAP_MAGICS.contains(&magic) {
+        let mut capture = Capture::from_file(path).map_err(Error::other)?;
+        let linktype = capture.get_datalink().0 as u16;
+        if linktype != LINKTYPE_ETHERNET {
+            return Err(Error::other(format!("Unsupported link-layer header type {} (only Ethernet captures are handled).", linktype)));
+        }
+
+        loop {
+            match capture.next_packet() {
+                Ok(packet) => add(parse(linktype, &packet)),
+                Err(pcap::Error::NoMorePackets) => { return Ok(()); },
+                Err(e) => { return Err(Error::other(e)); }
+            }
+        }
+    }
+
+    for detail in load_records(path)?.into_iter().filter(|d| conditions.matches(d)) {
+        report.add_record(detail);
+    }
+
+    Ok(())
+}
+
+/// Loads the records stored by a report in text, Markdown, CSV, JSON, NDJSON or SQLite format.
+/// The format is recognized from the content of the file, other files being rejected.
+/// 
+/// Text reports only hold rounded byte counts (e.g. "12 KB"), which are read as such.
+/// Reports listing the records of several intervals (NDJSON streams and SQLite databases)
+/// are merged: in cumulative mode the last counters of each record are kept, while in delta
+/// mode the counters of all intervals are summed.
+pub fn load_records(path: &str) -> Result<Vec<TrafficDetail>, Error> {
+    let content = fs::read(path)?;
+
+    if content.starts_with(SQLITE_MAGIC) {
+        return load_sqlite(path);
+    }
+
+    let text = String::from_utf8_lossy(&content);
+    if text.starts_with(TEXT_HEADER) {
+        // Markdown reports start like text ones, but their tables have no borders
+        if text.lines().any(|l| l.starts_with(MARKDOWN_SEPARATOR)) {
+            load_markdown(&text)
+        } else {
+            load_text(&text)
+        }
+    } else if text.trim_start().starts_with('{') {
+        match serde_json::from_str::<Value>(&text) {
+            Ok(document) => load_json(&document),
+            Err(_) => load_ndjson(&text)
+        }
+    } else if is_csv(&text) {
+        load_csv(&text)
+    } else {
+        Err(Error::other("Unsupported report format."))
+    }
+}
+
+// CSV reports start with comment lines, followed by the titles of the columns, among which the counters
+fn is_csv(text: &str) -> bool {
+    text.lines()
+            .find(|l| !l.starts_with('#'))
+            .is_some_and(|l| l.split(',').any(|name| name.trim() == Field::Packets.name()))
+}
+
+fn load_json(document: &Value) -> Result<Vec<TrafficDetail>, Error> {
+    let records = match document.get("records").and_then(|r| r.as_array()) {
+        Some(r) => r,
+        None => { return Err(Error::other("No records found.")); }
+    };
+
+    records.iter().map(from_object).collect()
+}
+
+fn load_ndjson(text: &str) -> Result<Vec<TrafficDetail>, Error> {
+    let mut delta = false;
+    let mut snapshots: Vec<Vec<(String, TrafficDetail)>> = vec![];
+    let mut last_timestamp = String::new();
+
+    for line in text.lines().filter(|l| !l.trim().is_empty()) {
+        let object: Value = serde_json::from_str(line).map_err(Error::other)?;
+
+        match object.get("type").and_then(|t| t.as_str()) {
+            Some("metadata") => {
+                delta |= object.get("delta").and_then(|d| d.as_bool()).unwrap_or(false);
+            },
+            Some("record") => {
+                let timestamp = object.get("timestamp").and_then(|t| t.as_str()).unwrap_or_default();
+                if snapshots.is_empty() || timestamp != last_timestamp {
+                    snapshots.push(vec![]);
+                    last_timestamp = String::from(timestamp);
+                }
+
+                // Streams written without keys identify records by all their key fields
+                let detail = from_object(&object)?;
+                let key = match object.get("key").and_then(|k| k.as_str()) {
+                    Some(k) => String::from(k),
+                    None => identity(&detail)
+                };
+
+                if let Some(snapshot) = snapshots.last_mut() {
+                    snapshot.push((key, detail));
+                }
+            },
+            _ => {}
+        }
+    }
+
+    Ok(merge_snapshots(snapshots, delta))
+}
+
+fn load_csv(text: &str) -> Result<Vec<TrafficDetail>, Error> {
+    // Metadata is written as comment lines before the table
+    let table: String = text.lines()
+            .filter(|l| !l.starts_with('#'))
+            .map(|l| format!("{}\n", l))
+            .collect();
+
+    let mut reader = ::csv::Reader::from_reader(table.as_bytes());
+    let headers = reader.headers().map_err(Error::other)?.clone();
+    let mut records = vec![];
+
+    for row in reader.records() {
+        let row = row.map_err(Error::other)?;
+        let mut detail = TrafficDetail::new();
+
+        for (name, value) in headers.iter().zip(row.iter()) {
+            set_value(&mut detail, name, value)?;
+        }
+        records.push(detail);
+    }
+
+    Ok(records)
+}
+
+fn load_text(text: &str) -> Result<Vec<TrafficDetail>, Error> {
+    // Table rows are enclosed in borders, the first one holding the titles. Only the first
+    // table lists records, the following ones list the traffic not carrying IP.
+    let rows = text.lines()
+            .skip_while(|l| !l.starts_with('+'))
+            .take_while(|l| l.starts_with('+') || l.starts_with('|'))
+            .filter(|l| l.starts_with('|'))
+            .map(|l| l.trim().trim_matches('|').split('|').map(|c| String::from(c.trim())).collect());
+
+    load_table(rows)
+}
+
+fn load_markdown(text: &str) -> Result<Vec<TrafficDetail>, Error> {
+    // The first table lists the records, its titles being followed by a separator row
+    let rows = text.lines()
+            .skip_while(|l| !l.starts_with('|'))
+            .take_while(|l| l.starts_with('|'))
+            .filter(|l| !l.starts_with(MARKDOWN_SEPARATOR))
+            .map(markdown_cells);
+
+    load_table(rows)
+}
+
+// Cells are separated by pipes, those within cells being escaped, while summary labels are in bold
+fn markdown_cells(line: &str) -> Vec<String> {
+    let mut cells = vec![];
+    let mut cell = String::new();
+    let mut chars = line.trim().trim_start_matches('|').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => cell.push(chars.next().unwrap_or('|')),
+            '|' => cells.push(std::mem::take(&mut cell)),
+            _ => cell.push(c)
+        }
+    }
+
+    cells.iter().map(|c| {
+        let c = c.trim();
+        String::from(c.strip_prefix("**").and_then(|c| c.strip_suffix("**")).unwrap_or(c))
+    }).collect()
+}
+
+// Reads the rows of the table of a human readable report, the first one holding the titles
+fn load_table(mut rows: impl Iterator<Item = Vec<String>>) -> Result<Vec<TrafficDetail>, Error> {
+    let names: Vec<String> = match rows.next() {
+        Some(titles) => titles.iter().map(|t| match Field::from_title(t) {
+            Some(field) => String::from(field.name()),
+            None => t.to_lowercase()
+        }).collect(),
+        None => { return Err(Error::other("No table found.")); }
+    };
+
+    let mut records = vec![];
+    for row in rows {
+        // Empty reports hold a single empty row, and summary rows are computed again when needed
+        if row.iter().all(|c| c.is_empty()) || SUMMARY_LABELS.iter().any(|l| row[0].starts_with(l)) {
+            continue;
+        }
+
+        let mut detail = TrafficDetail::new();
+        for (name, value) in names.iter().zip(&row) {
+            match Field::from_name(name) {
+                Some(field) if is_byte_count(field) => set_value(&mut detail, name, &parse_bytes(value)?.to_string())?,
+                _ => set_value(&mut detail, name, value)?
+            }
+        }
+        records.push(detail);
+    }
+
+    Ok(records)
+}
+
+fn is_byte_count(field: Field) -> bool {
+    matches!(field, Field::Bytes | Field::FrameBytes | Field::CapturedBytes | Field::IpBytes | Field::PayloadBytes)
+}
+
+// Parses a byte count formatted with its unit, e.g. "12 KB"
+fn parse_bytes(value: &str) -> Result<usize, Error> {
+    let invalid = || Error::other(format!("Invalid byte count: {}", value));
+    let (number, unit) = value.split_once(' ').ok_or_else(invalid)?;
+    let number = number.parse::<usize>().map_err(|_| invalid())?;
+
+    match unit.trim() {
+        "B" => Ok(number),
+        "KB" => Ok(number * 1_000),
+        "MB" => Ok(number * 1_000_000),
+        "GB" => Ok(number * 1_000_000_000),
+        _ => Err(invalid())
+    }
+}
+
+fn load_sqlite(path: &str) -> Result<Vec<TrafficDetail>, Error> {
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(Error::other)?;
+    let mut records = vec![];
+
+    let captures: Vec<(i64, bool)> = {
+        let mut select = connection.prepare("SELECT id, delta FROM captures ORDER BY id").map_err(Error::other)?;
+        let rows = select.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).map_err(Error::other)?;
+        rows.collect::<Result<_, _>>().map_err(Error::other)?
+    };
+
+    let mut select = connection.prepare("SELECT f.snapshot_id, f.src_ip, f.dst_ip, f.src_port, f.dst_port, f.protocols, \
+            f.bytes, f.packets, f.frame_bytes, f.captured_bytes, f.ip_bytes, f.payload_bytes, f.first_ts, f.last_ts, \
+            f.interface, f.vlan, f.ext_headers, f.src_mac, f.dst_mac, f.src_vendor, f.dst_vendor, f.flow_key \
+            FROM flows f JOIN snapshots s ON f.snapshot_id = s.id WHERE s.capture_id = ?1 ORDER BY f.snapshot_id")
+            .map_err(Error::other)?;
+
+    for (capture_id, delta) in captures {
+        let mut snapshots: Vec<Vec<(String, TrafficDetail)>> = vec![];
+        let mut last_snapshot = None;
+
+        let rows = select.query_map([capture_id], |row| {
+            let mut detail = TrafficDetail::new();
+            detail.src_ip = row.get::<_, Option<String>>(1)?.unwrap_or_default();
+            detail.dst_ip = row.get::<_, Option<String>>(2)?.unwrap_or_default();
+            detail.src_port = row.get::<_, Option<i64>>(3)?.map(|p| p.to_string()).unwrap_or_default();
+            detail.dst_port = row.get::<_, Option<i64>>(4)?.map(|p| p.to_string()).unwrap_or_default();
+            detail.protocols = parse_list(&row.get::<_, Option<String>>(5)?.unwrap_or_default());
+            detail.bytes = row.get::<_, i64>(6)? as usize;
+            detail.npackets = row.get::<_, i64>(7)? as usize;
+            detail.frame_bytes = row.get::<_, i64>(8)? as usize;
+            detail.captured_bytes = row.get::<_, i64>(9)? as usize;
+            detail.ip_bytes = row.get::<_, i64>(10)? as usize;
+            detail.payload_bytes = row.get::<_, i64>(11)? as usize;
+            detail.first_ts = row.get(12)?;
+            detail.last_ts = row.get(13)?;
+
+            let list = |i: usize| row.get::<_, Option<String>>(i).map(|l| parse_list(&l.unwrap_or_default()));
+            detail.interface = list(14)?;
+            detail.vlan = list(15)?;
+            detail.ext_headers = list(16)?;
+            detail.src_mac = list(17)?;
+            detail.dst_mac = list(18)?;
+            detail.src_vendor = list(19)?;
+            detail.dst_vendor = list(20)?;
+
+            let key = row.get::<_, Option<String>>(21)?.unwrap_or_else(|| identity(&detail));
+            Ok((row.get::<_, i64>(0)?, (key, detail)))
+        }).map_err(Error::other)?;
+
+        for row in rows {
+            let (snapshot_id, detail) = row.map_err(Error::other)?;
+            if last_snapshot != Some(snapshot_id) {
+                snapshots.push(vec![]);
+                last_snapshot = Some(snapshot_id);
+            }
+
+            if let Some(snapshot) = snapshots.last_mut() {
+                snapshot.push(detail);
+            }
+        }
+
+        records.extend(merge_snapshots(snapshots, delta));
+    }
+
+    Ok(records)
+}
+
+// Records listed by several snapshots with the same key are the same record, with updated counters
+// in cumulative mode, or with the counters of each interval in delta mode
+fn merge_snapshots(snapshots: Vec<Vec<(String, TrafficDetail)>>, delta: bool) -> Vec<TrafficDetail> {
+    if delta {
+        return snapshots.into_iter().flatten().map(|r| r.1).collect();
+    }
+
+    let mut order = vec![];
+    let mut latest: HashMap<String, TrafficDetail> = HashMap::new();
+
+    for (key, detail) in snapshots.into_iter().flatten() {
+        if !latest.contains_key(&key) {
+            order.push(key.clone());
+        }
+        latest.insert(key, detail);
+    }
+
+    order.iter().filter_map(|k| latest.remove(k)).collect()
+}
+
+// Identifies a record stored without its key by all the fields that may be part of one
+fn identity(detail: &TrafficDetail) -> String {
+    format!("{}|{}|{}|{}|{}|{}|{}|{}", detail.src_ip, detail.dst_ip, detail.src_port, detail.dst_port,
+            format_list(&detail.protocols), format_list(&detail.interface), format_list(&detail.vlan), detail.first_ts)
+}
+
+fn from_object(object: &Value) -> Result<TrafficDetail, Error> {
+    let mut detail = TrafficDetail::new();
+
+    if let Some(members) = object.as_object() {
+        for (name, value) in members {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                v => v.to_string()
+            };
+            set_value(&mut detail, name, &value)?;
+        }
+    }
+
+    Ok(detail)
+}
+
+// Sets the field of the detail written in the column with the given name, if any.
+// Derived fields (e.g. durations and rates) are computed again when needed.
+fn set_value(detail: &mut TrafficDetail, name: &str, value: &str) -> Result<(), Error> {
+    let count = || value.parse::<usize>().map_err(|_| Error::other(format!("Invalid {}: {}", name, value)));
+    let time = || parse_time(value).ok_or_else(|| Error::other(format!("Invalid {}: {}", name, value)));
+
+    match name {
+        "src_ip" | "src_subnet" | "src_group" => detail.src_ip = String::from(value),
+        "dst_ip" | "dst_subnet" | "dst_group" => detail.dst_ip = String::from(value),
+        "src_port" => detail.src_port = String::from(value),
+        "dst_port" => detail.dst_port = String::from(value),
+        "protocols" | "protocol" => detail.protocols = parse_list(value),
+        "interface" => detail.interface = parse_list(value),
+        "vlan" => detail.vlan = parse_list(value),
+        "ext_headers" => detail.ext_headers = parse_list(value),
+        "src_mac" => detail.src_mac = parse_list(value),
+        "dst_mac" => detail.dst_mac = parse_list(value),
+        "src_vendor" => detail.src_vendor = parse_list(value),
+        "dst_vendor" => detail.dst_vendor = parse_list(value),
+        "bytes" => detail.bytes = count()?,
+        "packets" => detail.npackets = count()?,
+        "frame_bytes" => detail.frame_bytes = count()?,
+        "captured_bytes" => detail.captured_bytes = count()?,
+        "ip_bytes" => detail.ip_bytes = count()?,
+        "payload_bytes" => detail.payload_bytes = count()?,
+        "first_ts" => detail.first_ts = time()?,
+        "last_ts" => detail.last_ts = time()?,
+        _ => {}
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{self, OutputFormat};
+    use crate::aggregation::Aggregation;
+    use crate::pcapng::PcapngWriter;
+
+    fn detail(src_port: &str, interfaces: &[&str], payload_bytes: usize) -> TrafficDetail {
+        let mut detail = TrafficDetail::new();
+        detail.src_ip = String::from("10.0.0.1");
+        detail.dst_ip = String::from("10.0.0.2");
+        detail.src_port = String::from(src_port);
+        detail.dst_port = String::from("443");
+        detail.protocols = BTreeSet::from([String::from("TCP")]);
+        detail.interface = interfaces.iter().map(|i| String::from(*i)).collect();
+        detail.payload_bytes = payload_bytes;
+        detail.first_ts = 1_667_811_600;
+        detail.last_ts = 1_667_811_660;
+        detail
+    }
+
+    // Writes a report in the given format and loads it back
+    fn round_trip(format: OutputFormat, name: &str) -> Vec<TrafficDetail> {
+        let mut report = TrafficReport::new();
+        assert!(report.set_columns(vec![Field::Interface]));
+        report.new_detail(detail("1000", &["eth0"], 300));
+        report.new_detail(detail("2000", &["eth1", "wlan0"], 2000));
+
+        let mut content = vec![];
+        output::write(&report.snapshot(), format, true, &mut content).unwrap();
+        load_content(name, &content).unwrap()
+    }
+
+    fn load_content(name: &str, content: &[u8]) -> Result<Vec<TrafficDetail>, Error> {
+        let path = std::env::temp_dir().join(format!("rsniffer-input-{}-{}", std::process::id(), name));
+        let path = path.to_str().unwrap();
+        fs::write(path, content).unwrap();
+
+        let records = load_records(path);
+        fs::remove_file(path).ok();
+        records
+    }
+
+    fn summary(records: &[TrafficDetail]) -> Vec<(String, usize, usize, String, i64, i64)> {
+        let mut summary: Vec<_> = records.iter()
+                .map(|d| (d.src_port.clone(), d.bytes, d.npackets, format_list(&d.interface), d.first_ts, d.last_ts))
+                .collect();
+        summary.sort();
+        summary
+    }
+
+    fn expected() -> Vec<(String, usize, usize, String, i64, i64)> {
+        vec![(String::from("1000"), 300, 1, String::from("eth0"), 1_667_811_600, 1_667_811_660),
+             (String::from("2000"), 2000, 1, String::from("eth1, wlan0"), 1_667_811_600, 1_667_811_660)]
+    }
+
+    #[test]
+    fn reports_are_read_back_in_every_format() {
+        for (format, name) in [(OutputFormat::Text, "report.txt"), (OutputFormat::Markdown, "report.md"),
+                               (OutputFormat::Csv, "report.csv"), (OutputFormat::Json, "report.json"),
+                               (OutputFormat::Ndjson, "report.ndjson")] {
+            let records = round_trip(format, name);
+            assert_eq!(summary(&records), expected(), "{}", name);
+            assert!(records.iter().all(|r| r.dst_ip == "10.0.0.2" && r.protocols.contains("TCP")), "{}", name);
+        }
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        let mut report = TrafficReport::new();
+        report.new_detail(detail("1000", &["eth0"], 300));
+        let mut html = vec![];
+        output::write(&report.snapshot(), OutputFormat::Html, true, &mut html).unwrap();
+
+        for (name, content) in [("report.html", html.as_slice()), ("notes.txt", b"Nothing to see here\n".as_slice()),
+                                ("empty", b"".as_slice())] {
+            let error = load_content(name, content).unwrap_err();
+            assert_eq!(error.to_string(), "Unsupported report format.", "{}", name);
+        }
+    }
+
+    #[test]
+    fn captures_on_other_link_layers_are_rejected() {
+        let path = std::env::temp_dir().join(format!("rsniffer-input-{}.pcapng", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        // Raw IP packets (LINKTYPE_RAW)
+        let mut file = File::create(path).unwrap();
+        let mut writer = PcapngWriter::new(&mut file, 101, "tun0", None).unwrap();
+        writer.write_packet(1_667_811_600_000_000, 20, &[0x45; 20]).unwrap();
+        writer.flush().unwrap();
+
+        let error = load_into(path, &mut TrafficReport::new(), &Conditions::default()).unwrap_err();
+        std::fs::remove_file(path).ok();
+        assert_eq!(error.to_string(), "Unsupported link-layer header type 101 on interface tun0 (only Ethernet \
+                captures are handled).");
+    }
+
+    #[test]
+    fn markdown_cells_are_unescaped() {
+        assert_eq!(markdown_cells("| a\\|b | **TOTAL (2 records)** |  | 12 KB |"),
+                   vec!["a|b", "TOTAL (2 records)", "", "12 KB"]);
+    }
+
+    #[test]
+    fn byte_counts_are_read_with_their_unit() {
+        assert_eq!(parse_bytes("300 B").unwrap(), 300);
+        assert_eq!(parse_bytes("12 KB").unwrap(), 12_000);
+        assert_eq!(parse_bytes("3 GB").unwrap(), 3_000_000_000);
+        assert!(parse_bytes("12").is_err());
+        assert!(parse_bytes("12 TB").is_err());
+    }
+
+    #[test]
+    fn ndjson_intervals_are_merged() {
+        let record = |timestamp: &str, packets: usize| format!("{{\"type\":\"record\",\"timestamp\":\"{}\",\"src_ip\":\"10.0.0.1\",\
+                \"dst_ip\":\"10.0.0.2\",\"protocols\":\"TCP\",\"packets\":{},\"first_ts\":1667811600}}", timestamp, packets);
+        let metadata = |delta: bool| format!("{{\"type\":\"metadata\",\"delta\":{}}}", delta);
+
+        // Cumulative counters are replaced by the ones of the following intervals
+        let lines = [metadata(false), record("t1", 2), record("t2", 5)].join("\n");
+        let records = load_ndjson(&lines).unwrap();
+        assert_eq!(records.iter().map(|r| r.npackets).collect::<Vec<usize>>(), vec![5]);
+
+        // Counters of each interval are all kept, to be summed
+        let lines = [metadata(true), record("t1", 2), record("t2", 5)].join("\n");
+        let records = load_ndjson(&lines).unwrap();
+        assert_eq!(records.iter().map(|r| r.npackets).collect::<Vec<usize>>(), vec![2, 5]);
+    }
+
+    #[test]
+    fn records_differing_by_interface_are_kept_apart() {
+        let mut report = TrafficReport::new();
+        report.set_aggregation(Aggregation::parse("interface").unwrap());
+
+        // Both records start in the same second, and are updated in the second interval
+        let mut content = vec![];
+        for _ in 0..2 {
+            report.new_detail(detail("1000", &["eth0"], 100));
+            report.new_detail(detail("2000", &["eth1"], 300));
+            output::write(&report.snapshot(), OutputFormat::Ndjson, true, &mut content).unwrap();
+            report.roll_interval();
+        }
+
+        let mut records: Vec<(String, usize, usize)> = load_content("interfaces.ndjson", &content).unwrap().iter()
+                .map(|r| (format_list(&r.interface), r.bytes, r.npackets))
+                .collect();
+        records.sort();
+        assert_eq!(records, vec![(String::from("eth0"), 200, 2), (String::from("eth1"), 600, 2)]);
+    }
+
+    #[test]
+    fn invalid_values_are_reported() {
+        let error = load_csv("src_ip,packets\n10.0.0.1,many\n").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid packets: many");
+    }
+}
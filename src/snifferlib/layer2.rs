@@ -0,0 +1,137 @@
+use std::{cmp::Reverse, collections::BTreeMap};
+
+// EtherType values below this one are the length of an IEEE 802.3 frame, followed by an LLC header
+const MAX_802_3_LENGTH: u16 = 0x05dc;
+
+/// Operation carried by an ARP message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ArpOperation {
+    Request,
+    Reply,
+    Other(u16)
+}
+
+impl ArpOperation {
+    pub fn from_code(code: u16) -> ArpOperation {
+        match code {
+            1 => ArpOperation::Request,
+            2 => ArpOperation::Reply,
+            _ => ArpOperation::Other(code)
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            ArpOperation::Request => String::from("request"),
+            ArpOperation::Reply => String::from("reply"),
+            ArpOperation::Other(code) => format!("operation {}", code)
+        }
+    }
+}
+
+/// Addresses carried by an ARP message
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ArpMessage {
+    pub operation: ArpOperation,
+    pub sender_mac: String,
+    pub sender_ip: String,
+    pub target_mac: String,
+    pub target_ip: String
+}
+
+/// A frame that does not carry IP traffic, identified by its EtherType
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer2Frame {
+    /// EtherType in hexadecimal (e.g. "0x0806"), or "802.3" for frames carrying an LLC header
+    pub ethertype: String,
+    /// Name of the protocol, if known (e.g. "ARP", "LLDP" or "STP")
+    pub protocol: &'static str,
+    pub arp: Option<ArpMessage>
+}
+
+impl Layer2Frame {
+    /// Returns the frame identified by the given EtherType or, for 802.3 frames,
+    /// by the destination service access point of its LLC header
+    pub fn new(ethertype: u16, dsap: Option<u8>) -> Layer2Frame {
+        if ethertype <= MAX_802_3_LENGTH {
+            let protocol = match dsap {
+                Some(0x42) => "STP",
+                Some(0xaa) => "SNAP",
+                Some(0xe0) => "IPX",
+                Some(0xfe) => "OSI",
+                _ => "LLC"
+            };
+
+            return Layer2Frame { ethertype: String::from("802.3"), protocol, arp: None };
+        }
+
+        let protocol = match ethertype {
+            0x0806 => "ARP",
+            0x0842 => "Wake-on-LAN",
+            0x22f3 => "TRILL",
+            0x6003 => "DECnet",
+            0x8035 => "RARP",
+            0x809b => "AppleTalk",
+            0x80f3 => "AARP",
+            0x8137 => "IPX",
+            0x8808 => "Ethernet flow control",
+            0x8809 => "LACP",
+            0x8847 | 0x8848 => "MPLS",
+            0x8863 => "PPPoE discovery",
+            0x8864 => "PPPoE session",
+            0x888e => "EAPOL",
+            0x88cc => "LLDP",
+            0x88e5 => "MACsec",
+            0x88f7 => "PTP",
+            0x8902 => "CFM",
+            0x8906 => "FCoE",
+            _ => "unknown"
+        };
+
+        Layer2Frame { ethertype: format!("{:#06x}", ethertype), protocol, arp: None }
+    }
+}
+
+/// Packets and bytes (frame length) of the frames not carrying IP traffic,
+/// by EtherType and, for ARP, by message
+#[derive(Debug, Clone, Default)]
+pub struct Layer2Traffic {
+    ethertypes: BTreeMap<(String, &'static str), (usize, usize)>,
+    arp: BTreeMap<ArpMessage, (usize, usize)>
+}
+
+impl Layer2Traffic {
+    pub fn count(&mut self, frame: &Layer2Frame, bytes: usize) {
+        let counters = self.ethertypes.entry((String::from(&frame.ethertype), frame.protocol)).or_insert((0, 0));
+        counters.0 += 1;
+        counters.1 += bytes;
+
+        if let Some(message) = &frame.arp {
+            let counters = self.arp.entry(message.clone()).or_insert((0, 0));
+            counters.0 += 1;
+            counters.1 += bytes;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ethertypes.is_empty()
+    }
+
+    /// Returns the EtherType, protocol, packets and bytes of each kind of frame, the busiest first
+    pub fn ethertypes(&self) -> Vec<(&str, &'static str, usize, usize)> {
+        let mut ethertypes: Vec<(&str, &'static str, usize, usize)> = self.ethertypes.iter()
+                .map(|((ethertype, protocol), (packets, bytes))| (ethertype.as_str(), *protocol, *packets, *bytes))
+                .collect();
+        ethertypes.sort_by_key(|e| Reverse(e.2));
+        ethertypes
+    }
+
+    /// Returns each ARP message with its packets and bytes, the most repeated first
+    pub fn arp(&self) -> Vec<(&ArpMessage, usize, usize)> {
+        let mut arp: Vec<(&ArpMessage, usize, usize)> = self.arp.iter()
+                .map(|(message, (packets, bytes))| (message, *packets, *bytes))
+                .collect();
+        arp.sort_by_key(|a| Reverse(a.1));
+        arp
+    }
+}
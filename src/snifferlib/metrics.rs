@@ -0,0 +1,270 @@
+use super::report::{TrafficDetail, TrafficReport, format_list};
+use super::capture::CaptureStats;
+use super::state_handler::{State, StateHandler};
+use std::{collections::BTreeMap, fmt::Write as _, io::{BufRead, BufReader, Error, ErrorKind, Write},
+          net::{TcpListener, TcpStream}, sync::{Arc, Mutex}, thread::{self, JoinHandle}, time::Duration};
+
+// number of flows exposed when no limit is set on the report
+const DEFAULT_TOP_FLOWS: usize = 10;
+
+// time between checks for new connections (and for the sniffer being stopped)
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// time given to a client to send its request, after which the connection is dropped
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Counters cumulated since the start of the capture, not affected by the expiration of records
+#[derive(Default)]
+pub struct Counters {
+    /// Packets and bytes by protocol
+    pub protocols: BTreeMap<String, (usize, usize)>,
+    /// Packets not handled by the parser, by reason
+    pub unhandled: BTreeMap<&'static str, usize>,
+    pub capture: CaptureStats
+}
+
+impl Counters {
+    pub fn count(&mut self, detail: &TrafficDetail) {
+        if detail.handled {
+            let counters = self.protocols.entry(format_list(&detail.protocols)).or_insert((0, 0));
+            counters.0 += detail.npackets;
+            counters.1 += detail.bytes;
+        } else {
+            *self.unhandled.entry(detail.error.unwrap_or("unknown")).or_insert(0) += 1;
+        }
+    }
+}
+
+/// Binds the endpoint to the given address (e.g. "127.0.0.1:9184"), so that errors are reported
+/// before starting the capture
+pub fn bind(address: &str) -> Result<TcpListener, Error> {
+    let listener = TcpListener::bind(address)?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+/// Serves the metrics in the Prometheus text format, until the sniffer is stopped.
+/// Each connection is handled on its own thread, so that a slow client does not delay the others
+pub fn serve(listener: TcpListener, report: Arc<Mutex<TrafficReport>>, state: Arc<StateHandler>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        loop {
+            if let State::Stopped = state.state() {
+                break;
+            }
+
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    let (report, state) = (Arc::clone(&report), Arc::clone(&state));
+                    thread::spawn(move || respond(stream, &report, &state).ok());
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+                Err(_) => thread::sleep(POLL_INTERVAL)
+            }
+        }
+    })
+}
+
+fn respond(mut stream: TcpStream, report: &Arc<Mutex<TrafficReport>>, state: &StateHandler) -> Result<(), Error> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+    // Only the request line matters, headers are skipped
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+    let (status, body) = if path == "/metrics" || path.starts_with("/metrics?") {
+        let rh = report.lock().unwrap();
+        ("200 OK", render(&rh, state))
+    } else {
+        ("404 Not Found", String::from("Metrics are served at /metrics\n"))
+    };
+
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+            Content-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body)?;
+    stream.flush()
+}
+
+/// Renders the metrics of the report and the state of the sniffer in the Prometheus text format
+pub fn render(report: &TrafficReport, state: &StateHandler) -> String {
+    let counters = report.counters();
+    let mut out = String::new();
+
+    header(&mut out, "rsniffer_packets_total", "counter", "Packets captured and handled, by protocol.");
+    for (protocol, (packets, _)) in &counters.protocols {
+        writeln!(out, "rsniffer_packets_total{{protocol=\"{}\"}} {}", escape(protocol), packets).ok();
+    }
+
+    header(&mut out, "rsniffer_bytes_total", "counter", "Bytes captured and handled, by protocol.");
+    for (protocol, (_, bytes)) in &counters.protocols {
+        writeln!(out, "rsniffer_bytes_total{{protocol=\"{}\"}} {}", escape(protocol), bytes).ok();
+    }
+
+    header(&mut out, "rsniffer_parser_errors_total", "counter", "Packets the parser could not handle, by reason.");
+    for (reason, packets) in &counters.unhandled {
+        writeln!(out, "rsniffer_parser_errors_total{{reason=\"{}\"}} {}", reason, packets).ok();
+    }
+
+    let layer2 = report.layer2();
+    header(&mut out, "rsniffer_layer2_packets_total", "counter", "Frames not carrying IP, by EtherType.");
+    for (ethertype, protocol, packets, _) in layer2.ethertypes() {
+        writeln!(out, "rsniffer_layer2_packets_total{{ethertype=\"{}\",protocol=\"{}\"}} {}", ethertype, protocol, packets).ok();
+    }
+
+    header(&mut out, "rsniffer_layer2_bytes_total", "counter", "Bytes of the frames not carrying IP, by EtherType.");
+    for (ethertype, protocol, _, bytes) in layer2.ethertypes() {
+        writeln!(out, "rsniffer_layer2_bytes_total{{ethertype=\"{}\",protocol=\"{}\"}} {}", ethertype, protocol, bytes).ok();
+    }
+
+    // ARP messages are summed up by operation, as senders and targets would be too many labels
+    let mut arp: BTreeMap<String, usize> = BTreeMap::new();
+    for (message, packets, _) in layer2.arp() {
+        *arp.entry(message.operation.name()).or_insert(0) += packets;
+    }
+
+    header(&mut out, "rsniffer_arp_packets_total", "counter", "ARP messages, by operation.");
+    for (operation, packets) in &arp {
+        writeln!(out, "rsniffer_arp_packets_total{{operation=\"{}\"}} {}", operation, packets).ok();
+    }
+
+    header(&mut out, "rsniffer_pcap_received_packets_total", "counter", "Packets received by pcap.");
+    writeln!(out, "rsniffer_pcap_received_packets_total {}", counters.capture.received).ok();
+    header(&mut out, "rsniffer_pcap_dropped_packets_total", "counter", "Packets dropped by pcap, by where they were dropped.");
+    writeln!(out, "rsniffer_pcap_dropped_packets_total{{by=\"kernel\"}} {}", counters.capture.dropped).ok();
+    writeln!(out, "rsniffer_pcap_dropped_packets_total{{by=\"interface\"}} {}", counters.capture.if_dropped).ok();
+
+    header(&mut out, "rsniffer_records", "gauge", "Records currently tracked by the report.");
+    writeln!(out, "rsniffer_records {}", report.len()).ok();
+
+    // Flows come and go, so their counters are exposed as gauges
+    let top = report.top(report.limit().unwrap_or(DEFAULT_TOP_FLOWS));
+    let labels: Vec<String> = top.iter().map(|d| flow_labels(report, d)).collect();
+
+    header(&mut out, "rsniffer_flow_bytes", "gauge", "Bytes of the top records of the report.");
+    for (detail, labels) in top.iter().zip(&labels) {
+        writeln!(out, "rsniffer_flow_bytes{{{}}} {}", labels, detail.bytes).ok();
+    }
+
+    header(&mut out, "rsniffer_flow_packets", "gauge", "Packets of the top records of the report.");
+    for (detail, labels) in top.iter().zip(&labels) {
+        writeln!(out, "rsniffer_flow_packets{{{}}} {}", labels, detail.npackets).ok();
+    }
+
+    let current = match state.state() {
+        State::Running => "running",
+        State::Pausing | State::Paused => "paused",
+        State::Stopped => "stopped"
+    };
+
+    header(&mut out, "rsniffer_state", "gauge", "State of the sniffer.");
+    for s in ["running", "paused", "stopped"] {
+        writeln!(out, "rsniffer_state{{state=\"{}\"}} {}", s, if s == current { 1 } else { 0 }).ok();
+    }
+
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {} {}\n# TYPE {} {}", name, help, name, kind).ok();
+}
+
+// Labels identifying a record, named after the columns identifying it
+fn flow_labels(report: &TrafficReport, detail: &TrafficDetail) -> String {
+    let labels: Vec<String> = report.key_columns().iter()
+            .map(|c| format!("{}=\"{}\"", c.name(), escape(&c.value(detail, 0.0))))
+            .collect();
+    labels.join(",")
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregation::Aggregation;
+    use crate::expiration::Expiration;
+    use std::{collections::BTreeSet, io::Read, time::Instant};
+
+    fn detail(src_port: &str, protocol: &str, interface: &str) -> TrafficDetail {
+        let mut detail = TrafficDetail::new();
+        detail.src_ip = String::from("10.0.0.1");
+        detail.dst_ip = String::from("10.0.0.2");
+        detail.src_port = String::from(src_port);
+        detail.dst_port = String::from("53");
+        detail.protocols = BTreeSet::from([String::from(protocol)]);
+        detail.interface = BTreeSet::from([String::from(interface)]);
+        detail.payload_bytes = 100;
+        detail.first_ts = 1000;
+        detail.last_ts = 1000;
+        detail
+    }
+
+    #[test]
+    fn counters_outlive_the_records() {
+        let mut report = TrafficReport::new();
+        report.set_aggregation(Aggregation::parse("src_port,interface").unwrap());
+        report.set_expiration(Expiration { idle_timeout: Some(60), ..Default::default() });
+        report.new_detail(detail("1000", "TCP", "eth0"));
+        report.new_detail(detail("1000", "TCP", "eth0"));
+        report.new_detail(detail("2000", "UDP", "my \"wifi\""));
+
+        let mut unhandled = TrafficDetail::new();
+        unhandled.handled = false;
+        unhandled.error = Some("malformed");
+        report.new_detail(unhandled);
+
+        // Setting the Paused state would block until resumed
+        let state = StateHandler::new();
+        state.set_state(State::Pausing);
+        let metrics = render(&report, &state);
+        let lines: Vec<&str> = metrics.lines().collect();
+
+        for line in ["# TYPE rsniffer_packets_total counter", "rsniffer_packets_total{protocol=\"TCP\"} 2",
+                     "rsniffer_bytes_total{protocol=\"UDP\"} 100", "rsniffer_parser_errors_total{reason=\"malformed\"} 1",
+                     "rsniffer_records 2", "rsniffer_flow_bytes{src_port=\"1000\",interface=\"eth0\",protocols=\"TCP\"} 200",
+                     "rsniffer_flow_packets{src_port=\"2000\",interface=\"my \\\"wifi\\\"\",protocols=\"UDP\"} 1",
+                     "rsniffer_state{state=\"running\"} 0", "rsniffer_state{state=\"paused\"} 1"] {
+            assert!(lines.contains(&line), "{}", line);
+        }
+
+        // Closed records leave the gauges, not the counters
+        report.expire(1100);
+        let metrics = render(&report, &state);
+        assert!(metrics.contains("rsniffer_packets_total{protocol=\"TCP\"} 2\n"));
+        assert!(metrics.contains("rsniffer_records 0\n"));
+        assert!(!metrics.contains("rsniffer_flow_bytes{"));
+    }
+
+    #[test]
+    fn idle_clients_do_not_delay_other_scrapes() {
+        let listener = bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let state = Arc::new(StateHandler::new());
+        let handle = serve(listener, Arc::new(Mutex::new(TrafficReport::new())), Arc::clone(&state));
+
+        // Connects without ever sending a request
+        let _idle = TcpStream::connect(address).unwrap();
+        thread::sleep(POLL_INTERVAL * 2);
+
+        let start = Instant::now();
+        let mut scrape = TcpStream::connect(address).unwrap();
+        scrape.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        scrape.read_to_string(&mut response).unwrap();
+
+        assert!(start.elapsed() < READ_TIMEOUT);
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("rsniffer_state{state=\"running\"} 1"));
+
+        state.set_state(State::Stopped);
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,594 @@
+use super::report::TrafficDetail;
+use std::{collections::BTreeSet, io::Error, net::{IpAddr, Ipv4Addr, Ipv6Addr, ToSocketAddrs, UdpSocket}, time::{SystemTime, UNIX_EPOCH}};
+
+// maximum number of flow records sent in a single message
+const V5_MAX_RECORDS: usize = 30;
+const MAX_RECORDS: usize = 20;
+
+// number of messages after which templates are sent again, as collectors may miss them over UDP
+const TEMPLATE_REFRESH: u32 = 20;
+
+// identifiers of the templates describing IPv4 and IPv6 flows
+const V4_TEMPLATE_ID: u16 = 256;
+const V6_TEMPLATE_ID: u16 = 257;
+
+// identifiers of the sets holding templates
+const V9_TEMPLATE_SET_ID: u16 = 0;
+const IPFIX_TEMPLATE_SET_ID: u16 = 2;
+
+// information elements, shared by NetFlow v9 and IPFIX unless stated otherwise
+const IN_BYTES: u16 = 1;
+const IN_PKTS: u16 = 2;
+const PROTOCOL: u16 = 4;
+const L4_SRC_PORT: u16 = 7;
+const IPV4_SRC_ADDR: u16 = 8;
+const L4_DST_PORT: u16 = 11;
+const IPV4_DST_ADDR: u16 = 12;
+const LAST_SWITCHED: u16 = 21;   // NetFlow v9 only, milliseconds of uptime
+const FIRST_SWITCHED: u16 = 22;  // NetFlow v9 only, milliseconds of uptime
+const IPV6_SRC_ADDR: u16 = 27;
+const IPV6_DST_ADDR: u16 = 28;
+const FLOW_START_SECONDS: u16 = 150;  // IPFIX only
+const FLOW_END_SECONDS: u16 = 151;    // IPFIX only
+
+// IANA numbers of the protocols whose type and code are exported in place of ports
+const ICMP: u8 = 1;
+const ICMPV6: u8 = 58;
+
+/// Version of the protocol used to export flows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlowVersion {
+    /// NetFlow v5, with fixed records for IPv4 flows only
+    V5,
+    /// NetFlow v9, with templates for IPv4 and IPv6 flows
+    V9,
+    /// IPFIX (also known as NetFlow v10), with templates for IPv4 and IPv6 flows
+    Ipfix
+}
+
+impl FlowVersion {
+    pub fn from_name(name: &str) -> Option<FlowVersion> {
+        match name.trim().to_lowercase().as_str() {
+            "v5" | "5" => Some(FlowVersion::V5),
+            "v9" | "9" => Some(FlowVersion::V9),
+            "ipfix" | "v10" | "10" => Some(FlowVersion::Ipfix),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FlowVersion::V5 => "v5",
+            FlowVersion::V9 => "v9",
+            FlowVersion::Ipfix => "ipfix"
+        }
+    }
+}
+
+/// A record of the report, as described by flow export protocols
+struct Flow {
+    src_ip: IpAddr,
+    dst_ip: IpAddr,
+    src_port: u16,
+    dst_port: u16,
+    protocol: u8,
+    bytes: u64,
+    packets: u64,
+    first_ts: i64,
+    last_ts: i64
+}
+
+impl Flow {
+    /// Converts a record of the report. Addresses replaced by a subnet are exported as the address
+    /// of the network, while the ones that are not part of the aggregation key are left unspecified.
+    fn from_detail(detail: &TrafficDetail) -> Flow {
+        let (src_ip, dst_ip) = match (parse_ip(&detail.src_ip), parse_ip(&detail.dst_ip)) {
+            (Some(IpAddr::V4(src)), Some(IpAddr::V6(dst))) => (IpAddr::V6(src.to_ipv6_mapped()), IpAddr::V6(dst)),
+            (Some(IpAddr::V6(src)), Some(IpAddr::V4(dst))) => (IpAddr::V6(src), IpAddr::V6(dst.to_ipv6_mapped())),
+            (Some(src), Some(dst)) => (src, dst),
+            (Some(src), None) => (src, unspecified(&src)),
+            (None, Some(dst)) => (unspecified(&dst), dst),
+            (None, None) => (IpAddr::V4(Ipv4Addr::UNSPECIFIED), IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+        };
+
+        // As usual in flow records, ICMP type and code are given together as destination port
+        let protocol = protocol_number(&detail.protocols);
+        let (src_port, dst_port) = match protocol {
+            ICMP | ICMPV6 => {
+                let (icmp_type, code) = (detail.src_port.parse::<u16>().unwrap_or(0), detail.dst_port.parse::<u16>().unwrap_or(0));
+                (0, (icmp_type << 8) | (code & 0xff))
+            },
+            _ => (detail.src_port.parse().unwrap_or(0), detail.dst_port.parse().unwrap_or(0))
+        };
+
+        Flow {
+            src_ip,
+            dst_ip,
+            src_port,
+            dst_port,
+            protocol,
+            bytes: detail.ip_bytes as u64,
+            packets: detail.npackets as u64,
+            first_ts: detail.first_ts,
+            last_ts: detail.last_ts
+        }
+    }
+}
+
+/// Exports records of the report as flows to a collector, over UDP, using NetFlow v5, NetFlow v9
+/// or IPFIX. Templates are sent with the first message and then periodically, while sequence
+/// numbers let the collector detect lost messages.
+/// 
+/// #Example
+/// ```
+/// use snifferlib::{FlowExporter, FlowVersion, TrafficDetail};
+/// use std::{collections::BTreeSet, net::UdpSocket};
+/// 
+/// let collector = UdpSocket::bind("127.0.0.1:0").unwrap();
+/// let address = collector.local_addr().unwrap().to_string();
+/// 
+/// let mut flow = TrafficDetail::new();
+/// flow.src_ip = String::from("192.168.1.49");
+/// flow.dst_ip = String::from("52.97.186.114");
+/// flow.src_port = String::from("52583");
+/// flow.dst_port = String::from("443");
+/// flow.protocols = BTreeSet::from([String::from("TCP")]);
+/// flow.ip_bytes = 1500;
+/// 
+/// let mut exporter = FlowExporter::new(&address, FlowVersion::Ipfix).unwrap();
+/// exporter.export(&[flow]).unwrap();
+/// 
+/// let mut buf = [0; 1500];
+/// let len = collector.recv(&mut buf).unwrap();
+/// assert_eq!(&buf[..2], &[0, 10], "IPFIX message");
+/// assert_eq!(u16::from_be_bytes([buf[2], buf[3]]) as usize, len);
+/// ```
+pub struct FlowExporter {
+    socket: UdpSocket,
+    collector: String,
+    version: FlowVersion,
+    // milliseconds since epoch when the exporter was started, used to compute its uptime
+    start: u64,
+    // messages (NetFlow v9) or flows (NetFlow v5 and IPFIX) sent so far
+    sequence: u32,
+    messages: u32
+}
+
+impl FlowExporter {
+    /// Returns an exporter sending flows to the given collector (e.g. "127.0.0.1:2055")
+    pub fn new(collector: &str, version: FlowVersion) -> Result<Self, Error> {
+        let address = match collector.to_socket_addrs()?.next() {
+            Some(a) => a,
+            None => { return Err(Error::other(format!("Couldn't resolve {}.", collector))); }
+        };
+
+        let socket = if address.is_ipv4() { UdpSocket::bind("0.0.0.0:0")? } else { UdpSocket::bind("[::]:0")? };
+        socket.connect(address)?;
+
+        Ok(Self {
+            socket,
+            collector: String::from(collector),
+            version,
+            start: now_millis(),
+            sequence: 0,
+            messages: 0
+        })
+    }
+
+    pub fn description(&self) -> String {
+        format!("{} to {}", self.version.name(), self.collector)
+    }
+
+    /// Sends the given records as flows, in as many messages as needed
+    pub fn export(&mut self, details: &[TrafficDetail]) -> Result<(), Error> {
+        let mut flows: Vec<Flow> = details.iter().map(Flow::from_detail).collect();
+
+        // NetFlow v5 has no room for IPv6 addresses
+        if self.version == FlowVersion::V5 {
+            flows.retain(|f| f.src_ip.is_ipv4());
+        }
+
+        let chunk = if self.version == FlowVersion::V5 { V5_MAX_RECORDS } else { MAX_RECORDS };
+        for flows in flows.chunks(chunk) {
+            let message = match self.version {
+                FlowVersion::V5 => self.v5_message(flows),
+                FlowVersion::V9 => self.v9_message(flows),
+                FlowVersion::Ipfix => self.ipfix_message(flows)
+            };
+
+            self.socket.send(&message)?;
+            self.messages = self.messages.wrapping_add(1);
+        }
+
+        Ok(())
+    }
+
+    fn v5_message(&mut self, flows: &[Flow]) -> Vec<u8> {
+        let now = now_millis();
+        let mut buf = vec![];
+
+        buf.extend_from_slice(&5u16.to_be_bytes());
+        buf.extend_from_slice(&(flows.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&self.uptime(now).to_be_bytes());
+        buf.extend_from_slice(&((now / 1000) as u32).to_be_bytes());
+        buf.extend_from_slice(&((now % 1000 * 1_000_000) as u32).to_be_bytes());
+        buf.extend_from_slice(&self.sequence.to_be_bytes());
+        buf.extend_from_slice(&[0, 0, 0, 0]);  // engine type and id, sampling interval
+
+        for flow in flows {
+            buf.extend_from_slice(&octets(&flow.src_ip));
+            buf.extend_from_slice(&octets(&flow.dst_ip));
+            buf.extend_from_slice(&[0; 8]);  // next hop, input and output interfaces
+            buf.extend_from_slice(&(flow.packets.min(u32::MAX as u64) as u32).to_be_bytes());
+            buf.extend_from_slice(&(flow.bytes.min(u32::MAX as u64) as u32).to_be_bytes());
+            buf.extend_from_slice(&self.uptime(flow.first_ts as u64 * 1000).to_be_bytes());
+            buf.extend_from_slice(&self.uptime(flow.last_ts as u64 * 1000).to_be_bytes());
+            buf.extend_from_slice(&flow.src_port.to_be_bytes());
+            buf.extend_from_slice(&flow.dst_port.to_be_bytes());
+            buf.extend_from_slice(&[0, 0, flow.protocol, 0]);  // padding, TCP flags, protocol, ToS
+            buf.extend_from_slice(&[0; 8]);  // AS numbers, masks and padding
+        }
+
+        self.sequence = self.sequence.wrapping_add(flows.len() as u32);
+        buf
+    }
+
+    fn v9_message(&mut self, flows: &[Flow]) -> Vec<u8> {
+        let now = now_millis();
+        let mut body = vec![];
+        let mut count = 0;
+
+        if self.messages % TEMPLATE_REFRESH == 0 {
+            body.extend(self.templates(V9_TEMPLATE_SET_ID));
+            count += 2;
+        }
+
+        body.extend(self.data_sets(flows));
+        count += flows.len();
+
+        let mut buf = vec![];
+        buf.extend_from_slice(&9u16.to_be_bytes());
+        buf.extend_from_slice(&(count as u16).to_be_bytes());
+        buf.extend_from_slice(&self.uptime(now).to_be_bytes());
+        buf.extend_from_slice(&((now / 1000) as u32).to_be_bytes());
+        buf.extend_from_slice(&self.sequence.to_be_bytes());
+        buf.extend_from_slice(&0u32.to_be_bytes());  // source id
+        buf.extend(body);
+
+        self.sequence = self.sequence.wrapping_add(1);
+        buf
+    }
+
+    fn ipfix_message(&mut self, flows: &[Flow]) -> Vec<u8> {
+        let mut body = vec![];
+
+        if self.messages % TEMPLATE_REFRESH == 0 {
+            body.extend(self.templates(IPFIX_TEMPLATE_SET_ID));
+        }
+
+        body.extend(self.data_sets(flows));
+
+        let mut buf = vec![];
+        buf.extend_from_slice(&10u16.to_be_bytes());
+        buf.extend_from_slice(&((body.len() + 16) as u16).to_be_bytes());
+        buf.extend_from_slice(&((now_millis() / 1000) as u32).to_be_bytes());
+        buf.extend_from_slice(&self.sequence.to_be_bytes());
+        buf.extend_from_slice(&0u32.to_be_bytes());  // observation domain id
+        buf.extend(body);
+
+        self.sequence = self.sequence.wrapping_add(flows.len() as u32);
+        buf
+    }
+
+    // Returns a set with the templates for both IPv4 and IPv6 flows
+    fn templates(&self, set_id: u16) -> Vec<u8> {
+        let mut records = vec![];
+
+        for (template_id, v6) in [(V4_TEMPLATE_ID, false), (V6_TEMPLATE_ID, true)] {
+            let fields = self.fields(v6);
+            records.extend_from_slice(&template_id.to_be_bytes());
+            records.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+
+            for (id, len) in fields {
+                records.extend_from_slice(&id.to_be_bytes());
+                records.extend_from_slice(&len.to_be_bytes());
+            }
+        }
+
+        set(set_id, records)
+    }
+
+    // Returns a set of IPv4 flows and a set of IPv6 flows, each one omitted if empty
+    fn data_sets(&self, flows: &[Flow]) -> Vec<u8> {
+        let mut buf = vec![];
+
+        for (template_id, v6) in [(V4_TEMPLATE_ID, false), (V6_TEMPLATE_ID, true)] {
+            let mut records = vec![];
+
+            for flow in flows.iter().filter(|f| f.src_ip.is_ipv6() == v6) {
+                records.extend_from_slice(&flow.bytes.to_be_bytes());
+                records.extend_from_slice(&flow.packets.to_be_bytes());
+                records.push(flow.protocol);
+                records.extend_from_slice(&flow.src_port.to_be_bytes());
+                records.extend_from_slice(&octets(&flow.src_ip));
+                records.extend_from_slice(&flow.dst_port.to_be_bytes());
+                records.extend_from_slice(&octets(&flow.dst_ip));
+
+                if self.version == FlowVersion::Ipfix {
+                    records.extend_from_slice(&(flow.first_ts as u32).to_be_bytes());
+                    records.extend_from_slice(&(flow.last_ts as u32).to_be_bytes());
+                } else {
+                    records.extend_from_slice(&self.uptime(flow.first_ts as u64 * 1000).to_be_bytes());
+                    records.extend_from_slice(&self.uptime(flow.last_ts as u64 * 1000).to_be_bytes());
+                }
+            }
+
+            if !records.is_empty() {
+                buf.extend(set(template_id, records));
+            }
+        }
+
+        buf
+    }
+
+    // Fields of the templates, in the order records are encoded by data_sets
+    fn fields(&self, v6: bool) -> Vec<(u16, u16)> {
+        let (src, dst, len) = if v6 { (IPV6_SRC_ADDR, IPV6_DST_ADDR, 16) } else { (IPV4_SRC_ADDR, IPV4_DST_ADDR, 4) };
+        let (start, end) = if self.version == FlowVersion::Ipfix {
+            (FLOW_START_SECONDS, FLOW_END_SECONDS)
+        } else {
+            (FIRST_SWITCHED, LAST_SWITCHED)
+        };
+
+        vec![(IN_BYTES, 8), (IN_PKTS, 8), (PROTOCOL, 1), (L4_SRC_PORT, 2), (src, len),
+             (L4_DST_PORT, 2), (dst, len), (start, 4), (end, 4)]
+    }
+
+    // Milliseconds elapsed between the start of the exporter and the given time
+    fn uptime(&self, millis: u64) -> u32 {
+        millis.saturating_sub(self.start) as u32
+    }
+}
+
+// Wraps the given records in a set (or flowset) with the given id, padded to 4 bytes
+fn set(id: u16, mut records: Vec<u8>) -> Vec<u8> {
+    while records.len() % 4 != 0 {
+        records.push(0);
+    }
+
+    let mut buf = vec![];
+    buf.extend_from_slice(&id.to_be_bytes());
+    buf.extend_from_slice(&((records.len() + 4) as u16).to_be_bytes());
+    buf.extend(records);
+    buf
+}
+
+// Parses an address, or the address of a subnet (e.g. 192.168.1.0/24)
+fn parse_ip(ip: &str) -> Option<IpAddr> {
+    let addr = match ip.split_once('/') {
+        Some((a, _)) => a,
+        None => ip
+    };
+
+    addr.parse().ok()
+}
+
+fn unspecified(ip: &IpAddr) -> IpAddr {
+    if ip.is_ipv4() { IpAddr::V4(Ipv4Addr::UNSPECIFIED) } else { IpAddr::V6(Ipv6Addr::UNSPECIFIED) }
+}
+
+fn octets(ip: &IpAddr) -> Vec<u8> {
+    match ip {
+        IpAddr::V4(a) => a.octets().to_vec(),
+        IpAddr::V6(a) => a.octets().to_vec()
+    }
+}
+
+// Returns the IANA number of the given protocol, or 0 if unknown or if several protocols are listed
+// Records holding several protocols (once aggregated) have none in particular
+fn protocol_number(protocols: &BTreeSet<String>) -> u8 {
+    if protocols.len() != 1 {
+        return 0;
+    }
+
+    match protocols.iter().next().map(|p| p.as_str()) {
+        Some("ICMP") => ICMP,
+        Some("TCP") => 6,
+        Some("UDP") => 17,
+        Some("ICMPv6") => ICMPV6,
+        _ => 0
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn detail(src_ip: &str, dst_ip: &str, src_port: &str, dst_port: &str, protocol: &str) -> TrafficDetail {
+        let mut detail = TrafficDetail::new();
+        detail.src_ip = String::from(src_ip);
+        detail.dst_ip = String::from(dst_ip);
+        detail.src_port = String::from(src_port);
+        detail.dst_port = String::from(dst_port);
+        detail.protocols = BTreeSet::from([String::from(protocol)]);
+        detail.ip_bytes = 1500;
+        detail.npackets = 3;
+        detail.first_ts = 1_667_811_600;
+        detail.last_ts = 1_667_811_660;
+        detail
+    }
+
+    fn collector() -> (UdpSocket, String) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let address = socket.local_addr().unwrap().to_string();
+        (socket, address)
+    }
+
+    fn receive(socket: &UdpSocket) -> Vec<u8> {
+        let mut buf = [0; 65536];
+        let len = socket.recv(&mut buf).unwrap();
+        buf[..len].to_vec()
+    }
+
+    fn u16_at(buf: &[u8], pos: usize) -> u16 {
+        u16::from_be_bytes([buf[pos], buf[pos + 1]])
+    }
+
+    fn u32_at(buf: &[u8], pos: usize) -> u32 {
+        u32::from_be_bytes([buf[pos], buf[pos + 1], buf[pos + 2], buf[pos + 3]])
+    }
+
+    fn u64_at(buf: &[u8], pos: usize) -> u64 {
+        (u64::from(u32_at(buf, pos)) << 32) | u64::from(u32_at(buf, pos + 4))
+    }
+
+    // Splits the body of a message into its sets, as id and content
+    fn split_sets(body: &[u8]) -> Vec<(u16, &[u8])> {
+        let mut sets = vec![];
+        let mut pos = 0;
+
+        while pos < body.len() {
+            let (id, len) = (u16_at(body, pos), u16_at(body, pos + 2) as usize);
+            assert!(len >= 4 && len % 4 == 0 && pos + len <= body.len(), "set length {}", len);
+            sets.push((id, &body[pos + 4..pos + len]));
+            pos += len;
+        }
+
+        sets
+    }
+
+    // Decodes the templates of a template set, as id and fields
+    fn templates(set: &[u8]) -> Vec<(u16, Vec<(u16, u16)>)> {
+        let mut templates = vec![];
+        let mut pos = 0;
+
+        while pos + 4 <= set.len() {
+            let (id, count) = (u16_at(set, pos), u16_at(set, pos + 2) as usize);
+            let fields = (0..count).map(|i| (u16_at(set, pos + 4 + i * 4), u16_at(set, pos + 6 + i * 4))).collect();
+            templates.push((id, fields));
+            pos += 4 + count * 4;
+        }
+
+        templates
+    }
+
+    // Decodes the first record of a data set described by the given fields, as field id and value
+    fn record(set: &[u8], fields: &[(u16, u16)]) -> Vec<(u16, Vec<u8>)> {
+        let mut pos = 0;
+        fields.iter().map(|(id, len)| {
+            let value = set[pos..pos + *len as usize].to_vec();
+            pos += *len as usize;
+            (*id, value)
+        }).collect()
+    }
+
+    fn value(record: &[(u16, Vec<u8>)], id: u16) -> &[u8] {
+        &record.iter().find(|(i, _)| *i == id).unwrap().1
+    }
+
+    #[test]
+    fn v5_records_are_fixed_and_ipv4_only() {
+        let (socket, address) = collector();
+        let mut exporter = FlowExporter::new(&address, FlowVersion::V5).unwrap();
+
+        let flows = [detail("192.168.1.49", "52.97.186.114", "52583", "443", "TCP"),
+                     detail("2001:db8::1", "2001:db8::2", "5353", "53", "UDP")];
+        exporter.export(&flows).unwrap();
+        let message = receive(&socket);
+
+        assert_eq!(message.len(), 24 + 48);
+        assert_eq!((u16_at(&message, 0), u16_at(&message, 2)), (5, 1));
+        assert_eq!(u32_at(&message, 16), 0, "sequence");
+
+        let record = &message[24..];
+        assert_eq!(&record[0..4], &[192, 168, 1, 49]);
+        assert_eq!(&record[4..8], &[52, 97, 186, 114]);
+        assert_eq!((u32_at(record, 16), u32_at(record, 20)), (3, 1500));
+        assert_eq!((u16_at(record, 32), u16_at(record, 34)), (52583, 443));
+        assert_eq!(record[38], 6);
+
+        // Sequence numbers count the flows sent so far
+        exporter.export(&flows[..1]).unwrap();
+        assert_eq!(u32_at(&receive(&socket), 16), 1);
+    }
+
+    #[test]
+    fn v9_templates_describe_data_records() {
+        let (socket, address) = collector();
+        let mut exporter = FlowExporter::new(&address, FlowVersion::V9).unwrap();
+
+        exporter.export(&[detail("10.0.0.1", "10.0.0.2", "8", "0", "ICMP")]).unwrap();
+        let message = receive(&socket);
+
+        assert_eq!(u16_at(&message, 0), 9);
+        assert_eq!(u16_at(&message, 2), 3, "two templates and a record");
+
+        let sets = split_sets(&message[20..]);
+        assert_eq!(sets.iter().map(|s| s.0).collect::<Vec<u16>>(), vec![V9_TEMPLATE_SET_ID, V4_TEMPLATE_ID]);
+
+        let templates = templates(sets[0].1);
+        assert_eq!(templates.iter().map(|t| t.0).collect::<Vec<u16>>(), vec![V4_TEMPLATE_ID, V6_TEMPLATE_ID]);
+        assert!(templates[0].1.contains(&(FIRST_SWITCHED, 4)));
+        assert!(templates[1].1.contains(&(IPV6_SRC_ADDR, 16)));
+
+        let record = record(sets[1].1, &templates[0].1);
+        assert_eq!(u64_at(value(&record, IN_BYTES), 0), 1500);
+        assert_eq!(u64_at(value(&record, IN_PKTS), 0), 3);
+        assert_eq!(value(&record, PROTOCOL), &[ICMP]);
+        assert_eq!(value(&record, IPV4_SRC_ADDR), &[10, 0, 0, 1]);
+        assert_eq!(value(&record, IPV4_DST_ADDR), &[10, 0, 0, 2]);
+
+        // ICMP type and code are given as destination port
+        assert_eq!(value(&record, L4_SRC_PORT), &[0, 0]);
+        assert_eq!(value(&record, L4_DST_PORT), &[8, 0]);
+
+        // Templates are only sent again after a while, and sequence numbers count messages
+        exporter.export(&[detail("10.0.0.1", "10.0.0.2", "1000", "80", "TCP")]).unwrap();
+        let message = receive(&socket);
+        assert_eq!((u16_at(&message, 2), u32_at(&message, 12)), (1, 1));
+        assert_eq!(split_sets(&message[20..])[0].0, V4_TEMPLATE_ID);
+    }
+
+    #[test]
+    fn ipfix_messages_carry_their_length_and_ipv6_flows() {
+        let (socket, address) = collector();
+        let mut exporter = FlowExporter::new(&address, FlowVersion::Ipfix).unwrap();
+
+        // Mixed families are exported as IPv6 flows, with an IPv4-mapped address
+        exporter.export(&[detail("10.0.0.1", "2001:db8::2", "5353", "53", "UDP")]).unwrap();
+        let message = receive(&socket);
+
+        assert_eq!(u16_at(&message, 0), 10);
+        assert_eq!(u16_at(&message, 2) as usize, message.len());
+
+        let sets = split_sets(&message[16..]);
+        assert_eq!(sets.iter().map(|s| s.0).collect::<Vec<u16>>(), vec![IPFIX_TEMPLATE_SET_ID, V6_TEMPLATE_ID]);
+
+        let templates = templates(sets[0].1);
+        let record = record(sets[1].1, &templates[1].1);
+        assert_eq!(value(&record, IPV6_SRC_ADDR), &"::ffff:10.0.0.1".parse::<Ipv6Addr>().unwrap().octets());
+        assert_eq!(value(&record, IPV6_DST_ADDR), &"2001:db8::2".parse::<Ipv6Addr>().unwrap().octets());
+        assert_eq!(value(&record, PROTOCOL), &[17]);
+        assert_eq!((u16_at(value(&record, L4_SRC_PORT), 0), u16_at(value(&record, L4_DST_PORT), 0)), (5353, 53));
+        assert_eq!(u32_at(value(&record, FLOW_START_SECONDS), 0), 1_667_811_600);
+        assert_eq!(u32_at(value(&record, FLOW_END_SECONDS), 0), 1_667_811_660);
+    }
+
+    #[test]
+    fn aggregated_records_have_no_protocol() {
+        let mut flow = detail("10.0.0.0/24", "", "", "", "TCP");
+        flow.protocols.insert(String::from("UDP"));
+
+        let flow = Flow::from_detail(&flow);
+        assert_eq!(flow.protocol, 0);
+        assert_eq!(flow.src_ip, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)));
+        assert_eq!(flow.dst_ip, IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        assert_eq!((flow.src_port, flow.dst_port), (0, 0));
+    }
+}
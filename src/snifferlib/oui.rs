@@ -0,0 +1,193 @@
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, fs, io::Error, path::Path};
+
+// places where OUI databases are commonly installed (by Wireshark and by the ieee-data package)
+const DEFAULT_PATHS: [&str; 5] = ["/usr/share/wireshark/manuf", "/usr/local/share/wireshark/manuf",
+                                      "/usr/share/ieee-data/oui.txt", "/usr/share/misc/oui.txt",
+                                      "/opt/homebrew/share/wireshark/manuf"];
+
+// number of bits of a MAC address
+const MAC_BITS: u32 = 48;
+
+/// Vendors of network interfaces, by prefix of their MAC addresses. Besides the usual 24 bit
+/// OUIs, longer prefixes (e.g. /28 and /36 blocks) are handled, the longest match winning.
+pub struct OuiDatabase {
+    path: String,
+    prefixes: BTreeMap<u32, HashMap<u64, String>>
+}
+
+impl OuiDatabase {
+    /// Loads the database from the given file, either in the format of the Wireshark manuf file
+    /// (prefix, short name and optional long name separated by tabs) or in the format of the
+    /// IEEE oui.txt registry ("00-00-0C   (hex)   Cisco Systems, Inc")
+    pub fn load(path: &str) -> Result<OuiDatabase, Error> {
+        let content = fs::read(path)?;
+        let mut database = OuiDatabase { path: String::from(path), prefixes: BTreeMap::new() };
+
+        for line in String::from_utf8_lossy(&content).lines() {
+            let entry = if line.contains("(hex)") {
+                line.split_once("(hex)").map(|(prefix, name)| (prefix.trim(), name.trim()))
+            } else if line.starts_with('#') {
+                None
+            } else {
+                manuf_entry(line)
+            };
+
+            if let Some((bits, prefix, name)) = entry.and_then(|(p, n)| parse_prefix(p).map(|(b, p)| (b, p, n))) {
+                if !name.is_empty() {
+                    database.prefixes.entry(bits).or_default().insert(prefix, String::from(name));
+                }
+            }
+        }
+
+        if database.is_empty() {
+            return Err(Error::other("No vendor found in the file."));
+        }
+
+        Ok(database)
+    }
+
+    /// Loads the database from the first of the usual places holding one
+    pub fn find() -> Option<OuiDatabase> {
+        DEFAULT_PATHS.iter()
+                .filter(|p| Path::new(p).exists())
+                .find_map(|p| OuiDatabase::load(p).ok())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+
+    pub fn description(&self) -> String {
+        let count: usize = self.prefixes.values().map(|p| p.len()).sum();
+        format!("{} ({} prefixes)", self.path, count)
+    }
+
+    /// Returns the vendor of the given MAC address (e.g. "00:1b:c5:00:01:02"), if known
+    pub fn vendor(&self, mac: &str) -> Option<&str> {
+        let address = match parse_hex(mac)? {
+            (12, address) => address,
+            _ => { return None; }
+        };
+
+        self.prefixes.iter().rev()
+                .find_map(|(bits, prefixes)| prefixes.get(&(address >> (MAC_BITS - bits))))
+                .map(|v| v.as_str())
+    }
+
+    /// Returns the distinct vendors of the given MAC addresses. Unknown vendors are left out.
+    pub fn vendors(&self, macs: &BTreeSet<String>) -> BTreeSet<String> {
+        macs.iter().filter_map(|m| self.vendor(m)).map(String::from).collect()
+    }
+}
+
+// Lines of the manuf file hold the prefix, the short name and, in recent versions, the long name,
+// separated by tabs. Older versions give the long name as a comment, after the short one.
+fn manuf_entry(line: &str) -> Option<(&str, &str)> {
+    let mut fields = line.split('\t').map(|f| f.trim()).filter(|f| !f.is_empty());
+    let prefix = fields.next()?;
+    let short = fields.next()?;
+
+    let name = match (fields.next(), short.split_once('#')) {
+        (Some(long), _) => long.trim_start_matches('#').trim(),
+        (None, Some((short, long))) => if long.trim().is_empty() { short.trim() } else { long.trim() },
+        (None, None) => short
+    };
+
+    Some((prefix, name))
+}
+
+// Parses a prefix such as "00:00:0C", "00-00-0C" or "00:1B:C5:00:00:00/36", returning
+// its length in bits and its value
+fn parse_prefix(prefix: &str) -> Option<(u32, u64)> {
+    let (address, mask) = match prefix.split_once('/') {
+        Some((a, m)) => (a, Some(m.parse::<u32>().ok()?)),
+        None => (prefix, None)
+    };
+
+    let (digits, value) = parse_hex(address)?;
+    let bits = mask.unwrap_or(digits * 4);
+    if digits % 2 != 0 || bits == 0 || bits > digits * 4 {
+        return None;
+    }
+
+    Some((bits, value >> (digits * 4 - bits)))
+}
+
+// Parses the hexadecimal digits of an address, ignoring separators, returning their number and value
+fn parse_hex(address: &str) -> Option<(u32, u64)> {
+    let digits: String = address.chars().filter(|c| !matches!(c, ':' | '-' | '.')).collect();
+    if digits.is_empty() || digits.len() > 12 {
+        return None;
+    }
+
+    u64::from_str_radix(&digits, 16).ok().map(|v| (digits.len() as u32, v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, content: &str) -> Result<OuiDatabase, Error> {
+        let path = std::env::temp_dir().join(format!("rsniffer-oui-{}-{}", std::process::id(), name));
+        let path = path.to_str().unwrap();
+        fs::write(path, content).unwrap();
+
+        let database = OuiDatabase::load(path);
+        fs::remove_file(path).ok();
+        database
+    }
+
+    #[test]
+    fn manuf_entries_are_parsed() {
+        assert_eq!(manuf_entry("00:00:0C\tCisco\tCisco Systems, Inc"), Some(("00:00:0C", "Cisco Systems, Inc")));
+        assert_eq!(manuf_entry("00:00:0C\tCisco  # Cisco Systems, Inc"), Some(("00:00:0C", "Cisco Systems, Inc")));
+        assert_eq!(manuf_entry("00:00:0C\tCisco"), Some(("00:00:0C", "Cisco")));
+        assert_eq!(manuf_entry("00:00:0C"), None);
+    }
+
+    #[test]
+    fn prefixes_are_parsed() {
+        assert_eq!(parse_prefix("00:00:0C"), Some((24, 0x00000c)));
+        assert_eq!(parse_prefix("00-1B-C5"), Some((24, 0x001bc5)));
+        assert_eq!(parse_prefix("00:1B:C5:00:10:00/36"), Some((36, 0x001bc5001)));
+        assert_eq!(parse_prefix("00:1B:C5:00:00:00/0"), None);
+        assert_eq!(parse_prefix("00:1B:C5/28"), None);
+        assert_eq!(parse_prefix("00:1B:C"), None);
+        assert_eq!(parse_prefix("vendor"), None);
+    }
+
+    #[test]
+    fn longest_prefix_wins() {
+        let database = load("manuf", "# Wireshark manuf file\n\
+                                      00:00:0C\tCisco\tCisco Systems, Inc\n\
+                                      00:1B:C5\tIeeeRegi\tIEEE Registration Authority\n\
+                                      00:1B:C5:00:10:00/36\tExample\tExample Ltd\n").unwrap();
+
+        assert_eq!(database.vendor("00:00:0c:12:34:56"), Some("Cisco Systems, Inc"));
+        assert_eq!(database.vendor("00:1b:c5:00:10:ff"), Some("Example Ltd"));
+        assert_eq!(database.vendor("00:1b:c5:00:20:ff"), Some("IEEE Registration Authority"));
+        assert_eq!(database.vendor("02:00:00:00:00:01"), None);
+        assert_eq!(database.vendor("00:00:0c"), None);
+
+        let macs = BTreeSet::from([String::from("00:00:0c:00:00:01"), String::from("00:00:0c:00:00:02"),
+                                   String::from("02:00:00:00:00:01")]);
+        assert_eq!(database.vendors(&macs), BTreeSet::from([String::from("Cisco Systems, Inc")]));
+        assert!(database.description().ends_with("(3 prefixes)"));
+    }
+
+    #[test]
+    fn ieee_registry_is_parsed() {
+        let database = load("oui.txt", "OUI/MA-L                                                    Organization\n\
+                                        00-00-0C   (hex)\t\tCisco Systems, Inc\n\
+                                        00000C     (base 16)\t\tCisco Systems, Inc\n\
+                                        \t\t\t\t170 West Tasman Drive\n").unwrap();
+
+        assert_eq!(database.vendor("00-00-0C-12-34-56"), Some("Cisco Systems, Inc"));
+        assert!(database.description().ends_with("(1 prefixes)"));
+    }
+
+    #[test]
+    fn files_without_vendors_are_rejected() {
+        assert!(load("empty", "# nothing but comments\n\nnot a prefix\tvendor\n").is_err());
+    }
+}
@@ -0,0 +1,242 @@
+use super::aggregation::Dimension;
+use super::diff::DiffField;
+use super::field::Field;
+use super::report::{TrafficDetail, format_bytes};
+use super::layer2::Layer2Traffic;
+use chrono::{DateTime, Local};
+use std::io::{Error, Write};
+
+mod text;
+mod csv;
+mod json;
+mod html;
+mod markdown;
+
+/// Format used to write the report
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Csv,
+    Json,
+    Ndjson,
+    Html,
+    Markdown
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name.trim() {
+            "text" => Some(OutputFormat::Text),
+            "csv" => Some(OutputFormat::Csv),
+            "json" => Some(OutputFormat::Json),
+            "ndjson" => Some(OutputFormat::Ndjson),
+            "html" => Some(OutputFormat::Html),
+            "markdown" | "md" => Some(OutputFormat::Markdown),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Html => "html",
+            OutputFormat::Markdown => "markdown"
+        }
+    }
+
+    /// Tells whether the format is meant to be read by programs rather than by humans
+    pub fn machine_readable(&self) -> bool {
+        matches!(self, OutputFormat::Csv | OutputFormat::Json | OutputFormat::Ndjson)
+    }
+
+    /// Tells whether each snapshot is appended to the destination, rather than replacing its content
+    pub fn appends(&self) -> bool {
+        *self == OutputFormat::Ndjson
+    }
+}
+
+/// A column of the report, either identifying a record or holding one of its fields.
+/// Comparisons add columns describing the changes, which records alone have no value for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    Key(Dimension),
+    Field(Field),
+    Diff(DiffField)
+}
+
+impl Column {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Column::Key(dim) => dim.title(),
+            Column::Field(field) => field.title(),
+            Column::Diff(field) => field.title()
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::Key(dim) => dim.name(),
+            Column::Field(field) => field.name(),
+            Column::Diff(field) => field.name()
+        }
+    }
+
+    /// Tells whether the raw value of this column is a number
+    pub fn numeric(&self) -> bool {
+        match self {
+            Column::Key(_) => false,
+            Column::Field(field) => field.numeric(),
+            Column::Diff(field) => field.numeric()
+        }
+    }
+
+    /// Returns the value of this column as printed in human readable reports
+    pub fn value(&self, detail: &TrafficDetail, elapsed: f64) -> String {
+        match self {
+            Column::Key(dim) => dim.value(detail),
+            Column::Field(field) => field.value(detail, elapsed),
+            Column::Diff(_) => String::new()
+        }
+    }
+
+    /// Returns the value of this column as written in machine readable reports
+    pub fn raw(&self, detail: &TrafficDetail, elapsed: f64) -> String {
+        match self {
+            Column::Key(dim) => dim.value(detail),
+            Column::Field(field) => field.raw(detail, elapsed),
+            Column::Diff(_) => String::new()
+        }
+    }
+}
+
+/// A row of the table of a report: a record or a summary of records, along with the values
+/// of its own, if any (e.g. the changes listed by a comparison)
+pub trait Row {
+    fn detail(&self) -> &TrafficDetail;
+
+    /// Returns the value of the given column as printed in human readable reports
+    fn value(&self, column: &Column, elapsed: f64) -> String {
+        column.value(self.detail(), elapsed)
+    }
+
+    /// Returns the value of the given column as written in machine readable reports
+    fn raw(&self, column: &Column, elapsed: f64) -> String {
+        column.raw(self.detail(), elapsed)
+    }
+}
+
+impl Row for TrafficDetail {
+    fn detail(&self) -> &TrafficDetail {
+        self
+    }
+}
+
+/// An entry of the metadata describing how the report has been generated
+pub struct MetaEntry {
+    pub name: &'static str,
+    pub label: &'static str,
+    pub value: String
+}
+
+impl MetaEntry {
+    pub fn new(name: &'static str, label: &'static str, value: String) -> Self {
+        Self { name, label, value }
+    }
+}
+
+/// Consistent view of the report at a given time. Its rows are records, unless the report
+/// is the result of a comparison.
+pub struct Snapshot<R = TrafficDetail> {
+    pub metadata: Vec<MetaEntry>,
+    pub timestamp: DateTime<Local>,
+    pub columns: Vec<Column>,
+    /// Number of leading columns identifying a record
+    pub nkeys: usize,
+    /// Whether the records hold the counters of the last interval only (delta mode), rather than
+    /// the ones cumulated since the start of the capture
+    pub delta: bool,
+    pub records: Vec<R>,
+    /// Records updated during the last interval, whatever the limit and including the ones closed (expired
+    /// or evicted) since (in delta mode, all the records listed before applying the limit), so that sinks
+    /// storing them lose none
+    pub active: Vec<TrafficDetail>,
+    /// Number of records beyond the limit, summed up in a single detail
+    pub others: Option<(usize, R)>,
+    /// Number of records and their totals
+    pub total: (usize, R),
+    /// Frames not carrying IP traffic, listed in a section of their own
+    pub layer2: Layer2Traffic,
+    /// Seconds elapsed since the start of the current interval, over which current rates are computed
+    pub elapsed: f64
+}
+
+impl<R: Row> Snapshot<R> {
+    /// Returns the key identifying the given row within the capture: the values of its key columns
+    /// and the time of its first packet, as a record expired and then seen again is a new one
+    pub fn key(&self, row: &R) -> String {
+        let mut values: Vec<String> = self.columns.iter().take(self.nkeys).map(|c| row.raw(c, 0.0)).collect();
+        values.push(row.detail().first_ts.to_string());
+        values.join("|")
+    }
+}
+
+/// Writes the snapshot to the given destination, in the given format.
+/// If metadata is false, machine readable formats leave it out (e.g. to write it elsewhere),
+/// while human readable ones always include it.
+pub fn write<R: Row>(snapshot: &Snapshot<R>, format: OutputFormat, metadata: bool, out: &mut dyn Write) -> Result<(), Error> {
+    match format {
+        OutputFormat::Text => text::write(snapshot, out),
+        OutputFormat::Csv => csv::write(snapshot, metadata, out),
+        OutputFormat::Json => json::write(snapshot, metadata, out),
+        OutputFormat::Ndjson => json::write_lines(snapshot, metadata, out),
+        OutputFormat::Html => html::write(snapshot, out),
+        OutputFormat::Markdown => markdown::write(snapshot, out)
+    }
+}
+
+/// Writes the metadata of the snapshot to the given destination, as a CSV
+/// table with a name and a value column
+pub fn write_metadata<R>(snapshot: &Snapshot<R>, out: &mut dyn Write) -> Result<(), Error> {
+    csv::write_metadata(snapshot, out)
+}
+
+/// A table of the section listing the traffic not carrying IP, as printed in human readable reports
+pub struct Layer2Table {
+    pub title: &'static str,
+    pub columns: Vec<&'static str>,
+    pub rows: Vec<Vec<String>>
+}
+
+/// Returns the tables listing the traffic not carrying IP, by EtherType and by ARP message.
+/// There are none when the capture held IP traffic only.
+pub fn layer2_tables(layer2: &Layer2Traffic) -> Vec<Layer2Table> {
+    if layer2.is_empty() {
+        return vec![];
+    }
+
+    let mut tables = vec![Layer2Table {
+        title: "Non-IP traffic by EtherType",
+        columns: vec!["ETHERTYPE", "PROTOCOL", "PACKETS", "BYTES"],
+        rows: layer2.ethertypes().iter()
+                .map(|(ethertype, protocol, packets, bytes)| vec![String::from(*ethertype), String::from(*protocol),
+                        packets.to_string(), format_bytes(*bytes)])
+                .collect()
+    }];
+
+    let arp = layer2.arp();
+    if !arp.is_empty() {
+        tables.push(Layer2Table {
+            title: "ARP messages",
+            columns: vec!["OPERATION", "SENDER MAC", "SENDER IP", "TARGET MAC", "TARGET IP", "PACKETS", "BYTES"],
+            rows: arp.iter()
+                    .map(|(m, packets, bytes)| vec![m.operation.name(), String::from(&m.sender_mac), String::from(&m.sender_ip),
+                            String::from(&m.target_mac), String::from(&m.target_ip), packets.to_string(), format_bytes(*bytes)])
+                    .collect()
+        });
+    }
+
+    tables
+}
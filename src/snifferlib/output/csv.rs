@@ -0,0 +1,84 @@
+use super::{Row, Snapshot};
+use std::io::{Error, Write};
+
+/// Writes the snapshot as a CSV table with a header row, one row per record.
+/// Metadata, if requested, is written first as comment lines starting with '#'.
+/// Traffic not carrying IP does not fit in the table and is left out.
+pub fn write<R: Row>(snapshot: &Snapshot<R>, metadata: bool, out: &mut dyn Write) -> Result<(), Error> {
+    if metadata {
+        for entry in &snapshot.metadata {
+            writeln!(out, "# {}: {}", entry.name, entry.value)?;
+        }
+
+        writeln!(out, "# last_update: {}", snapshot.timestamp.to_rfc3339())?;
+    }
+
+    let mut writer = ::csv::Writer::from_writer(out);
+    writer.write_record(snapshot.columns.iter().map(|c| c.name()))?;
+
+    for detail in &snapshot.records {
+        writer.write_record(snapshot.columns.iter().map(|c| detail.raw(c, snapshot.elapsed)))?;
+    }
+
+    writer.flush()
+}
+
+/// Writes the metadata of the snapshot as a CSV table with a name and a value column
+pub fn write_metadata<R>(snapshot: &Snapshot<R>, out: &mut dyn Write) -> Result<(), Error> {
+    let mut writer = ::csv::Writer::from_writer(out);
+    writer.write_record(["name", "value"])?;
+
+    for entry in &snapshot.metadata {
+        writer.write_record([entry.name, &entry.value])?;
+    }
+
+    writer.write_record(["last_update", &snapshot.timestamp.to_rfc3339()])?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::Field;
+    use crate::report::{TrafficDetail, TrafficReport, format_iso_ts};
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn records_are_written_with_their_raw_values() {
+        let mut detail = TrafficDetail::new();
+        detail.src_ip = String::from("10.0.0.1");
+        detail.dst_ip = String::from("10.0.0.2");
+        detail.src_port = String::from("1000");
+        detail.dst_port = String::from("80");
+        detail.protocols = BTreeSet::from([String::from("TCP")]);
+        detail.interface = BTreeSet::from([String::from("eth0"), String::from("wlan0")]);
+        detail.payload_bytes = 123_456;
+        detail.first_ts = 1_667_811_600;
+        detail.last_ts = 1_667_811_660;
+
+        let mut report = TrafficReport::new();
+        assert!(report.set_columns(vec![Field::Interface, Field::Duration]));
+        report.new_detail(detail);
+
+        let mut content = vec![];
+        write(&report.snapshot(), true, &mut content).unwrap();
+        let content = String::from_utf8(content).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+
+        // Metadata comes first, as comments
+        let header = lines.iter().position(|l| !l.starts_with("# ")).unwrap();
+        assert!(lines[..header].iter().any(|l| l.starts_with("# filter: ")));
+        assert!(lines[header - 1].starts_with("# last_update: "));
+
+        // Byte counts are not rounded, and lists holding commas are quoted
+        assert_eq!(lines[header..], [
+            "src_ip,dst_ip,src_port,dst_port,protocols,bytes,packets,first_ts,last_ts,interface,duration",
+            &format!("10.0.0.1,10.0.0.2,1000,80,TCP,123456,1,{},{},\"eth0, wlan0\",60", format_iso_ts(1_667_811_600),
+                    format_iso_ts(1_667_811_660))
+        ]);
+
+        let mut content = vec![];
+        write(&report.snapshot(), false, &mut content).unwrap();
+        assert!(content.starts_with(b"src_ip,"));
+    }
+}
@@ -0,0 +1,254 @@
+use super::{Row, Snapshot, layer2_tables};
+use super::super::report::{format_bytes, format_list};
+use std::io::{Error, Write};
+
+// maximum number of bars shown in a chart
+const CHART_BARS: usize = 10;
+
+// size (in pixels) of the elements of a chart
+const CHART_WIDTH: usize = 640;
+const BAR_HEIGHT: usize = 22;
+const LABEL_WIDTH: usize = 220;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.5em; }
+h2 { font-size: 1.2em; margin-top: 1.5em; }
+ul.metadata { list-style: none; padding: 0; }
+ul.metadata li { margin: 0.2em 0; }
+ul.metadata b { display: inline-block; min-width: 12em; }
+.charts { display: flex; flex-wrap: wrap; gap: 2em; }
+input#filter { margin: 0.5em 0; padding: 0.3em; width: 24em; }
+table { border-collapse: collapse; font-size: 0.9em; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; white-space: nowrap; }
+th { background: #eee; cursor: pointer; user-select: none; }
+tr.summary td { font-weight: bold; background: #f7f7f7; }
+";
+
+const SCRIPT: &str = "
+function filterRows(text) {
+    text = text.toLowerCase();
+    document.querySelectorAll('#records tbody tr').forEach(function (row) {
+        row.style.display = row.textContent.toLowerCase().indexOf(text) >= 0 ? '' : 'none';
+    });
+}
+
+function sortRows(index) {
+    var th = document.querySelectorAll('#records thead th')[index];
+    var ascending = th.dataset.order !== 'asc';
+    th.dataset.order = ascending ? 'asc' : 'desc';
+
+    var tbody = document.querySelector('#records tbody');
+    var rows = Array.prototype.slice.call(tbody.rows);
+    rows.sort(function (a, b) {
+        var x = a.cells[index].dataset.value, y = b.cells[index].dataset.value;
+        var nx = parseFloat(x), ny = parseFloat(y);
+        var cmp = (!isNaN(nx) && !isNaN(ny) && String(nx) === x && String(ny) === y) ? nx - ny : x.localeCompare(y);
+        return ascending ? cmp : -cmp;
+    });
+    rows.forEach(function (row) { tbody.appendChild(row); });
+}
+";
+
+/// Writes the snapshot as a self-contained HTML page, with the configuration of the capture,
+/// charts of the top talkers and of the protocols, a sortable and filterable table and, if any
+/// traffic not carrying IP has been seen, the tables listing it
+pub fn write<R: Row>(snapshot: &Snapshot<R>, out: &mut dyn Write) -> Result<(), Error> {
+    writeln!(out, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>rsniffer report</title>\n<style>{}</style>\n<script>{}</script>\n</head>\n<body>", STYLE, SCRIPT)?;
+    writeln!(out, "<h1>rsniffer report</h1>")?;
+
+    // Configuration
+    writeln!(out, "<ul class=\"metadata\">")?;
+    for entry in &snapshot.metadata {
+        writeln!(out, "<li><b>{}</b>{}</li>", escape(entry.label), escape(&entry.value))?;
+    }
+    writeln!(out, "<li><b>Last update</b>{}</li>\n</ul>", snapshot.timestamp.format("%Y-%m-%d %H:%M:%S %Z"))?;
+
+    // Charts
+    if let Some(first) = snapshot.columns.first() {
+        let talkers = totals(&snapshot.records, |d| d.value(first, snapshot.elapsed));
+        let protocols = totals(&snapshot.records, |d| format_list(&d.detail().protocols));
+
+        writeln!(out, "<div class=\"charts\">")?;
+        writeln!(out, "<div><h2>Top talkers by {}</h2>", escape(first.title()))?;
+        write_chart(&talkers, out)?;
+        writeln!(out, "</div>\n<div><h2>Protocols</h2>")?;
+        write_chart(&protocols, out)?;
+        writeln!(out, "</div>\n</div>")?;
+    }
+
+    // Records
+    writeln!(out, "<h2>Records</h2>")?;
+    writeln!(out, "<input id=\"filter\" type=\"search\" placeholder=\"Filter records...\" oninput=\"filterRows(this.value)\">")?;
+    writeln!(out, "<table id=\"records\">\n<thead>\n<tr>")?;
+    for (i, column) in snapshot.columns.iter().enumerate() {
+        writeln!(out, "<th onclick=\"sortRows({})\">{}</th>", i, escape(column.title()))?;
+    }
+    writeln!(out, "</tr>\n</thead>\n<tbody>")?;
+
+    for detail in &snapshot.records {
+        writeln!(out, "<tr>")?;
+        for column in &snapshot.columns {
+            writeln!(out, "<td data-value=\"{}\">{}</td>", escape(&detail.raw(column, snapshot.elapsed)),
+                    escape(&detail.value(column, snapshot.elapsed)))?;
+        }
+        writeln!(out, "</tr>")?;
+    }
+    writeln!(out, "</tbody>\n<tfoot>")?;
+
+    // Summaries are kept out of the body, so that they are neither sorted nor filtered
+    if let Some((nothers, others)) = &snapshot.others {
+        write_summary(snapshot, &format!("OTHERS ({} records)", nothers), others, out)?;
+    }
+
+    let (ntotal, total) = &snapshot.total;
+    write_summary(snapshot, &format!("TOTAL ({} records)", ntotal), total, out)?;
+
+    writeln!(out, "</tfoot>\n</table>")?;
+
+    for layer2 in layer2_tables(&snapshot.layer2) {
+        writeln!(out, "<h2>{}</h2>\n<table>\n<thead>\n<tr>", escape(layer2.title))?;
+        for column in &layer2.columns {
+            writeln!(out, "<th>{}</th>", escape(column))?;
+        }
+        writeln!(out, "</tr>\n</thead>\n<tbody>")?;
+
+        for row in &layer2.rows {
+            let cells: Vec<String> = row.iter().map(|c| format!("<td>{}</td>", escape(c))).collect();
+            writeln!(out, "<tr>{}</tr>", cells.concat())?;
+        }
+        writeln!(out, "</tbody>\n</table>")?;
+    }
+
+    writeln!(out, "</body>\n</html>")
+}
+
+fn write_summary<R: Row>(snapshot: &Snapshot<R>, label: &str, row: &R, out: &mut dyn Write) -> Result<(), Error> {
+    writeln!(out, "<tr class=\"summary\">\n<td colspan=\"{}\">{}</td>", snapshot.nkeys, escape(label))?;
+    for column in snapshot.columns.iter().skip(snapshot.nkeys) {
+        writeln!(out, "<td>{}</td>", escape(&row.value(column, snapshot.elapsed)))?;
+    }
+    writeln!(out, "</tr>")
+}
+
+// Draws an horizontal bar chart of the given (label, bytes) pairs
+fn write_chart(bars: &[(String, usize)], out: &mut dyn Write) -> Result<(), Error> {
+    let max = bars.iter().map(|b| b.1).max().unwrap_or(0).max(1);
+    let bar_space = CHART_WIDTH - LABEL_WIDTH - 80;
+
+    writeln!(out, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">",
+            CHART_WIDTH, (bars.len().max(1)) * BAR_HEIGHT)?;
+
+    if bars.is_empty() {
+        writeln!(out, "<text x=\"0\" y=\"15\" font-size=\"12\">No traffic</text>")?;
+    }
+
+    for (i, (label, bytes)) in bars.iter().enumerate() {
+        let y = i * BAR_HEIGHT;
+        let width = (bytes * bar_space / max).max(1);
+        writeln!(out, "<text x=\"{}\" y=\"{}\" font-size=\"12\" text-anchor=\"end\">{}</text>",
+                LABEL_WIDTH - 6, y + 15, escape(label))?;
+        writeln!(out, "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#4a90d9\"/>",
+                LABEL_WIDTH, y + 3, width, BAR_HEIGHT - 6)?;
+        writeln!(out, "<text x=\"{}\" y=\"{}\" font-size=\"12\">{}</text>",
+                LABEL_WIDTH + width + 6, y + 15, escape(format_bytes(*bytes).trim()))?;
+    }
+
+    writeln!(out, "</svg>")
+}
+
+// Sums the bytes of the records by the given label, keeping the greatest ones only
+fn totals<R: Row>(records: &[R], label: impl Fn(&R) -> String) -> Vec<(String, usize)> {
+    let mut totals: Vec<(String, usize)> = vec![];
+
+    for row in records {
+        let l = label(row);
+        match totals.iter_mut().find(|t| t.0 == l) {
+            Some(t) => t.1 += row.detail().bytes,
+            None => totals.push((l, row.detail().bytes))
+        }
+    }
+
+    totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    totals.truncate(CHART_BARS);
+    totals
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::Field;
+    use crate::report::{TrafficDetail, TrafficReport};
+    use std::collections::BTreeSet;
+
+    fn detail(src_ip: &str, protocol: &str, payload_bytes: usize) -> TrafficDetail {
+        let mut detail = TrafficDetail::new();
+        detail.src_ip = String::from(src_ip);
+        detail.dst_ip = String::from("10.0.0.2");
+        detail.src_port = String::from("1000");
+        detail.dst_port = String::from("80");
+        detail.protocols = BTreeSet::from([String::from(protocol)]);
+        detail.interface = BTreeSet::from([String::from("<eth0>")]);
+        detail.payload_bytes = payload_bytes;
+        detail
+    }
+
+    fn html(report: &TrafficReport) -> String {
+        let mut content = vec![];
+        write(&report.snapshot(), &mut content).unwrap();
+        String::from_utf8(content).unwrap()
+    }
+
+    #[test]
+    fn the_page_is_self_contained() {
+        let mut report = TrafficReport::new();
+        assert!(report.set_columns(vec![Field::Interface]));
+        report.set_limit(Some(2));
+        report.new_detail(detail("10.0.0.1", "TCP", 3000));
+        report.new_detail(detail("10.0.0.3", "UDP", 2000));
+        report.new_detail(detail("10.0.0.4", "UDP", 1000));
+        let page = html(&report);
+
+        assert!(page.starts_with("<!DOCTYPE html>") && page.trim_end().ends_with("</html>"));
+        assert!(!page.contains("<link") && !page.contains(" src="));
+
+        // Values are escaped, and the raw ones are kept for sorting
+        assert!(page.contains("<td data-value=\"&lt;eth0&gt;\">&lt;eth0&gt;</td>"));
+        assert!(page.contains("<td data-value=\"3000\">"));
+
+        // Summaries are kept out of the sortable rows
+        let (body, foot) = page.split_once("<tfoot>").unwrap();
+        assert_eq!(body.matches("<tr>").count() - 1, 2);
+        assert!(foot.contains("OTHERS (1 records)") && foot.contains("TOTAL (3 records)"));
+
+        // Charts of the listed records
+        assert_eq!(page.matches("<svg").count(), 2);
+        assert!(page.contains(">10.0.0.1</text>") && page.contains(">UDP</text>"));
+        assert!(!page.contains("Non-IP traffic"));
+    }
+
+    #[test]
+    fn charts_keep_the_greatest_bars() {
+        let records: Vec<TrafficDetail> = (0..15).map(|i| {
+            let mut d = detail(&format!("10.0.1.{}", i), "TCP", 0);
+            d.bytes = i * 10;
+            d
+        }).collect();
+
+        let bars = totals(&records, |d| String::from(&d.src_ip));
+        assert_eq!(bars.len(), CHART_BARS);
+        assert_eq!(bars[0], (String::from("10.0.1.14"), 140));
+
+        let bars = totals(&records, |d| format_list(&d.protocols));
+        assert_eq!(bars, vec![(String::from("TCP"), 1050)]);
+    }
+}
@@ -0,0 +1,221 @@
+use super::{Column, Row, Snapshot};
+use super::super::layer2::{ArpMessage, Layer2Traffic};
+use serde_json::{json, Map, Value};
+use std::io::{Error, Write};
+
+/// Writes the snapshot as a single JSON document holding the metadata, the records,
+/// the others and total summaries and the traffic not carrying IP, if any
+pub fn write<R: Row>(snapshot: &Snapshot<R>, metadata: bool, out: &mut dyn Write) -> Result<(), Error> {
+    let mut document = Map::new();
+
+    if metadata {
+        document.insert(String::from("metadata"), metadata_object(snapshot));
+    }
+
+    let records: Vec<Value> = snapshot.records.iter().map(|d| record(snapshot, d)).collect();
+    document.insert(String::from("records"), Value::Array(records));
+
+    if let Some((nothers, others)) = &snapshot.others {
+        document.insert(String::from("others"), summary(snapshot, *nothers, others));
+    }
+
+    let (ntotal, total) = &snapshot.total;
+    document.insert(String::from("total"), summary(snapshot, *ntotal, total));
+
+    if !snapshot.layer2.is_empty() {
+        document.insert(String::from("layer2"), layer2_object(&snapshot.layer2));
+    }
+
+    serde_json::to_writer_pretty(&mut *out, &Value::Object(document))?;
+    writeln!(out)
+}
+
+/// Writes the snapshot as newline-delimited JSON, one record per line. Each line carries
+/// the timestamp of the snapshot and its type ("metadata", "record", or "ethertype" and "arp"
+/// for the traffic not carrying IP), so that the snapshots of several intervals can be appended
+/// to the same stream. Record lines also carry the key identifying the record within the capture.
+pub fn write_lines<R: Row>(snapshot: &Snapshot<R>, metadata: bool, out: &mut dyn Write) -> Result<(), Error> {
+    let timestamp = snapshot.timestamp.to_rfc3339();
+
+    if metadata {
+        let line = json!({ "type": "metadata", "timestamp": timestamp, "delta": snapshot.delta,
+                "metadata": metadata_object(snapshot) });
+        writeln!(out, "{}", line)?;
+    }
+
+    for detail in &snapshot.records {
+        let mut line = Map::new();
+        line.insert(String::from("type"), json!("record"));
+        line.insert(String::from("timestamp"), json!(timestamp));
+        line.insert(String::from("key"), json!(snapshot.key(detail)));
+
+        if let Value::Object(fields) = record(snapshot, detail) {
+            line.extend(fields);
+        }
+
+        writeln!(out, "{}", Value::Object(line))?;
+    }
+
+    for (ethertype, protocol, packets, bytes) in snapshot.layer2.ethertypes() {
+        let line = json!({ "type": "ethertype", "timestamp": timestamp, "ethertype": ethertype, "protocol": protocol,
+                "packets": packets, "bytes": bytes });
+        writeln!(out, "{}", line)?;
+    }
+
+    for (message, packets, bytes) in snapshot.layer2.arp() {
+        let mut line = Map::new();
+        line.insert(String::from("type"), json!("arp"));
+        line.insert(String::from("timestamp"), json!(timestamp));
+
+        if let Value::Object(fields) = arp_message(message, packets, bytes) {
+            line.extend(fields);
+        }
+
+        writeln!(out, "{}", Value::Object(line))?;
+    }
+
+    Ok(())
+}
+
+// Traffic not carrying IP, by EtherType and by ARP message
+fn layer2_object(layer2: &Layer2Traffic) -> Value {
+    let ethertypes: Vec<Value> = layer2.ethertypes().iter()
+            .map(|(ethertype, protocol, packets, bytes)| json!({ "ethertype": ethertype, "protocol": protocol,
+                    "packets": packets, "bytes": bytes }))
+            .collect();
+    let arp: Vec<Value> = layer2.arp().iter().map(|(message, packets, bytes)| arp_message(message, *packets, *bytes)).collect();
+
+    json!({ "ethertypes": ethertypes, "arp": arp })
+}
+
+fn arp_message(message: &ArpMessage, packets: usize, bytes: usize) -> Value {
+    json!({
+        "operation": message.operation.name(),
+        "sender_mac": message.sender_mac,
+        "sender_ip": message.sender_ip,
+        "target_mac": message.target_mac,
+        "target_ip": message.target_ip,
+        "packets": packets,
+        "bytes": bytes
+    })
+}
+
+pub fn metadata_object<R>(snapshot: &Snapshot<R>) -> Value {
+    let mut metadata = Map::new();
+    for entry in &snapshot.metadata {
+        metadata.insert(String::from(entry.name), json!(entry.value));
+    }
+
+    metadata.insert(String::from("last_update"), json!(snapshot.timestamp.to_rfc3339()));
+    Value::Object(metadata)
+}
+
+/// Returns the given record as a JSON object, with a member for each column
+pub fn record<R: Row>(snapshot: &Snapshot<R>, row: &R) -> Value {
+    let mut record = Map::new();
+    for column in &snapshot.columns {
+        record.insert(String::from(column.name()), value(column, row, snapshot.elapsed));
+    }
+
+    Value::Object(record)
+}
+
+// Summaries only hold the number of records they refer to and the counters
+fn summary<R: Row>(snapshot: &Snapshot<R>, nrecords: usize, row: &R) -> Value {
+    let mut summary = Map::new();
+    summary.insert(String::from("records"), json!(nrecords));
+
+    for column in snapshot.columns.iter().skip(snapshot.nkeys) {
+        summary.insert(String::from(column.name()), value(column, row, snapshot.elapsed));
+    }
+
+    Value::Object(summary)
+}
+
+// Numeric fields are written as JSON numbers, everything else as strings
+fn value<R: Row>(column: &Column, row: &R, elapsed: f64) -> Value {
+    let raw = row.raw(column, elapsed);
+
+    if !column.numeric() {
+        return Value::String(raw);
+    }
+
+    match serde_json::from_str::<serde_json::Number>(&raw) {
+        Ok(number) => Value::Number(number),
+        Err(_) => Value::String(raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::Field;
+    use crate::report::{TrafficDetail, TrafficReport};
+    use std::collections::BTreeSet;
+
+    fn report() -> TrafficReport {
+        let mut report = TrafficReport::new();
+        assert!(report.set_sorting(Some(String::from("bytes:desc"))));
+        assert!(report.set_columns(vec![Field::AvgBps]));
+        report.set_limit(Some(1));
+
+        for (src_port, payload_bytes) in [("1000", 300), ("2000", 100), ("3000", 200)] {
+            let mut detail = TrafficDetail::new();
+            detail.src_ip = String::from("10.0.0.1");
+            detail.dst_ip = String::from("10.0.0.2");
+            detail.src_port = String::from(src_port);
+            detail.dst_port = String::from("80");
+            detail.protocols = BTreeSet::from([String::from("TCP")]);
+            detail.payload_bytes = payload_bytes;
+            detail.first_ts = 1_667_811_600;
+            detail.last_ts = 1_667_811_610;
+            report.new_detail(detail);
+        }
+
+        report
+    }
+
+    #[test]
+    fn numbers_are_written_as_such() {
+        let mut content = vec![];
+        write(&report().snapshot(), true, &mut content).unwrap();
+        let document: Value = serde_json::from_slice(&content).unwrap();
+
+        assert!(document["metadata"]["last_update"].is_string());
+        assert!(document.get("layer2").is_none());
+
+        let records = document["records"].as_array().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["src_port"], json!("1000"));
+        assert_eq!(records[0]["bytes"], json!(300));
+        assert_eq!(records[0]["avg_bps"], json!(240.0));
+
+        // Summaries hold the counters only
+        assert_eq!(document["others"], json!({ "records": 2, "bytes": 300, "packets": 2,
+                "first_ts": records[0]["first_ts"], "last_ts": records[0]["last_ts"], "avg_bps": 240.0 }));
+        assert_eq!(document["total"]["records"], json!(3));
+        assert_eq!(document["total"]["bytes"], json!(600));
+
+        let mut content = vec![];
+        write(&report().snapshot(), false, &mut content).unwrap();
+        assert!(serde_json::from_slice::<Value>(&content).unwrap().get("metadata").is_none());
+    }
+
+    #[test]
+    fn lines_are_typed_and_keyed() {
+        let snapshot = report().snapshot();
+        let mut content = vec![];
+        write_lines(&snapshot, true, &mut content).unwrap();
+        write_lines(&snapshot, false, &mut content).unwrap();
+
+        let lines: Vec<Value> = content.split(|b| *b == b'\n').filter(|l| !l.is_empty())
+                .map(|l| serde_json::from_slice(l).unwrap()).collect();
+        let types: Vec<&str> = lines.iter().map(|l| l["type"].as_str().unwrap()).collect();
+        assert_eq!(types, vec!["metadata", "record", "record"]);
+
+        assert_eq!(lines[0]["delta"], json!(false));
+        assert_eq!(lines[1]["key"], json!("10.0.0.1|10.0.0.2|1000|80|TCP|1667811600"));
+        assert_eq!(lines[1]["timestamp"], lines[0]["timestamp"]);
+        assert_eq!(lines[1]["bytes"], json!(300));
+    }
+}
@@ -0,0 +1,90 @@
+use super::{Row, Snapshot, layer2_tables};
+use std::io::{Error, Write};
+
+/// Writes the snapshot as a bullet list of metadata followed by a GitHub-flavoured
+/// Markdown table, with columns padded so that the source is readable as well. Traffic not
+/// carrying IP, if any, is listed afterwards in tables of its own.
+pub fn write<R: Row>(snapshot: &Snapshot<R>, out: &mut dyn Write) -> Result<(), Error> {
+    writeln!(out, "This report was generated by rsniffer with the following configuration:\n")?;
+
+    for entry in &snapshot.metadata {
+        writeln!(out, "- **{}**: {}", entry.label, escape(&entry.value))?;
+    }
+
+    writeln!(out, "\nLast update: {}\n", snapshot.timestamp.format("%Y-%m-%d %H:%M:%S %Z"))?;
+
+    let mut rows: Vec<Vec<String>> = snapshot.records.iter()
+            .map(|d| snapshot.columns.iter().map(|c| escape(&d.value(c, snapshot.elapsed))).collect())
+            .collect();
+
+    if snapshot.total.0 > 0 {
+        // Records beyond the limit are summed up in a single row
+        if let Some((nothers, others)) = &snapshot.others {
+            rows.push(summary_row(snapshot, format!("**OTHERS ({} records)**", nothers), others));
+        }
+
+        let (ntotal, total) = &snapshot.total;
+        rows.push(summary_row(snapshot, format!("**TOTAL ({} records)**", ntotal), total));
+    } else {
+        rows.push(snapshot.columns.iter().map(|_| String::new()).collect());
+    }
+
+    let titles: Vec<String> = snapshot.columns.iter().map(|c| String::from(c.title())).collect();
+    write_table(&titles, &rows, out)?;
+
+    for layer2 in layer2_tables(&snapshot.layer2) {
+        writeln!(out, "\n### {}\n", layer2.title)?;
+
+        let titles: Vec<String> = layer2.columns.iter().map(|c| String::from(*c)).collect();
+        let rows: Vec<Vec<String>> = layer2.rows.iter().map(|r| r.iter().map(|c| escape(c)).collect()).collect();
+        write_table(&titles, &rows, out)?;
+    }
+
+    Ok(())
+}
+
+fn write_table(titles: &[String], rows: &[Vec<String>], out: &mut dyn Write) -> Result<(), Error> {
+    let mut widths: Vec<usize> = titles.iter().map(|t| t.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    write_row(titles, &widths, out)?;
+    let separators: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+    writeln!(out, "|-{}-|", separators.join("-|-"))?;
+
+    for row in rows {
+        write_row(row, &widths, out)?;
+    }
+
+    Ok(())
+}
+
+fn write_row(cells: &[String], widths: &[usize], out: &mut dyn Write) -> Result<(), Error> {
+    let padded: Vec<String> = cells.iter().zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+    writeln!(out, "| {} |", padded.join(" | "))
+}
+
+// Summary rows show their label in the first column, followed by the cumulated counters
+fn summary_row<R: Row>(snapshot: &Snapshot<R>, label: String, row: &R) -> Vec<String> {
+    let mut cells = vec![label];
+
+    for (i, column) in snapshot.columns.iter().enumerate().skip(1) {
+        if i < snapshot.nkeys {
+            cells.push(String::new());
+        } else {
+            cells.push(escape(&row.value(column, snapshot.elapsed)));
+        }
+    }
+
+    cells
+}
+
+// Pipes would end the cell, while newlines would end the row
+fn escape(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
@@ -0,0 +1,90 @@
+use super::{Row as ReportRow, Snapshot, layer2_tables};
+use prettytable::{Table, Row, Cell, format};
+use std::io::{Error, Write};
+
+/// Writes the snapshot as a textual header followed by an ASCII table and, if any
+/// traffic not carrying IP has been seen, by the tables listing it
+pub fn write<R: ReportRow>(snapshot: &Snapshot<R>, out: &mut dyn Write) -> Result<(), Error> {
+    write_header(snapshot, out)?;
+
+    let mut table = new_table();
+    table.set_titles(Row::new(snapshot.columns.iter().map(|c| Cell::new(c.title())).collect()));
+
+    if snapshot.total.0 > 0 {
+        for detail in &snapshot.records {
+            table.add_row(Row::new(snapshot.columns.iter()
+                    .map(|c| Cell::new(&detail.value(c, snapshot.elapsed))).collect()));
+        }
+
+        // Records beyond the limit are summed up in a single row
+        if let Some((nothers, others)) = &snapshot.others {
+            table.add_row(summary_row(snapshot, format!("OTHERS ({} records)", nothers), others));
+        }
+
+        let (ntotal, total) = &snapshot.total;
+        table.add_row(summary_row(snapshot, format!("TOTAL ({} records)", ntotal), total));
+    } else {
+        table.add_row(Row::new(snapshot.columns.iter().map(|_| Cell::new("")).collect()));
+    }
+
+    print(&table, out)?;
+
+    for layer2 in layer2_tables(&snapshot.layer2) {
+        writeln!(out, "\n{}:", layer2.title)?;
+
+        let mut table = new_table();
+        table.set_titles(Row::new(layer2.columns.iter().map(|c| Cell::new(c)).collect()));
+        for row in &layer2.rows {
+            table.add_row(Row::new(row.iter().map(|c| Cell::new(c)).collect()));
+        }
+        print(&table, out)?;
+    }
+
+    Ok(())
+}
+
+fn new_table() -> Table {
+    let mut table = Table::new();
+    let format = format::FormatBuilder::new()
+        .column_separator('|')
+        .borders('|')
+        .separators(&[format::LinePosition::Top, format::LinePosition::Bottom, format::LinePosition::Title],
+                    format::LineSeparator::new('-', '+', '+', '+'))
+        .padding(1, 1)
+        .build();
+    table.set_format(format);
+    table
+}
+
+fn print(table: &Table, out: &mut dyn Write) -> Result<(), Error> {
+    match table.print(out) {
+        Err(why) => Err(Error::other(format!("Couldn't print report table. {}", why))),
+        Ok(_lines) => Ok(())
+    }
+}
+
+fn write_header<R>(snapshot: &Snapshot<R>, out: &mut dyn Write) -> Result<(), Error> {
+    writeln!(out, "This report was generated by rsniffer with the following configuration:")?;
+
+    for entry in &snapshot.metadata {
+        writeln!(out, " -> {}: {}", entry.label, entry.value)?;
+    }
+
+    writeln!(out, "\nLast update: {}\n", snapshot.timestamp.format("%Y-%m-%d %H:%M:%S %Z"))?;
+    Ok(())
+}
+
+// Summary rows show their label in the first column, followed by the cumulated counters
+fn summary_row<R: ReportRow>(snapshot: &Snapshot<R>, label: String, row: &R) -> Row {
+    let mut cells = vec![Cell::new(&label)];
+
+    for (i, column) in snapshot.columns.iter().enumerate().skip(1) {
+        if i < snapshot.nkeys {
+            cells.push(Cell::new(""));
+        } else {
+            cells.push(Cell::new(&row.value(column, snapshot.elapsed)));
+        }
+    }
+
+    Row::new(cells)
+}
@@ -1,99 +1,242 @@
 use super::report::TrafficDetail;
-use chrono::{NaiveDateTime, Local, TimeZone};
+use std::collections::BTreeSet;
+use super::pcapng::LINKTYPE_ETHERNET;
+use super::layer2::{Layer2Frame, ArpMessage, ArpOperation};
 use pcap::Device;
 use pnet::packet::{
     ethernet::{EtherTypes, EthernetPacket},
-    ip::IpNextHeaderProtocols,
+    vlan::VlanPacket,
+    arp::ArpPacket,
+    ip::{IpNextHeaderProtocol, IpNextHeaderProtocols},
+    icmp::IcmpPacket,
+    icmpv6::Icmpv6Packet,
     ipv4::Ipv4Packet,
     udp::UdpPacket,
     tcp::TcpPacket,
     Packet, ipv6::Ipv6Packet
 };
 
+// length of the fixed IPv6 header, not included in the payload length
+const IPV6_HEADER_LEN: usize = 40;
 
-pub fn parse(packet: &pcap::Packet) -> TrafficDetail {
-    let mut result = TrafficDetail::new();
+// length of an 802.1Q or 802.1ad tag, followed by the next EtherType
+const VLAN_TAG_LEN: usize = 4;
+
+// length of the IPv6 fragment header, and mask of its fragment offset
+const FRAGMENT_HEADER_LEN: usize = 8;
+const FRAGMENT_OFFSET_MASK: u16 = 0xfff8;
 
-    parse_timestamp(packet, &mut result);
-    parse_layer2(packet, &mut result);
+// reasons why a packet is not handled
+const MALFORMED: &str = "malformed";
+const UNSUPPORTED: &str = "unsupported";
 
-    return result;
+/// Parses a packet read from a device or a pcap file whose link-layer header type is the given one
+pub fn parse(linktype: u16, packet: &pcap::Packet) -> TrafficDetail {
+    parse_frame(linktype, packet.header.ts.tv_sec.into(), packet.header.len as usize, packet.data)
 }
 
-fn parse_timestamp(packet: &pcap::Packet, res: &mut TrafficDetail) {
-    // Get timestamp from header
-    let ts = packet.header.ts.tv_sec;
-    
-    // Here choice has been made to use Local time rather than UTC (replace with Utc.from_...(...) to get UTC)
-    let dt = Local.from_utc_datetime(&NaiveDateTime::from_timestamp_opt(ts.into(), 0).unwrap_or_default());
+/// Parses a frame captured at the given time (seconds since epoch), whose original length on
+/// the wire is len, independently of the file or device it comes from. Only Ethernet frames
+/// are handled.
+pub fn parse_frame(linktype: u16, ts: i64, len: usize, data: &[u8]) -> TrafficDetail {
+    let mut result = TrafficDetail::new();
+
+    result.first_ts = ts;
+    result.last_ts = ts;
+    result.frame_bytes = len;
+    result.captured_bytes = data.len();
+
+    if linktype == LINKTYPE_ETHERNET {
+        parse_layer2(data, &mut result);
+    } else {
+        unhandled(&mut result, UNSUPPORTED);
+    }
 
-    let parsed = dt.format("%Y-%m-%d %H:%M:%S").to_string();
-    res.first_ts = String::from(&parsed);
-    res.last_ts = String::from(&parsed);
+    result
 }
 
-fn parse_layer2(packet: &pcap::Packet, res: &mut TrafficDetail) {
-    let ethernet = EthernetPacket::new(packet.data).unwrap();
+fn parse_layer2(data: &[u8], res: &mut TrafficDetail) {
+    let ethernet = match EthernetPacket::new(data) {
+        Some(e) => e,
+        None => { return unhandled(res, MALFORMED); }
+    };
+
+    res.src_mac = BTreeSet::from([ethernet.get_source().to_string()]);
+    res.dst_mac = BTreeSet::from([ethernet.get_destination().to_string()]);
+
+    let mut ethertype = ethernet.get_ethertype();
+    let mut payload = ethernet.payload();
+
+    // Frames may carry several 802.1Q or 802.1ad tags, the outer one first
+    let mut vlans = vec![];
+    while matches!(ethertype, EtherTypes::Vlan | EtherTypes::PBridge | EtherTypes::QinQ) {
+        let vlan = match VlanPacket::new(payload) {
+            Some(v) => v,
+            None => { return unhandled(res, MALFORMED); }
+        };
+        vlans.push(vlan.get_vlan_identifier().to_string());
+        ethertype = vlan.get_ethertype();
+        payload = &payload[VLAN_TAG_LEN..];
+    }
+    if !vlans.is_empty() {
+        res.vlan = BTreeSet::from([vlans.join(".")]);
+    }
 
-    match ethernet.get_ethertype() {
+    match ethertype {
         EtherTypes::Ipv4 => {
-            let ipv4_packet = Ipv4Packet::new(ethernet.payload()).unwrap();
+            let ipv4_packet = match Ipv4Packet::new(payload) {
+                Some(p) => p,
+                None => { return unhandled(res, MALFORMED); }
+            };
             res.src_ip = ipv4_packet.get_source().to_string();
             res.dst_ip = ipv4_packet.get_destination().to_string();
+            res.ip_bytes = usize::from(ipv4_packet.get_total_length());
 
             match ipv4_packet.get_next_level_protocol() {
-                IpNextHeaderProtocols::Udp  => parse_udp(IpPacket::V4(&ipv4_packet), res),
-                IpNextHeaderProtocols::Tcp  => parse_tcp(IpPacket::V4(&ipv4_packet), res),
-                _ => res.handled = false
+                IpNextHeaderProtocols::Udp  => parse_udp(IpPacket::V4(ipv4_packet.payload()), res),
+                IpNextHeaderProtocols::Tcp  => parse_tcp(IpPacket::V4(ipv4_packet.payload()), res),
+                IpNextHeaderProtocols::Icmp => parse_icmp(IpPacket::V4(ipv4_packet.payload()), res),
+                _ => unhandled(res, UNSUPPORTED)
             }
         },
         EtherTypes::Ipv6 => {
-            let ipv6_packet = Ipv6Packet::new(ethernet.payload()).unwrap();
+            let ipv6_packet = match Ipv6Packet::new(payload) {
+                Some(p) => p,
+                None => { return unhandled(res, MALFORMED); }
+            };
             res.src_ip = ipv6_packet.get_source().to_string();
             res.dst_ip = ipv6_packet.get_destination().to_string();
+            res.ip_bytes = IPV6_HEADER_LEN + usize::from(ipv6_packet.get_payload_length());
+
+            let (next_header, payload) = match skip_extension_headers(ipv6_packet.get_next_header(), ipv6_packet.payload(), res) {
+                Some(upper_layer) => upper_layer,
+                None => { return; }
+            };
 
-            match ipv6_packet.get_next_header() {
-                IpNextHeaderProtocols::Udp => parse_udp(IpPacket::V6(&ipv6_packet), res),
-                IpNextHeaderProtocols::Tcp => parse_tcp(IpPacket::V6(&ipv6_packet), res),
-                _ => res.handled = false
+            match next_header {
+                IpNextHeaderProtocols::Udp => parse_udp(IpPacket::V6(payload), res),
+                IpNextHeaderProtocols::Tcp => parse_tcp(IpPacket::V6(payload), res),
+                IpNextHeaderProtocols::Icmpv6 => parse_icmp(IpPacket::V6(payload), res),
+                _ => unhandled(res, UNSUPPORTED)
             }
         },
-        _ => res.handled = false
+        EtherTypes::Arp => {
+            let arp_packet = match ArpPacket::new(payload) {
+                Some(p) => p,
+                None => { return unhandled(res, MALFORMED); }
+            };
+
+            let mut frame = Layer2Frame::new(ethertype.0, None);
+            frame.arp = Some(ArpMessage {
+                operation: ArpOperation::from_code(arp_packet.get_operation().0),
+                sender_mac: arp_packet.get_sender_hw_addr().to_string(),
+                sender_ip: arp_packet.get_sender_proto_addr().to_string(),
+                target_mac: arp_packet.get_target_hw_addr().to_string(),
+                target_ip: arp_packet.get_target_proto_addr().to_string()
+            });
+            layer2_only(res, frame);
+        },
+        // 802.3 frames start with an LLC header, whose first byte tells the protocol
+        _ => layer2_only(res, Layer2Frame::new(ethertype.0, payload.first().copied()))
     }
 }
 
-fn parse_udp(packet: IpPacket, res: &mut TrafficDetail) {
-    let udp_packet = match packet {
-        IpPacket::V4(ipv4_packet) => UdpPacket::new(ipv4_packet.payload()).or(None),
-        IpPacket::V6(ipv6_packet) => UdpPacket::new(ipv6_packet.payload()).or(None)
-    };
+// Walks the chain of IPv6 extension headers, recording them, and returns the upper-layer protocol
+// with its payload. Nothing is returned if the packet is malformed, encrypted (ESP) or a fragment
+// other than the first one, which does not hold the upper-layer header.
+fn skip_extension_headers<'a>(mut next_header: IpNextHeaderProtocol, mut payload: &'a [u8],
+                              res: &mut TrafficDetail) -> Option<(IpNextHeaderProtocol, &'a [u8])> {
+    loop {
+        let len = match next_header {
+            IpNextHeaderProtocols::Hopopt | IpNextHeaderProtocols::Ipv6Route | IpNextHeaderProtocols::Ipv6Opts |
+            IpNextHeaderProtocols::MobilityHeader | IpNextHeaderProtocols::Hip | IpNextHeaderProtocols::Shim6 => {
+                payload.get(1).map(|l| (usize::from(*l) + 1) * 8)
+            },
+            IpNextHeaderProtocols::Ipv6Frag => Some(FRAGMENT_HEADER_LEN),
+            IpNextHeaderProtocols::Ah => payload.get(1).map(|l| (usize::from(*l) + 2) * 4),
+            _ => break
+        };
+
+        res.ext_headers.insert(String::from(extension_header_name(next_header)));
+
+        let len = match len {
+            Some(l) if l <= payload.len() => l,
+            _ => { unhandled(res, MALFORMED); return None; }
+        };
+
+        if next_header == IpNextHeaderProtocols::Ipv6Frag {
+            let offset = u16::from_be_bytes([payload[2], payload[3]]) & FRAGMENT_OFFSET_MASK;
+            if offset != 0 {
+                unhandled(res, UNSUPPORTED);
+                return None;
+            }
+        }
 
-    match udp_packet {
+        next_header = IpNextHeaderProtocol(payload[0]);
+        payload = &payload[len..];
+    }
+
+    Some((next_header, payload))
+}
+
+// Extension headers are named after the keywords of the IANA registry
+fn extension_header_name(header: IpNextHeaderProtocol) -> &'static str {
+    match header {
+        IpNextHeaderProtocols::Hopopt => "HOPOPT",
+        IpNextHeaderProtocols::Ipv6Route => "IPv6-Route",
+        IpNextHeaderProtocols::Ipv6Frag => "IPv6-Frag",
+        IpNextHeaderProtocols::Ipv6Opts => "IPv6-Opts",
+        IpNextHeaderProtocols::Ah => "AH",
+        IpNextHeaderProtocols::MobilityHeader => "Mobility",
+        IpNextHeaderProtocols::Hip => "HIP",
+        IpNextHeaderProtocols::Shim6 => "Shim6",
+        _ => "unknown"
+    }
+}
+
+fn parse_udp(packet: IpPacket, res: &mut TrafficDetail) {
+    match UdpPacket::new(packet.payload()) {
         Some(packet) => {
             res.src_port = packet.get_source().to_string();
             res.dst_port = packet.get_destination().to_string();
-            res.bytes = usize::from(packet.payload().len());
-            res.protocols = String::from("UDP");
+            res.payload_bytes = packet.payload().len();
+            res.protocols = BTreeSet::from([String::from("UDP")]);
         },
-        None => res.handled = false
+        None => unhandled(res, MALFORMED)
     }
     
 }
 
 fn parse_tcp(packet: IpPacket, res: &mut TrafficDetail) {
-    let tcp_packet: Option<TcpPacket> = match packet {
-        IpPacket::V4(ipv4_packet) => TcpPacket::new(ipv4_packet.payload()).or(None),
-        IpPacket::V6(ipv6_packet) => TcpPacket::new(ipv6_packet.payload()).or(None)
-    };
-
-    match tcp_packet {
+    match TcpPacket::new(packet.payload()) {
         Some(packet) => {
             res.src_port = packet.get_source().to_string();
             res.dst_port = packet.get_destination().to_string();
-            res.bytes = usize::from(packet.payload().len());
-            res.protocols = String::from("TCP");
+            res.payload_bytes = packet.payload().len();
+            res.protocols = BTreeSet::from([String::from("TCP")]);
         },
-        None => res.handled = false
+        None => unhandled(res, MALFORMED)
+    }
+}
+
+// ICMP messages have no ports: their type and code are recorded in place of the source
+// and destination ports, so that each kind of message is a flow of its own
+fn parse_icmp(packet: IpPacket, res: &mut TrafficDetail) {
+    let message = match packet {
+        IpPacket::V4(payload) => IcmpPacket::new(payload)
+                .map(|p| (p.get_icmp_type().0, p.get_icmp_code().0, p.payload().len(), "ICMP")),
+        IpPacket::V6(payload) => Icmpv6Packet::new(payload)
+                .map(|p| (p.get_icmpv6_type().0, p.get_icmpv6_code().0, p.payload().len(), "ICMPv6"))
+    };
+
+    match message {
+        Some((icmp_type, code, payload_len, protocol)) => {
+            res.src_port = icmp_type.to_string();
+            res.dst_port = code.to_string();
+            res.payload_bytes = payload_len;
+            res.protocols = BTreeSet::from([String::from(protocol)]);
+        },
+        None => unhandled(res, MALFORMED)
     }
 }
 
@@ -139,7 +282,224 @@ pub fn parse_device(dev: &Device, index: Option<usize>) -> String {
 
 
 /*************************** Utilities ***************************/
+fn unhandled(res: &mut TrafficDetail, reason: &'static str) {
+    res.handled = false;
+    res.error = Some(reason);
+}
+
+// Frames not carrying IP traffic are only accounted by EtherType, outside of the records
+fn layer2_only(res: &mut TrafficDetail, frame: Layer2Frame) {
+    res.handled = false;
+    res.layer2 = Some(frame);
+}
+
+// Payload of an IP packet, following its header (and the extension headers, for IPv6)
 enum IpPacket<'a> {
-    V4(&'a Ipv4Packet<'a>),
-    V6(&'a Ipv6Packet<'a>)
-}
\ No newline at end of file
+    V4(&'a [u8]),
+    V6(&'a [u8])
+}
+
+impl<'a> IpPacket<'a> {
+    fn payload(&self) -> &'a [u8] {
+        match self {
+            IpPacket::V4(payload) | IpPacket::V6(payload) => payload
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+    const DST_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x02];
+
+    fn ethernet(ethertype: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = [DST_MAC, SRC_MAC].concat();
+        frame.extend(ethertype.to_be_bytes());
+        frame.extend(payload);
+        frame
+    }
+
+    fn vlan_tag(id: u16, ethertype: u16, payload: &[u8]) -> Vec<u8> {
+        let mut tag = id.to_be_bytes().to_vec();
+        tag.extend(ethertype.to_be_bytes());
+        tag.extend(payload);
+        tag
+    }
+
+    fn ipv4(protocol: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x45, 0];
+        packet.extend(((20 + payload.len()) as u16).to_be_bytes());
+        packet.extend([0, 0, 0, 0, 64, protocol, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+        packet.extend(payload);
+        packet
+    }
+
+    fn ipv6(next_header: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x60, 0, 0, 0];
+        packet.extend((payload.len() as u16).to_be_bytes());
+        packet.extend([next_header, 64]);
+        packet.extend([[0x20, 0x01, 0x0d, 0xb8], [0; 4], [0; 4], [0, 0, 0, 1]].concat());
+        packet.extend([[0x20, 0x01, 0x0d, 0xb8], [0; 4], [0; 4], [0, 0, 0, 2]].concat());
+        packet.extend(payload);
+        packet
+    }
+
+    fn udp(src_port: u16, dst_port: u16, payload: &[u8]) -> Vec<u8> {
+        let mut datagram = src_port.to_be_bytes().to_vec();
+        datagram.extend(dst_port.to_be_bytes());
+        datagram.extend(((8 + payload.len()) as u16).to_be_bytes());
+        datagram.extend([0, 0]);
+        datagram.extend(payload);
+        datagram
+    }
+
+    fn tcp(src_port: u16, dst_port: u16, payload: &[u8]) -> Vec<u8> {
+        let mut segment = src_port.to_be_bytes().to_vec();
+        segment.extend(dst_port.to_be_bytes());
+        segment.extend([0, 0, 0, 1, 0, 0, 0, 0, 0x50, 0x18, 0xff, 0xff, 0, 0, 0, 0]);
+        segment.extend(payload);
+        segment
+    }
+
+    fn parse_ethernet(frame: &[u8]) -> TrafficDetail {
+        parse_frame(LINKTYPE_ETHERNET, 1_667_811_600, frame.len(), frame)
+    }
+
+    fn set(values: &[&str]) -> BTreeSet<String> {
+        values.iter().map(|v| String::from(*v)).collect()
+    }
+
+    #[test]
+    fn tcp_over_ipv4() {
+        let frame = ethernet(0x0800, &ipv4(6, &tcp(52583, 443, b"hello")));
+        let detail = parse_ethernet(&frame);
+
+        assert!(detail.handled);
+        assert_eq!((detail.src_ip.as_str(), detail.dst_ip.as_str()), ("10.0.0.1", "10.0.0.2"));
+        assert_eq!((detail.src_port.as_str(), detail.dst_port.as_str()), ("52583", "443"));
+        assert_eq!(detail.protocols, set(&["TCP"]));
+        assert_eq!(detail.src_mac, set(&["02:00:00:00:00:01"]));
+        assert_eq!(detail.dst_mac, set(&["02:00:00:00:00:02"]));
+        assert_eq!((detail.frame_bytes, detail.ip_bytes, detail.payload_bytes), (59, 45, 5));
+        assert_eq!((detail.first_ts, detail.last_ts), (1_667_811_600, 1_667_811_600));
+        assert!(detail.vlan.is_empty());
+    }
+
+    #[test]
+    fn vlan_tags_are_joined_outer_first() {
+        let frame = ethernet(0x8100, &vlan_tag(100, 0x0800, &ipv4(17, &udp(5353, 53, b"query"))));
+        let detail = parse_ethernet(&frame);
+        assert!(detail.handled);
+        assert_eq!(detail.vlan, set(&["100"]));
+        assert_eq!((detail.src_port.as_str(), detail.dst_port.as_str()), ("5353", "53"));
+        assert_eq!(detail.protocols, set(&["UDP"]));
+
+        // 802.1ad service tag followed by an 802.1Q customer tag, with priority bits set
+        let inner = vlan_tag(0x2000 | 20, 0x0800, &ipv4(17, &udp(5353, 53, b"query")));
+        let frame = ethernet(0x88a8, &vlan_tag(300, 0x8100, &inner));
+        let detail = parse_ethernet(&frame);
+        assert!(detail.handled);
+        assert_eq!(detail.vlan, set(&["300.20"]));
+        assert_eq!(detail.src_ip, "10.0.0.1");
+    }
+
+    #[test]
+    fn truncated_vlan_tag_is_malformed() {
+        let detail = parse_ethernet(&ethernet(0x8100, &[0, 100]));
+        assert!(!detail.handled);
+        assert_eq!(detail.error, Some(MALFORMED));
+    }
+
+    #[test]
+    fn icmp_type_and_code_replace_ports() {
+        let frame = ethernet(0x0800, &ipv4(1, &[3, 1, 0, 0, 0, 0, 0, 0]));
+        let detail = parse_ethernet(&frame);
+        assert!(detail.handled);
+        assert_eq!((detail.src_port.as_str(), detail.dst_port.as_str()), ("3", "1"));
+        assert_eq!(detail.protocols, set(&["ICMP"]));
+
+        let frame = ethernet(0x86dd, &ipv6(58, &[128, 0, 0, 0, 0, 1, 0, 1]));
+        let detail = parse_ethernet(&frame);
+        assert!(detail.handled);
+        assert_eq!((detail.src_port.as_str(), detail.dst_port.as_str()), ("128", "0"));
+        assert_eq!(detail.protocols, set(&["ICMPv6"]));
+        assert_eq!((detail.src_ip.as_str(), detail.dst_ip.as_str()), ("2001:db8::1", "2001:db8::2"));
+        assert_eq!(detail.ip_bytes, 48);
+    }
+
+    #[test]
+    fn arp_is_counted_outside_records() {
+        let mut arp = vec![0, 1, 0x08, 0x00, 6, 4, 0, 1];
+        arp.extend(SRC_MAC);
+        arp.extend([192, 168, 1, 1]);
+        arp.extend([0; 6]);
+        arp.extend([192, 168, 1, 2]);
+
+        let detail = parse_ethernet(&ethernet(0x0806, &arp));
+        assert!(!detail.handled);
+        assert_eq!(detail.error, None);
+
+        let frame = detail.layer2.unwrap();
+        assert_eq!((frame.ethertype.as_str(), frame.protocol), ("0x0806", "ARP"));
+        assert_eq!(frame.arp, Some(ArpMessage {
+            operation: ArpOperation::Request,
+            sender_mac: String::from("02:00:00:00:00:01"),
+            sender_ip: String::from("192.168.1.1"),
+            target_mac: String::from("00:00:00:00:00:00"),
+            target_ip: String::from("192.168.1.2")
+        }));
+    }
+
+    #[test]
+    fn layer2_frames_are_identified() {
+        // 802.3 frame whose LLC header is addressed to the spanning tree protocol
+        let detail = parse_ethernet(&ethernet(0x0026, &[0x42, 0x42, 0x03, 0, 0]));
+        let frame = detail.layer2.unwrap();
+        assert_eq!((frame.ethertype.as_str(), frame.protocol), ("802.3", "STP"));
+
+        let detail = parse_ethernet(&ethernet(0x88cc, &[0x02, 0x07]));
+        let frame = detail.layer2.unwrap();
+        assert_eq!((frame.ethertype.as_str(), frame.protocol), ("0x88cc", "LLDP"));
+        assert_eq!(frame.arp, None);
+    }
+
+    #[test]
+    fn ipv6_extension_headers_are_skipped() {
+        // Hop-by-hop options, then the first fragment of a UDP datagram
+        let mut headers = vec![44, 0, 1, 4, 0, 0, 0, 0];
+        headers.extend([17, 0, 0, 1, 0, 0, 0, 42]);
+        headers.extend(udp(546, 547, b"dhcp"));
+
+        let detail = parse_ethernet(&ethernet(0x86dd, &ipv6(0, &headers)));
+        assert!(detail.handled);
+        assert_eq!(detail.ext_headers, set(&["HOPOPT", "IPv6-Frag"]));
+        assert_eq!((detail.src_port.as_str(), detail.dst_port.as_str()), ("546", "547"));
+        assert_eq!(detail.protocols, set(&["UDP"]));
+        assert_eq!(detail.payload_bytes, 4);
+    }
+
+    #[test]
+    fn ipv6_fragments_without_upper_layer_are_not_handled() {
+        let fragment = [17, 0, 0x05, 0xa8, 0, 0, 0, 42, 1, 2, 3, 4];
+        let detail = parse_ethernet(&ethernet(0x86dd, &ipv6(44, &fragment)));
+        assert!(!detail.handled);
+        assert_eq!(detail.error, Some(UNSUPPORTED));
+        assert_eq!(detail.ext_headers, set(&["IPv6-Frag"]));
+
+        // Routing header longer than the packet
+        let detail = parse_ethernet(&ethernet(0x86dd, &ipv6(43, &[17, 2, 0, 0, 0, 0, 0, 0])));
+        assert!(!detail.handled);
+        assert_eq!(detail.error, Some(MALFORMED));
+    }
+
+    #[test]
+    fn other_link_layers_are_unsupported() {
+        let frame = ethernet(0x0800, &ipv4(6, &tcp(1, 2, &[])));
+        let detail = parse_frame(113, 0, frame.len(), &frame);
+        assert!(!detail.handled);
+        assert_eq!(detail.error, Some(UNSUPPORTED));
+        assert_eq!(detail.captured_bytes, frame.len());
+    }
+}
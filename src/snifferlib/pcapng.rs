@@ -0,0 +1,459 @@
+use std::io::{Error, ErrorKind, Read, Write};
+
+// block types
+const SECTION_HEADER: u32 = 0x0a0d0d0a;
+const INTERFACE_DESCRIPTION: u32 = 0x00000001;
+const PACKET: u32 = 0x00000002;
+const SIMPLE_PACKET: u32 = 0x00000003;
+const ENHANCED_PACKET: u32 = 0x00000006;
+
+// magic number telling the byte order of a section
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+
+// option codes
+const OPT_END: u16 = 0;
+const SHB_USERAPPL: u16 = 4;
+const IF_NAME: u16 = 2;
+const IF_DESCRIPTION: u16 = 3;
+const IF_TSRESOL: u16 = 9;
+const IF_FILTER: u16 = 11;
+const IF_TSOFFSET: u16 = 14;
+
+// blocks bigger than this are considered corrupted rather than allocated
+const MAX_BLOCK_LEN: usize = 16 * 1024 * 1024;
+
+// snapshot length recorded for live captures, as libpcap's default one
+const SNAPLEN: u32 = 262_144;
+
+// timestamps are written with microsecond resolution
+const TSRESOL_MICROS: u8 = 6;
+
+/// Link-layer header type of Ethernet frames
+pub const LINKTYPE_ETHERNET: u16 = 1;
+
+/// Interface described by a pcapng file
+#[derive(Debug, Clone)]
+pub struct Interface {
+    pub linktype: u16,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    // number of timestamp units per second
+    units: u64,
+    // seconds added to timestamps
+    offset: i64
+}
+
+impl Interface {
+    /// Returns the name of the interface, its description or its position in the section
+    pub fn label(&self, id: usize) -> String {
+        match (&self.name, &self.description) {
+            (Some(name), _) => String::from(name),
+            (None, Some(description)) => String::from(description),
+            (None, None) => format!("if{}", id)
+        }
+    }
+}
+
+/// Packet read from a pcapng file
+pub struct Frame {
+    /// Position of the interface the packet was captured on, within its section
+    pub interface: usize,
+    /// Seconds since epoch
+    pub ts: i64,
+    /// Original length of the packet on the wire
+    pub len: usize,
+    pub data: Vec<u8>
+}
+
+/// Reads the packets of a pcapng file, one section after another. Name resolution,
+/// statistics and custom blocks are skipped, as well as comments.
+pub struct PcapngReader<R: Read> {
+    input: R,
+    big_endian: bool,
+    interfaces: Vec<Interface>,
+    // time of the last packet, given to packets without timestamp
+    last_ts: i64
+}
+
+impl<R: Read> PcapngReader<R> {
+    pub fn new(input: R) -> Self {
+        Self {
+            input,
+            big_endian: false,
+            interfaces: vec![],
+            last_ts: 0
+        }
+    }
+
+    /// Returns the interface with the given position in the current section
+    pub fn interface(&self, id: usize) -> Option<&Interface> {
+        self.interfaces.get(id)
+    }
+
+    /// Returns the next packet, or None at the end of the file
+    pub fn next_frame(&mut self) -> Result<Option<Frame>, Error> {
+        loop {
+            let mut head = [0u8; 8];
+            match self.input.read_exact(&mut head) {
+                Ok(_) => {},
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => { return Ok(None); },
+                Err(e) => { return Err(e); }
+            }
+
+            // The byte order of a section is only known once its header is read
+            let block_type = u32::from_le_bytes([head[0], head[1], head[2], head[3]]);
+            if block_type == SECTION_HEADER {
+                let mut magic = [0u8; 4];
+                self.input.read_exact(&mut magic)?;
+                self.big_endian = match magic {
+                    m if u32::from_le_bytes(m) == BYTE_ORDER_MAGIC => false,
+                    m if u32::from_be_bytes(m) == BYTE_ORDER_MAGIC => true,
+                    _ => { return Err(Error::other("Invalid pcapng section header.")); }
+                };
+                self.interfaces.clear();
+
+                self.read_body(&head, 4)?;
+                continue;
+            }
+
+            let block_type = self.u32(&head, 0);
+            let body = self.read_body(&head, 0)?;
+
+            let frame = match block_type {
+                INTERFACE_DESCRIPTION => { self.read_interface(&body)?; continue; },
+                ENHANCED_PACKET => self.read_enhanced(&body)?,
+                PACKET => self.read_packet(&body)?,
+                SIMPLE_PACKET => self.read_simple(&body)?,
+                _ => { continue; }
+            };
+
+            self.last_ts = frame.ts;
+            return Ok(Some(frame));
+        }
+    }
+
+    // Reads the body of a block (whose first bytes may have been read already) and its trailing length
+    fn read_body(&mut self, head: &[u8; 8], read: usize) -> Result<Vec<u8>, Error> {
+        let total = self.u32(head, 4) as usize;
+        if total < 12 + read || total % 4 != 0 || total > MAX_BLOCK_LEN {
+            return Err(Error::other("Invalid pcapng block length."));
+        }
+
+        let mut body = vec![0u8; total - 12 - read];
+        self.input.read_exact(&mut body)?;
+
+        let mut trailer = [0u8; 4];
+        self.input.read_exact(&mut trailer)?;
+
+        Ok(body)
+    }
+
+    fn read_interface(&mut self, body: &[u8]) -> Result<(), Error> {
+        if body.len() < 8 {
+            return Err(Error::other("Invalid pcapng interface description block."));
+        }
+
+        let mut interface = Interface {
+            linktype: self.u16(body, 0),
+            name: None,
+            description: None,
+            units: 1_000_000,
+            offset: 0
+        };
+
+        for (code, value) in self.options(body, 8)? {
+            match code {
+                IF_NAME => interface.name = Some(text(value)),
+                IF_DESCRIPTION => interface.description = Some(text(value)),
+                IF_TSRESOL if !value.is_empty() => {
+                    let exponent = u32::from(value[0] & 0x7f);
+                    interface.units = if value[0] & 0x80 == 0 { 10u64.checked_pow(exponent) } else { 2u64.checked_pow(exponent) }
+                            .ok_or_else(|| Error::other("Invalid pcapng timestamp resolution."))?;
+                },
+                IF_TSOFFSET if value.len() == 8 => interface.offset = self.u64(value, 0) as i64,
+                _ => {}
+            }
+        }
+
+        self.interfaces.push(interface);
+        Ok(())
+    }
+
+    fn read_enhanced(&self, body: &[u8]) -> Result<Frame, Error> {
+        if body.len() < 20 {
+            return Err(Error::other("Invalid pcapng enhanced packet block."));
+        }
+
+        let interface = self.u32(body, 0) as usize;
+        let ts = (u64::from(self.u32(body, 4)) << 32) | u64::from(self.u32(body, 8));
+        let (caplen, len) = (self.u32(body, 12) as usize, self.u32(body, 16) as usize);
+
+        self.frame(body, interface, ts, caplen, len)
+    }
+
+    // Packet blocks are obsolete, but still written by old tools
+    fn read_packet(&self, body: &[u8]) -> Result<Frame, Error> {
+        if body.len() < 20 {
+            return Err(Error::other("Invalid pcapng packet block."));
+        }
+
+        let interface = self.u16(body, 0) as usize;
+        let ts = (u64::from(self.u32(body, 4)) << 32) | u64::from(self.u32(body, 8));
+        let (caplen, len) = (self.u32(body, 12) as usize, self.u32(body, 16) as usize);
+
+        self.frame(body, interface, ts, caplen, len)
+    }
+
+    // Simple packet blocks have no timestamp, and belong to the first interface
+    fn read_simple(&self, body: &[u8]) -> Result<Frame, Error> {
+        if body.len() < 4 {
+            return Err(Error::other("Invalid pcapng simple packet block."));
+        }
+
+        let len = self.u32(body, 0) as usize;
+        Ok(Frame {
+            interface: 0,
+            ts: self.last_ts,
+            len,
+            data: body[4..].iter().take(len).copied().collect()
+        })
+    }
+
+    // Both kinds of packet blocks hold the data of the packet after 20 bytes of fields
+    fn frame(&self, body: &[u8], interface: usize, ts: u64, caplen: usize, len: usize) -> Result<Frame, Error> {
+        let info = match self.interfaces.get(interface) {
+            Some(i) => i,
+            None => { return Err(Error::other(format!("Packet captured on unknown interface {}.", interface))); }
+        };
+
+        if 20 + caplen > body.len() {
+            return Err(Error::other("Invalid pcapng captured length."));
+        }
+
+        Ok(Frame {
+            interface,
+            ts: (ts / info.units) as i64 + info.offset,
+            len,
+            data: body[20..20 + caplen].to_vec()
+        })
+    }
+
+    // Returns the options found from the given position of the body, as code and value
+    fn options<'a>(&self, body: &'a [u8], mut pos: usize) -> Result<Vec<(u16, &'a [u8])>, Error> {
+        let mut options = vec![];
+
+        while pos + 4 <= body.len() {
+            let (code, len) = (self.u16(body, pos), self.u16(body, pos + 2) as usize);
+            if code == OPT_END { break; }
+
+            pos += 4;
+            if pos + len > body.len() {
+                return Err(Error::other("Invalid pcapng option."));
+            }
+
+            options.push((code, &body[pos..pos + len]));
+            pos += padded(len);
+        }
+
+        Ok(options)
+    }
+
+    fn u16(&self, bytes: &[u8], pos: usize) -> u16 {
+        let b = [bytes[pos], bytes[pos + 1]];
+        if self.big_endian { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) }
+    }
+
+    fn u32(&self, bytes: &[u8], pos: usize) -> u32 {
+        let b = [bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]];
+        if self.big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) }
+    }
+
+    fn u64(&self, bytes: &[u8], pos: usize) -> u64 {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(&bytes[pos..pos + 8]);
+        if self.big_endian { u64::from_be_bytes(b) } else { u64::from_le_bytes(b) }
+    }
+}
+
+/// Writes captured packets to a pcapng file, made of a single section with a single interface
+pub struct PcapngWriter<W: Write> {
+    out: W
+}
+
+impl<W: Write> PcapngWriter<W> {
+    /// Writes the section header and the description of the interface, recording its name
+    /// and the BPF filter applied to the capture, if any
+    pub fn new(mut out: W, linktype: u16, device: &str, filter: Option<&str>) -> Result<Self, Error> {
+        let mut section = vec![];
+        section.extend(BYTE_ORDER_MAGIC.to_le_bytes());
+        section.extend(1u16.to_le_bytes());
+        section.extend(0u16.to_le_bytes());
+        section.extend((-1i64).to_le_bytes());
+        push_option(&mut section, SHB_USERAPPL, format!("rsniffer {}", env!("CARGO_PKG_VERSION")).as_bytes());
+        push_option(&mut section, OPT_END, &[]);
+        write_block(&mut out, SECTION_HEADER, &section)?;
+
+        let mut interface = vec![];
+        interface.extend(linktype.to_le_bytes());
+        interface.extend(0u16.to_le_bytes());
+        interface.extend(SNAPLEN.to_le_bytes());
+        push_option(&mut interface, IF_NAME, device.as_bytes());
+        if let Some(f) = filter {
+            let mut value = vec![0u8];
+            value.extend(f.as_bytes());
+            push_option(&mut interface, IF_FILTER, &value);
+        }
+        push_option(&mut interface, IF_TSRESOL, &[TSRESOL_MICROS]);
+        push_option(&mut interface, OPT_END, &[]);
+        write_block(&mut out, INTERFACE_DESCRIPTION, &interface)?;
+
+        Ok(Self { out })
+    }
+
+    /// Writes a packet captured at the given time (in microseconds since epoch)
+    pub fn write_packet(&mut self, ts: u64, len: usize, data: &[u8]) -> Result<(), Error> {
+        let mut packet = Vec::with_capacity(20 + padded(data.len()));
+        packet.extend(0u32.to_le_bytes());
+        packet.extend(((ts >> 32) as u32).to_le_bytes());
+        packet.extend((ts as u32).to_le_bytes());
+        packet.extend((data.len() as u32).to_le_bytes());
+        packet.extend((len as u32).to_le_bytes());
+        packet.extend(data);
+        packet.resize(20 + padded(data.len()), 0);
+
+        write_block(&mut self.out, ENHANCED_PACKET, &packet)
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.out.flush()
+    }
+}
+
+fn write_block(out: &mut impl Write, block_type: u32, body: &[u8]) -> Result<(), Error> {
+    let total = (body.len() + 12) as u32;
+
+    out.write_all(&block_type.to_le_bytes())?;
+    out.write_all(&total.to_le_bytes())?;
+    out.write_all(body)?;
+    out.write_all(&total.to_le_bytes())
+}
+
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend(code.to_le_bytes());
+    body.extend((value.len() as u16).to_le_bytes());
+    body.extend(value);
+    body.resize(body.len() + padded(value.len()) - value.len(), 0);
+}
+
+// Blocks and options are padded to 32 bits
+fn padded(len: usize) -> usize {
+    len.div_ceil(4) * 4
+}
+
+fn text(value: &[u8]) -> String {
+    String::from_utf8_lossy(value).trim_end_matches('\0').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns a big-endian block with the given type and body
+    fn be_block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let total = (body.len() + 12) as u32;
+        [&block_type.to_be_bytes()[..], &total.to_be_bytes(), body, &total.to_be_bytes()].concat()
+    }
+
+    #[test]
+    fn written_packets_are_read_back() {
+        let mut file = vec![];
+        let mut writer = PcapngWriter::new(&mut file, LINKTYPE_ETHERNET, "eth0", Some("tcp port 443")).unwrap();
+        writer.write_packet(1_667_811_600_250_000, 60, &[0xaa; 42]).unwrap();
+        writer.write_packet(1_667_811_601_000_000, 1514, &[0xbb; 5]).unwrap();
+        writer.flush().unwrap();
+
+        assert_eq!(&file[..4], &SECTION_HEADER.to_le_bytes());
+        assert_eq!(file.len() % 4, 0);
+
+        let mut reader = PcapngReader::new(file.as_slice());
+        let frame = reader.next_frame().unwrap().unwrap();
+        assert_eq!((frame.interface, frame.ts, frame.len), (0, 1_667_811_600, 60));
+        assert_eq!(frame.data, vec![0xaa; 42]);
+
+        let interface = reader.interface(0).unwrap();
+        assert_eq!(interface.linktype, LINKTYPE_ETHERNET);
+        assert_eq!(interface.label(0), "eth0");
+
+        // Packet data is padded to 32 bits, the padding being left out
+        let frame = reader.next_frame().unwrap().unwrap();
+        assert_eq!((frame.ts, frame.len), (1_667_811_601, 1514));
+        assert_eq!(frame.data, vec![0xbb; 5]);
+
+        assert!(reader.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn big_endian_sections_are_read() {
+        let mut section = BYTE_ORDER_MAGIC.to_be_bytes().to_vec();
+        section.extend([0, 1, 0, 0]);
+        section.extend([0xff; 8]);
+
+        // Nanosecond timestamps offset by 100 seconds, on an unnamed interface
+        let mut interface = vec![0, 1, 0, 0, 0, 0, 0xff, 0xff];
+        interface.extend([0, IF_TSRESOL as u8, 0, 1, 9, 0, 0, 0]);
+        interface.extend([0, IF_TSOFFSET as u8, 0, 8]);
+        interface.extend(100u64.to_be_bytes());
+        interface.extend([0, 0, 0, 0]);
+
+        let ts: u64 = 1_667_811_600_123_456_789;
+        let mut packet = 0u32.to_be_bytes().to_vec();
+        packet.extend(((ts >> 32) as u32).to_be_bytes());
+        packet.extend((ts as u32).to_be_bytes());
+        packet.extend(4u32.to_be_bytes());
+        packet.extend(64u32.to_be_bytes());
+        packet.extend([1, 2, 3, 4]);
+
+        // Simple packet blocks take the time of the previous packet
+        let mut simple = 3u32.to_be_bytes().to_vec();
+        simple.extend([5, 6, 7, 0]);
+
+        let file = [be_block(SECTION_HEADER, &section), be_block(INTERFACE_DESCRIPTION, &interface),
+                    be_block(0x0bad, &[0; 4]), be_block(ENHANCED_PACKET, &packet),
+                    be_block(SIMPLE_PACKET, &simple)].concat();
+
+        let mut reader = PcapngReader::new(file.as_slice());
+        let frame = reader.next_frame().unwrap().unwrap();
+        assert_eq!((frame.ts, frame.len, frame.data), (1_667_811_700, 64, vec![1, 2, 3, 4]));
+        assert_eq!(reader.interface(0).unwrap().label(0), "if0");
+
+        let frame = reader.next_frame().unwrap().unwrap();
+        assert_eq!((frame.interface, frame.ts, frame.len, frame.data), (0, 1_667_811_700, 3, vec![5, 6, 7]));
+        assert!(reader.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn corrupted_files_are_rejected() {
+        let mut file = vec![];
+        PcapngWriter::new(&mut file, LINKTYPE_ETHERNET, "eth0", None).unwrap();
+
+        // Block length that is not a multiple of 4
+        let mut corrupted = file.clone();
+        corrupted.extend(ENHANCED_PACKET.to_le_bytes());
+        corrupted.extend(33u32.to_le_bytes());
+        let mut reader = PcapngReader::new(corrupted.as_slice());
+        assert!(reader.next_frame().is_err());
+
+        // Packet captured on an interface that was not described
+        let mut unknown = file.clone();
+        let mut packet = 1u32.to_le_bytes().to_vec();
+        packet.extend([0; 16]);
+        write_block(&mut unknown, ENHANCED_PACKET, &packet).unwrap();
+        let mut reader = PcapngReader::new(unknown.as_slice());
+        assert!(reader.next_frame().is_err());
+
+        // Section header with an unknown byte order magic
+        let invalid = be_block(SECTION_HEADER, &[0; 16]);
+        let mut reader = PcapngReader::new(invalid.as_slice());
+        assert!(reader.next_frame().is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use super::{set_layout, set_oui, parse_format, SnifferError};
+use super::condition::Conditions;
+use super::input::load_into;
+use super::output;
+use super::report::{TrafficReport, ByteCount};
+use std::io::Write;
+
+/// Builder used to query the records stored by a report (in text, Markdown, CSV, JSON, NDJSON or SQLite format)
+/// or the packets of a capture (in pcap or pcapng format), selecting, aggregating, sorting and limiting
+/// them as a live report would, and writing the result in any of the output formats. Without any
+/// criteria, a query simply converts a report or a capture to another format.
+/// 
+/// #Example
+/// ```no_run
+/// use snifferlib::Query;
+/// use std::io;
+/// 
+/// // Hosts that talked to port 22 on the 7th of November
+/// let res = Query::new(String::from("rsniffer.db"))
+///                 .filter(Some(String::from("dst_port=22,last_ts>=2022-11-07,first_ts<2022-11-08")))
+///                 .aggregate(Some(String::from("src_ip")))
+///                 .sort(Some(String::from("bytes:desc")))
+///                 .limit(Some(10))
+///                 .run(&mut io::stdout());
+/// assert!(res.is_ok());
+/// ```
+pub struct Query {
+    input: String,
+    filter: Option<String>,
+    sorting: Option<String>,
+    aggregation: Option<String>,
+    groups: Option<String>,
+    limit: Option<usize>,
+    columns: Option<String>,
+    oui: Option<String>,
+    bytes: Option<String>,
+    format: Option<String>
+}
+
+impl Query {
+    /// Returns a query over the records stored in the given file
+    pub fn new(input: String) -> Query {
+        Query {
+            input,
+            filter: None,
+            sorting: None,
+            aggregation: None,
+            groups: None,
+            limit: None,
+            columns: None,
+            oui: None,
+            bytes: None,
+            format: None
+        }
+    }
+
+    /// Sets the conditions records must meet to be selected, and returns the query by value.
+    /// Conditions are given as a comma separated list of field, operator (=, !=, >, >=, < or <=)
+    /// and value, where addresses can be compared with networks in CIDR notation and timestamps
+    /// are given as seconds since epoch, RFC 3339, "YYYY-MM-DD hh:mm:ss" or "YYYY-MM-DD" (local time).
+    pub fn filter(mut self, filter: Option<String>) -> Query {
+        self.filter = filter;
+        self
+    }
+
+    /// Sets the sorting criteria, and returns the query by value (see [`super::SnifferBuilder::sort`])
+    pub fn sort(mut self, sorting: Option<String>) -> Query {
+        self.sorting = sorting;
+        self
+    }
+
+    /// Sets the aggregation criteria, and returns the query by value (see [`super::SnifferBuilder::aggregate`])
+    pub fn aggregate(mut self, aggregation: Option<String>) -> Query {
+        self.aggregation = aggregation;
+        self
+    }
+
+    /// Sets the groups used by the src_group and dst_group aggregation criteria, and returns the query
+    /// by value (see [`super::SnifferBuilder::groups`])
+    pub fn groups(mut self, groups: Option<String>) -> Query {
+        self.groups = groups;
+        self
+    }
+
+    /// Sets the maximum number of records written, and returns the query by value
+    pub fn limit(mut self, limit: Option<usize>) -> Query {
+        self.limit = limit;
+        self
+    }
+
+    /// Sets the additional columns, and returns the query by value (see [`super::SnifferBuilder::columns`])
+    pub fn columns(mut self, columns: Option<String>) -> Query {
+        self.columns = columns;
+        self
+    }
+
+    /// Sets the OUI database used to resolve vendors, and returns the query by value
+    /// (see [`super::SnifferBuilder::oui`])
+    pub fn oui(mut self, path: Option<String>) -> Query {
+        self.oui = path;
+        self
+    }
+
+    /// Sets the length used to count the bytes of the packets of captures, and returns the query
+    /// by value (see [`super::SnifferBuilder::bytes`]). Records read from reports keep their counters.
+    pub fn bytes(mut self, bytes: Option<String>) -> Query {
+        self.bytes = bytes;
+        self
+    }
+
+    /// Sets the output format, and returns the query by value (see [`super::SnifferBuilder::format`])
+    pub fn format(mut self, format: Option<String>) -> Query {
+        self.format = format;
+        self
+    }
+
+    /// Runs the query and writes its result to the given destination
+    pub fn run(self, out: &mut dyn Write) -> Result<(), SnifferError> {
+        let mut report = TrafficReport::new();
+        set_layout(&mut report, &self.sorting, &self.aggregation, &self.groups, self.limit, &self.columns)?;
+        set_oui(&mut report, &self.oui, &self.columns)?;
+        let format = parse_format(&self.format)?;
+
+        if let Some(bytes) = &self.bytes {
+            match ByteCount::from_name(bytes) {
+                Some(count) => report.set_byte_count(count),
+                None => { return Err(SnifferError::new(String::from("Invalid bytes length."))); }
+            }
+        }
+
+        let conditions = match &self.filter {
+            Some(f) => match Conditions::parse(f) {
+                Some(c) => c,
+                None => { return Err(SnifferError::new(String::from("Invalid filter. Please use the field=value syntax."))); }
+            },
+            None => Conditions::default()
+        };
+
+        if let Err(e) = load_into(&self.input, &mut report, &conditions) {
+            return Err(SnifferError::new(format!("Couldn't read records from {}. {}", self.input, e)));
+        }
+
+        report.set_source(Some(String::from(&self.input)));
+        report.set_filter(self.filter);
+
+        match output::write(&report.snapshot(), format, true, out) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(SnifferError::new(format!("Couldn't write the result of the query. {}", e)))
+        }
+    }
+}
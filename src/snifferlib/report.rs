@@ -1,6 +1,15 @@
-use std::{collections::HashMap, fs::File, path::Path, io::Error, io::Write};
-use prettytable::{Table, format, row};
-use chrono::Local;
+use std::{collections::{BTreeSet, HashMap}, fs::OpenOptions, io::Error, io::Write, time::Instant};
+use chrono::{DateTime, Local};
+use super::aggregation::{Aggregation, Dimension};
+use super::sorting::Sorting;
+use super::field::Field;
+use super::output::{Column, MetaEntry, Snapshot};
+use super::expiration::{Expiration, Expiry};
+use super::sink::ReportSink;
+use super::metrics::Counters;
+use super::capture::CaptureStats;
+use super::layer2::{Layer2Frame, Layer2Traffic};
+use super::oui::OuiDatabase;
 
 // bytes size for 1 kilobyte
 const KB: usize = 1_000;
@@ -14,22 +23,90 @@ const _MB: usize = MB - 1;
 const GB: usize = 1_000_000_000;
 const _GB: usize = GB - 1;
 
-pub const NFIELDS: u32 = 9;
+// fields holding the cumulated counters of a record
+const COUNTER_FIELDS: [Field; 4] = [Field::Bytes, Field::Packets, Field::FirstTs, Field::LastTs];
+
 pub const DEFAULT_INTERVAL: u64 = 5;
 pub const DEFAULT_OUT: &str = "rsniffer_report.txt";
 
-#[derive(Debug)]
+/// Length used to count the bytes of a packet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ByteCount {
+    /// Original length of the frame on the wire
+    Frame,
+    /// Length of the captured part of the frame
+    Captured,
+    /// Total length of the IP packet (header included)
+    Ip,
+    /// Length of the transport layer payload
+    Payload
+}
+
+impl ByteCount {
+    pub fn from_name(name: &str) -> Option<ByteCount> {
+        match name.trim() {
+            "frame" => Some(ByteCount::Frame),
+            "captured" => Some(ByteCount::Captured),
+            "ip" => Some(ByteCount::Ip),
+            "payload" => Some(ByteCount::Payload),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ByteCount::Frame => "frame",
+            ByteCount::Captured => "captured",
+            ByteCount::Ip => "ip",
+            ByteCount::Payload => "payload"
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct TrafficDetail {
     pub src_ip: String,
     pub dst_ip: String,
+    /// Source port, or type of ICMP and ICMPv6 messages
     pub src_port: String,
+    /// Destination port, or code of ICMP and ICMPv6 messages
     pub dst_port: String,
-    pub protocols: String,
+    /// Protocols of the packets (several ones if aggregated). Like the other lists, they
+    /// are printed comma separated (see [`format_list`]).
+    pub protocols: BTreeSet<String>,
+    /// Interface (or interfaces) the packets have been captured on
+    pub interface: BTreeSet<String>,
+    /// VLAN IDs of the packets, the outer one first when tags are stacked (e.g. "100.20")
+    pub vlan: BTreeSet<String>,
+    /// IPv6 extension headers found before the upper-layer header (e.g. "HOPOPT", "IPv6-Frag")
+    pub ext_headers: BTreeSet<String>,
+    /// Ethernet addresses of the frames (several ones if aggregated, e.g. behind a router)
+    pub src_mac: BTreeSet<String>,
+    pub dst_mac: BTreeSet<String>,
+    /// Vendors of the network interfaces, resolved from the Ethernet addresses
+    pub src_vendor: BTreeSet<String>,
+    pub dst_vendor: BTreeSet<String>,
     pub bytes: usize,
+    pub frame_bytes: usize,
+    pub captured_bytes: usize,
+    pub ip_bytes: usize,
+    pub payload_bytes: usize,
     pub npackets: usize,
-    pub first_ts: String,
-    pub last_ts: String,
-    pub handled: bool
+    pub first_ts: i64,
+    pub last_ts: i64,
+    pub interval_bytes: usize,
+    pub interval_npackets: usize,
+    pub handled: bool,
+    /// Why the packet has not been handled, if so (e.g. "malformed" or "unsupported")
+    pub error: Option<&'static str>,
+    /// EtherType (and ARP message, if any) of frames not carrying IP traffic
+    pub layer2: Option<Layer2Frame>
+}
+
+impl Default for TrafficDetail {
+    fn default() -> Self {
+        TrafficDetail::new()
+    }
 }
 
 impl TrafficDetail {
@@ -39,161 +116,678 @@ impl TrafficDetail {
             dst_ip: String::new(),
             src_port: String::new(),
             dst_port: String::new(),
-            protocols: String::new(),
+            protocols: BTreeSet::new(),
+            interface: BTreeSet::new(),
+            vlan: BTreeSet::new(),
+            ext_headers: BTreeSet::new(),
+            src_mac: BTreeSet::new(),
+            dst_mac: BTreeSet::new(),
+            src_vendor: BTreeSet::new(),
+            dst_vendor: BTreeSet::new(),
             bytes: 0,
+            frame_bytes: 0,
+            captured_bytes: 0,
+            ip_bytes: 0,
+            payload_bytes: 0,
             npackets: 1,
-            first_ts: String::new(),
-            last_ts: String::new(),
-            handled: true
+            first_ts: 0,
+            last_ts: 0,
+            interval_bytes: 0,
+            interval_npackets: 0,
+            handled: true,
+            error: None,
+            layer2: None
         }
     }
 
-    pub fn key(&self) -> String {
-        
-        format!("{}:{}:{}:{}", self.src_ip, self.dst_ip, self.src_port, self.dst_port)
+    /// Cumulates into this detail the counters of another one, extending
+    /// the time window and the lists of protocols, interfaces, VLANs, extension headers and addresses if needed
+    pub fn merge(&mut self, other: &TrafficDetail) {
+        if self.first_ts == 0 || other.first_ts < self.first_ts { self.first_ts = other.first_ts; }
+        if other.last_ts > self.last_ts { self.last_ts = other.last_ts; }
+
+        merge_list(&mut self.protocols, &other.protocols);
+        merge_list(&mut self.interface, &other.interface);
+        merge_list(&mut self.vlan, &other.vlan);
+        merge_list(&mut self.ext_headers, &other.ext_headers);
+        merge_list(&mut self.src_mac, &other.src_mac);
+        merge_list(&mut self.dst_mac, &other.dst_mac);
+        merge_list(&mut self.src_vendor, &other.src_vendor);
+        merge_list(&mut self.dst_vendor, &other.dst_vendor);
+
+        self.bytes += other.bytes;
+        self.frame_bytes += other.frame_bytes;
+        self.captured_bytes += other.captured_bytes;
+        self.ip_bytes += other.ip_bytes;
+        self.payload_bytes += other.payload_bytes;
+        self.npackets += other.npackets;
+        self.interval_bytes += other.interval_bytes;
+        self.interval_npackets += other.interval_npackets;
+    }
+
+    /// Returns a copy of this detail holding the counters of the last interval only
+    pub fn window(&self) -> TrafficDetail {
+        let mut window = self.clone();
+        window.bytes = self.interval_bytes;
+        window.npackets = self.interval_npackets;
+        window
+    }
+
+    /// Returns the number of bytes according to the given length
+    pub fn count(&self, count: ByteCount) -> usize {
+        match count {
+            ByteCount::Frame => self.frame_bytes,
+            ByteCount::Captured => self.captured_bytes,
+            ByteCount::Ip => self.ip_bytes,
+            ByteCount::Payload => self.payload_bytes
+        }
     }
 
     pub fn bytes(&self) -> String {
-        let unit: &str;
-        let bytes: usize;
-
-        match self.bytes {
-            0..=_KB => { unit = " B"; bytes = self.bytes},
-            KB..=_MB => { unit = " KB"; bytes = self.bytes / KB},
-            MB..=_GB => { unit = " MB"; bytes = self.bytes / MB},
-            _ => { unit = " GB"; bytes = self.bytes / GB}
-        };
+        format_bytes(self.bytes)
+    }
+
+    /// Returns the time elapsed (in seconds) between the first and the last packet
+    pub fn duration(&self) -> i64 {
+        self.last_ts - self.first_ts
+    }
+
+    /// Returns the average bits per second over the lifetime of the record.
+    /// Records lasting less than a second are considered to last one second.
+    pub fn avg_bps(&self) -> f64 {
+        (self.bytes * 8) as f64 / self.duration().max(1) as f64
+    }
+
+    /// Returns the average packets per second over the lifetime of the record
+    pub fn avg_pps(&self) -> f64 {
+        self.npackets as f64 / self.duration().max(1) as f64
+    }
 
-        format!("{:>5}{:>2}", bytes, unit)
+    /// Returns the bits per second over the current interval, given the seconds elapsed since its start.
+    /// Intervals lasting less than a second are considered to last one second.
+    pub fn cur_bps(&self, elapsed: f64) -> f64 {
+        (self.interval_bytes * 8) as f64 / elapsed.max(1.0)
     }
+
+    /// Returns the packets per second over the current interval, given the seconds elapsed since its start
+    pub fn cur_pps(&self, elapsed: f64) -> f64 {
+        self.interval_npackets as f64 / elapsed.max(1.0)
+    }
+
+    pub fn first_ts(&self) -> String {
+        format_ts(self.first_ts)
+    }
+
+    pub fn last_ts(&self) -> String {
+        format_ts(self.last_ts)
+    }
+}
+
+// Adds to a list the items of another one it lacks
+fn merge_list(list: &mut BTreeSet<String>, other: &BTreeSet<String>) {
+    if list.is_empty() {
+        list.clone_from(other);
+    } else {
+        list.extend(other.iter().cloned());
+    }
+}
+
+/// Formats a list as printed in reports, its items being comma separated (e.g. "TCP, UDP")
+pub fn format_list(list: &BTreeSet<String>) -> String {
+    list.iter().map(|i| i.as_str()).collect::<Vec<&str>>().join(", ")
+}
+
+/// Parses a list as printed in reports (see [`format_list`])
+pub fn parse_list(text: &str) -> BTreeSet<String> {
+    text.split(", ").map(|i| i.trim()).filter(|i| !i.is_empty()).map(String::from).collect()
+}
+
+/// Formats the given number of bytes using the most suitable unit
+pub fn format_bytes(nbytes: usize) -> String {
+    let unit: &str;
+    let bytes: usize;
+
+    match nbytes {
+        0..=_KB => { unit = " B"; bytes = nbytes},
+        KB..=_MB => { unit = " KB"; bytes = nbytes / KB},
+        MB..=_GB => { unit = " MB"; bytes = nbytes / MB},
+        _ => { unit = " GB"; bytes = nbytes / GB}
+    };
+
+    format!("{:>5}{:>2}", bytes, unit)
+}
+
+/// Formats the given timestamp (seconds since epoch) following ISO 8601
+pub fn format_iso_ts(ts: i64) -> String {
+    let dt = DateTime::from_timestamp(ts, 0).unwrap_or_default().with_timezone(&Local);
+
+    dt.format("%Y-%m-%dT%H:%M:%S%:z").to_string()
+}
+
+/// Formats the given timestamp (seconds since epoch) as a date and time string
+pub fn format_ts(ts: i64) -> String {
+    // Here choice has been made to use Local time rather than UTC (replace with Utc to get UTC)
+    let dt = DateTime::from_timestamp(ts, 0).unwrap_or_default().with_timezone(&Local);
+
+    dt.format("%Y-%m-%d %H:%M:%S").to_string()
 }
 
 pub struct TrafficReport {
     traffic: HashMap<String, TrafficDetail>,
-    file_path: String,
+    sinks: Vec<Box<dyn ReportSink>>,
     device: (usize, String),
-    sorting: Option<String>,
+    source: Option<String>,
+    sorting: Sorting,
     filter: Option<String>,
-    interval: u64
+    aggregation: Aggregation,
+    limit: Option<usize>,
+    expiration: Expiration,
+    closed_path: Option<String>,
+    savefile: Option<String>,
+    // description of the flow exporter, if records are exported as flows once closed
+    flow_export: Option<String>,
+    closed: Vec<TrafficDetail>,
+    // records closed during the current interval, whose last counters are still to be written
+    ended: Vec<(String, TrafficDetail)>,
+    counters: Counters,
+    layer2: Layer2Traffic,
+    oui: Option<OuiDatabase>,
+    delta: bool,
+    timeseries_path: Option<String>,
+    new_records: usize,
+    columns: Vec<Field>,
+    byte_count: ByteCount,
+    interval: u64,
+    // start of the current interval, as the reports are not written exactly every interval
+    interval_start: Instant
 }
 
 impl Default for TrafficReport {
     fn default() -> Self {
-        let default_path = String::from(DEFAULT_OUT);
-        TrafficReport::new(default_path)
+        TrafficReport::new()
     }
 }
 
 impl TrafficReport {
-    pub fn new(file_path: String) -> Self {
+    pub fn new() -> Self {
         Self {
             traffic: HashMap::new(),
-            file_path,
+            sinks: vec![],
             device: (0, String::new()),
-            sorting: None,
+            source: None,
+            sorting: Sorting::default(),
             filter: None,
-            interval: DEFAULT_INTERVAL
+            aggregation: Aggregation::default(),
+            limit: None,
+            expiration: Expiration::default(),
+            closed_path: None,
+            savefile: None,
+            flow_export: None,
+            closed: vec![],
+            ended: vec![],
+            counters: Counters::default(),
+            layer2: Layer2Traffic::default(),
+            oui: None,
+            delta: false,
+            timeseries_path: None,
+            new_records: 0,
+            columns: vec![],
+            byte_count: ByteCount::Payload,
+            interval: DEFAULT_INTERVAL,
+            interval_start: Instant::now()
         }
     }
 
+    /// Writes a snapshot of the report to each of the sinks. All the sinks are given the
+    /// snapshot even if some of them fail, in which case the first error is returned.
     pub fn write(&mut self) -> Result<(), Error> {
-        let path = Path::new(&self.file_path);
+        let snapshot = self.snapshot();
+        let mut res = Ok(());
 
-        // Open a file in write-only mode, returns `io::Result<File>`
-        let mut file = match File::create(&path) {
-            Err(why) => { return Err(why); },
-            Ok(file) => file,
-        };
+        for sink in self.sinks.iter_mut() {
+            if let Err(e) = sink.write(&snapshot) {
+                if res.is_ok() { res = Err(e); }
+            }
+        }
 
-        file = self.write_header(file);
-
-        let mut table = Table::new();
-        let format = format::FormatBuilder::new()
-            .column_separator('|')
-            .borders('|')
-            .separators(&[format::LinePosition::Top, format::LinePosition::Bottom, format::LinePosition::Title],
-                        format::LineSeparator::new('-', '+', '+', '+'))
-            .padding(1, 1)
-            .build();
-        table.set_format(format);
-        table.set_titles(row!["SRC_IP", "DST_IP", "SRC_PORT", "DST_PORT", "PROTOCOLS", "BYTES", "PACKETS #", "FIRST TIMESTAMP", "LAST TIMESTAMP"]);
-        
+        res
+    }
 
-        if self.traffic.len() > 0 {
-            let sorted = self.sort();
-            for detail in sorted {
-                table.add_row(row![detail.1.src_ip, detail.1.dst_ip, detail.1.src_port, detail.1.dst_port, 
-                    detail.1.protocols, detail.1.bytes(), detail.1.npackets, detail.1.first_ts, detail.1.last_ts]);
-            }
+    /// Returns a consistent view of the report, with records sorted and limited as configured
+    pub fn snapshot(&self) -> Snapshot {
+        // In delta mode, only the records active during the last interval are listed,
+        // including the ones closed since
+        let windowed: Vec<(&String, TrafficDetail)>;
+        let records: Vec<(&String, &TrafficDetail)> = if self.delta {
+            windowed = self.traffic.iter()
+                    .chain(self.ended.iter().map(|e| (&e.0, &e.1)))
+                    .filter(|r| r.1.interval_npackets > 0)
+                    .map(|r| (r.0, r.1.window()))
+                    .collect();
+            windowed.iter().map(|r| (r.0, &r.1)).collect()
         } else {
-            table.add_row(row!["", "", "", "", "", "", "", "", ""]);
-        }
+            self.traffic.iter().collect()
+        };
 
-        match table.print(&mut file) {
-            Err(why) => panic!("Couldn't print report table to destination file. {}", why),
-            Ok(_lines) => { }
-        }
+        let sorted = self.sort(records);
+        let limit = self.limit.unwrap_or(sorted.len());
 
+        // Records beyond the limit are summed up in a single detail
+        let others = if sorted.len() > limit {
+            Some((sorted.len() - limit, Self::summary(sorted.iter().skip(limit).map(|d| d.1))))
+        } else {
+            None
+        };
 
-        // Make sure that everything is flushed
-        std::io::stdout().flush().unwrap();
+        // Sinks storing the records are given all the ones updated during the last interval,
+        // along with their last counters for the ones closed since
+        let active = if self.delta {
+            sorted.iter().map(|d| d.1.clone()).collect()
+        } else {
+            sorted.iter().filter(|d| d.1.interval_npackets > 0).map(|d| d.1.clone())
+                    .chain(self.ended.iter().map(|e| e.1.clone()))
+                    .collect()
+        };
 
-        Ok(())
+        Snapshot {
+            metadata: self.metadata(),
+            timestamp: Local::now(),
+            columns: self.columns(),
+            nkeys: self.key_columns().len(),
+            delta: self.delta,
+            records: sorted.iter().take(limit).map(|d| d.1.clone()).collect(),
+            active,
+            others,
+            total: (sorted.len(), Self::summary(sorted.iter().map(|d| d.1))),
+            layer2: self.layer2.clone(),
+            elapsed: self.elapsed()
+        }
     }
 
-    fn write_header(&self, mut file: File) -> File {
-        writeln!(&mut file, "This report was generated by rsniffer with the following configuration:").ok();
-
-        // Device
-        writeln!(&mut file, " -> Device ID: {}", self.device.0).ok();
-        writeln!(&mut file, " -> Device name: {}", self.device.1).ok();
+    fn metadata(&self) -> Vec<MetaEntry> {
+        let mut metadata = vec![];
 
+        // Device, or file the records have been read from
+        match &self.source {
+            Some(source) => metadata.push(MetaEntry::new("source", "Source", String::from(source))),
+            None => {
+                metadata.push(MetaEntry::new("device_id", "Device ID", self.device.0.to_string()));
+                metadata.push(MetaEntry::new("device_name", "Device name", String::from(&self.device.1)));
+            }
+        }
 
         // Filter
         let filter = match &self.filter {
             Some(f) => String::from(f),
             None => String::from("None")
         };
-        writeln!(&mut file, " -> Filter: {}", filter).ok();
+        metadata.push(MetaEntry::new("filter", "Filter", filter));
 
         // Sorting
-        let sorting = match &self.sorting {
-            Some(s) => String::from(s),
-            None => String::from("None")
+        let sorting = if self.sorting.is_empty() {
+            String::from("None")
+        } else {
+            self.sorting.description()
         };
-        writeln!(&mut file, " -> Sorting: {}", sorting).ok();
+        metadata.push(MetaEntry::new("sorting", "Sorting", sorting));
+
+        // Aggregation
+        metadata.push(MetaEntry::new("aggregation", "Aggregation", self.aggregation.description()));
+
+        // Outputs
+        if !self.sinks.is_empty() {
+            let outputs: Vec<String> = self.sinks.iter().map(|s| s.description()).collect();
+            metadata.push(MetaEntry::new("outputs", "Outputs", outputs.join(", ")));
+        }
+
+        if let Some(oui) = &self.oui {
+            metadata.push(MetaEntry::new("oui", "OUI database", oui.description()));
+        }
+
+        // Bytes
+        metadata.push(MetaEntry::new("bytes", "Bytes", format!("{} length", self.byte_count.name())));
+
+        // Additional columns
+        if !self.columns.is_empty() {
+            let columns: Vec<&str> = self.columns.iter().map(|c| c.name()).collect();
+            metadata.push(MetaEntry::new("columns", "Additional columns", columns.join(", ")));
+        }
+
+        // Limit
+        if let Some(limit) = self.limit {
+            metadata.push(MetaEntry::new("limit", "Limit", format!("top {} records", limit)));
+        }
+
+        // Mode
+        let mode = if self.delta {
+            format!("delta (counters of the last {}s only)", self.interval)
+        } else {
+            String::from("cumulative")
+        };
+        metadata.push(MetaEntry::new("mode", "Mode", mode));
+
+        if let Some(timeseries_path) = &self.timeseries_path {
+            metadata.push(MetaEntry::new("timeseries", "Time series", String::from(timeseries_path)));
+        }
+
+        // Expiration
+        if let Some(expiration) = self.expiration.description() {
+            metadata.push(MetaEntry::new("expiration", "Expiration", expiration));
+        }
+
+        if let Some(closed_path) = &self.closed_path {
+            metadata.push(MetaEntry::new("closed_records_log", "Closed records log", String::from(closed_path)));
+        }
+
+        if let Some(savefile) = &self.savefile {
+            metadata.push(MetaEntry::new("savefile", "Savefile", format!("{} (pcapng)", savefile)));
+        }
+
+        if let Some(flow_export) = &self.flow_export {
+            metadata.push(MetaEntry::new("flow_export", "Flow export", String::from(flow_export)));
+        }
 
         // Time interval
-        writeln!(&mut file, " -> Time interval: {}s", self.interval).ok();
+        if self.source.is_none() {
+            metadata.push(MetaEntry::new("time_interval", "Time interval", format!("{}s", self.interval)));
+        }
 
-        let dt = Local::now();
-        writeln!(&mut file, "\nLast update: {}\n", dt.format("%Y-%m-%d %H:%M:%S %Z").to_string()).ok();
+        metadata
+    }
 
-        file
+    // Columns identifying a record come first, followed by the cumulated counters
+    // and by the additional columns, if any
+    fn columns(&self) -> Vec<Column> {
+        let mut columns = self.key_columns();
+        columns.extend(COUNTER_FIELDS.iter().map(|f| Column::Field(*f)));
+        columns.extend(self.columns.iter().map(|f| Column::Field(*f)));
+        columns
     }
 
-    pub fn new_detail(&mut self, ndetail: TrafficDetail) {
+    pub fn key_columns(&self) -> Vec<Column> {
+        let mut columns: Vec<Column> = self.aggregation.dimensions().iter().map(|d| Column::Key(*d)).collect();
+        if !self.aggregation.has(Dimension::Protocol) {
+            columns.push(Column::Field(Field::Protocols));
+        }
+
+        columns
+    }
+
+    /// Returns a detail cumulating the counters of the given ones
+    pub fn summary<'a>(details: impl Iterator<Item = &'a TrafficDetail>) -> TrafficDetail {
+        let mut summary = TrafficDetail::new();
+        summary.npackets = 0;
+
+        for detail in details {
+            summary.merge(detail);
+        }
+
+        summary
+    }
+
+    pub fn new_detail(&mut self, mut ndetail: TrafficDetail) {
         if ndetail.handled == true {
-            self.traffic.entry(ndetail.key())
-                    .and_modify(|detail| {
-                        if ndetail.first_ts < detail.first_ts { detail.first_ts = String::from(&ndetail.first_ts); }
-                        if ndetail.last_ts > detail.last_ts { detail.last_ts = String::from(&ndetail.last_ts); }
-
-                        if !detail.protocols.contains(&ndetail.protocols) {
-                            detail.protocols.push_str(&format!(", {}", ndetail.protocols));
-                        }
-
-                        detail.bytes += ndetail.bytes;
-                        detail.npackets += 1;
-                    })
+            self.resolve_vendors(&mut ndetail);
+            self.aggregation.apply(&mut ndetail);
+            let key = self.aggregation.key(&ndetail);
+
+            ndetail.bytes = ndetail.count(self.byte_count);
+            ndetail.interval_bytes = ndetail.bytes;
+            ndetail.interval_npackets = ndetail.npackets;
+            self.counters.count(&ndetail);
+
+            // Make room for the new record if the limit has been reached
+            if !self.traffic.contains_key(&key) && self.expiration.full(self.traffic.len()) {
+                let victims = self.expiration.victims(&self.traffic);
+                self.close(victims.into_iter().map(|v| (v, Expiry::Evicted)).collect());
+            }
+
+            if !self.traffic.contains_key(&key) {
+                self.new_records += 1;
+            }
+
+            self.traffic.entry(key)
+                    .and_modify(|detail| detail.merge(&ndetail))
                     .or_insert( ndetail );
+        } else if let Some(frame) = &ndetail.layer2 {
+            // Frames not carrying IP traffic are counted with their whole length
+            self.layer2.count(frame, ndetail.frame_bytes);
+        } else {
+            self.counters.count(&ndetail);
         }
     }
 
+    /// Adds a record read from a stored report, whose counters are already computed
+    pub fn add_record(&mut self, mut detail: TrafficDetail) {
+        self.resolve_vendors(&mut detail);
+        self.aggregation.apply(&mut detail);
+        let key = self.aggregation.key(&detail);
+
+        self.traffic.entry(key)
+                .and_modify(|d| d.merge(&detail))
+                .or_insert(detail);
+    }
+
+    // Vendors already known (e.g. read from a stored report) are kept
+    fn resolve_vendors(&self, detail: &mut TrafficDetail) {
+        if let Some(oui) = &self.oui {
+            if detail.src_vendor.is_empty() { detail.src_vendor = oui.vendors(&detail.src_mac); }
+            if detail.dst_vendor.is_empty() { detail.dst_vendor = oui.vendors(&detail.dst_mac); }
+        }
+    }
+
+    /// Returns the counters cumulated since the start of the capture
+    pub fn counters(&self) -> &Counters {
+        &self.counters
+    }
+
+    pub fn set_capture_stats(&mut self, stats: CaptureStats) {
+        self.counters.capture = stats;
+    }
+
+    /// Returns the traffic not carrying IP, by EtherType and ARP message
+    pub fn layer2(&self) -> &Layer2Traffic {
+        &self.layer2
+    }
+
+    /// Forgets the traffic not carrying IP, e.g. when it is not relevant to the records listed
+    pub fn clear_layer2(&mut self) {
+        self.layer2 = Layer2Traffic::default();
+    }
+
+    /// Returns the number of records currently tracked
+    pub fn len(&self) -> usize {
+        self.traffic.len()
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Removes all the records from the report and returns them, by aggregation key
+    pub fn take_records(&mut self) -> HashMap<String, TrafficDetail> {
+        std::mem::take(&mut self.traffic)
+    }
+
+    /// Returns the n records with the most bytes
+    pub fn top(&self, n: usize) -> Vec<TrafficDetail> {
+        let mut records: Vec<(&String, &TrafficDetail)> = self.traffic.iter().collect();
+        records.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then_with(|| a.0.cmp(b.0)));
+        records.into_iter().take(n).map(|r| r.1.clone()).collect()
+    }
+
+    /// Closes the current interval: its counters are appended to the time series, if any,
+    /// and then reset so that the next interval starts from scratch
+    pub fn roll_interval(&mut self) {
+        if self.timeseries_path.is_some() {
+            self.append_timeseries().ok();
+        }
+
+        for detail in self.traffic.values_mut() {
+            detail.interval_bytes = 0;
+            detail.interval_npackets = 0;
+        }
+        self.ended.clear();
+        self.new_records = 0;
+        self.interval_start = Instant::now();
+    }
+
+    // Seconds elapsed since the start of the current interval
+    fn elapsed(&self) -> f64 {
+        self.interval_start.elapsed().as_secs_f64()
+    }
+
+    fn append_timeseries(&self) -> Result<(), Error> {
+        let path = match &self.timeseries_path {
+            Some(p) => p,
+            None => { return Ok(()); }
+        };
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if file.metadata()?.len() == 0 {
+            writeln!(&mut file, "timestamp,bytes,packets,new_records,active_records,records")?;
+        }
+
+        let window = Self::summary(self.traffic.values().chain(self.ended.iter().map(|e| &e.1)));
+        let active = self.traffic.values().filter(|d| d.interval_npackets > 0).count() + self.ended.len();
+        writeln!(&mut file, "{},{},{},{},{},{}", Local::now().format("%Y-%m-%d %H:%M:%S"), window.interval_bytes,
+                window.interval_npackets, self.new_records, active, self.traffic.len())?;
+
+        Ok(())
+    }
+
+    /// Removes from the report the records expired at time now (seconds since epoch)
+    pub fn expire(&mut self, now: i64) {
+        let expired: Vec<(String, Expiry)> = self.traffic.iter()
+                .filter_map(|(key, detail)| self.expiration.expired(detail, now).map(|e| (String::from(key), e)))
+                .collect();
+
+        self.close(expired);
+    }
+
+    fn close(&mut self, keys: Vec<(String, Expiry)>) {
+        let mut closed = vec![];
+        for (key, reason) in keys {
+            if let Some(detail) = self.traffic.remove(&key) {
+                if detail.interval_npackets > 0 {
+                    self.ended.push((key, detail.clone()));
+                }
+                closed.push((reason, detail));
+            }
+        }
+
+        if !closed.is_empty() {
+            self.log_closed(&closed).ok();
+
+            if self.flow_export.is_some() {
+                self.closed.extend(closed.into_iter().map(|c| c.1));
+            }
+        }
+    }
+
+    /// Returns the records to be exported as flows: the ones closed since the last call and,
+    /// if all is true (e.g. when the capture is stopped), the ones still in the report.
+    /// Flows are meant to be encoded and sent once the report is released.
+    pub fn pending_flows(&mut self, all: bool) -> Vec<TrafficDetail> {
+        let mut flows = std::mem::take(&mut self.closed);
+        if all && self.flow_export.is_some() {
+            flows.extend(self.traffic.values().cloned());
+        }
+
+        flows
+    }
+
+    // Closed records are appended to the log, if any, one per line
+    fn log_closed(&self, closed: &[(Expiry, TrafficDetail)]) -> Result<(), Error> {
+        let path = match &self.closed_path {
+            Some(p) => p,
+            None => { return Ok(()); }
+        };
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        let now = Local::now().format("%Y-%m-%d %H:%M:%S");
+        let columns = self.columns();
+
+        for (reason, detail) in closed {
+            let values: Vec<String> = columns.iter().map(|c| c.value(detail, self.elapsed())).collect();
+            writeln!(&mut file, "{} [{}] {}", now, reason.name(), values.join(" | "))?;
+        }
+
+        Ok(())
+    }
+
     pub fn set_filter(&mut self, f: Option<String>) {
         self.filter = f;
     }
 
+    pub fn set_expiration(&mut self, expiration: Expiration) {
+        self.expiration = expiration;
+    }
+
+    pub fn set_closed_path(&mut self, path: Option<String>) {
+        self.closed_path = path;
+    }
+
+    pub fn set_savefile(&mut self, path: Option<String>) {
+        self.savefile = path;
+    }
+
+    pub fn set_flow_export(&mut self, description: Option<String>) {
+        self.flow_export = description;
+    }
+
+    pub fn set_oui(&mut self, oui: Option<OuiDatabase>) {
+        self.oui = oui;
+    }
+
+    pub fn add_sink(&mut self, sink: Box<dyn ReportSink>) {
+        self.sinks.push(sink);
+    }
+
+    pub fn set_byte_count(&mut self, count: ByteCount) {
+        self.byte_count = count;
+    }
+
+    /// Sets the columns added to the report, after its keys and counters. Returns false, leaving the columns
+    /// unchanged, if one of them is listed twice or is already one of the keys or counters (the aggregation
+    /// is thus to be set first).
+    pub fn set_columns(&mut self, columns: Vec<Field>) -> bool {
+        let mut listed: Vec<Field> = self.key_columns().iter()
+                .filter_map(|c| match c {
+                    Column::Key(dim) => Some(dim.field()),
+                    Column::Field(field) => Some(*field),
+                    Column::Diff(_) => None
+                })
+                .chain(COUNTER_FIELDS)
+                .collect();
+
+        for field in &columns {
+            if listed.contains(field) {
+                return false;
+            }
+            listed.push(*field);
+        }
+
+        self.columns = columns;
+        true
+    }
+
+    pub fn set_delta(&mut self, delta: bool) {
+        self.delta = delta;
+    }
+
+    pub fn set_timeseries_path(&mut self, path: Option<String>) {
+        self.timeseries_path = path;
+    }
+
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+    }
+
+    pub fn set_aggregation(&mut self, aggregation: Aggregation) {
+        self.aggregation = aggregation;
+    }
+
     pub fn set_interval(&mut self, t: u64) {
         self.interval = t;
     }
@@ -204,81 +798,163 @@ impl TrafficReport {
             None => { return false; }
         };
 
-        if str.len() != 2 { return false };
-
-        let field = str.chars().nth(0).unwrap().to_digit(10).unwrap_or_default();
-        if field >= NFIELDS { return false; }
-
-        let direction = str.chars().nth(1).unwrap();
-        if direction != 'L' && direction != 'G' { return false; }
-
-        self.sorting = Some(str);
-        return true;
+        match Sorting::parse(&str) {
+            Some(sorting) => { self.sorting = sorting; true },
+            None => false
+        }
     }
 
     pub fn set_device(&mut self, device: (usize, String)) {
         self.device = device;
     }
 
-    fn sort(&self) -> Vec<(&std::string::String, &TrafficDetail)> {
-        let sort = match &self.sorting {
-            Some(s) => String::from(s),
-            None => { return self.traffic.iter().collect(); }
-        };
+    pub fn set_source(&mut self, source: Option<String>) {
+        self.source = source;
+    }
 
-        let field = sort.chars().nth(0).unwrap();
-        let direction = sort.chars().nth(1).unwrap();
-
-        let mut sorted: Vec<_> = self.traffic.iter().collect();
-        match field {
-            '0' => match direction {
-                'L' => sorted.sort_by(|a, b| a.1.src_ip.cmp(&b.1.src_ip)),
-                'G' => sorted.sort_by(|a, b| b.1.src_ip.cmp(&a.1.src_ip)),
-                _ => {}
-            },
-            '1' => match direction {
-                'L' => sorted.sort_by(|a, b| a.1.dst_ip.cmp(&b.1.dst_ip)),
-                'G' => sorted.sort_by(|a, b| b.1.dst_ip.cmp(&a.1.dst_ip)),
-                _ => {}
-            },
-            '2' => match direction {
-                'L' => sorted.sort_by(|a, b| a.1.src_port.cmp(&b.1.src_port)),
-                'G' => sorted.sort_by(|a, b| b.1.src_port.cmp(&a.1.src_port)),
-                _ => {}
-            },
-            '3' => match direction {
-                'L' => sorted.sort_by(|a, b| a.1.dst_port.cmp(&b.1.dst_port)),
-                'G' => sorted.sort_by(|a, b| b.1.dst_port.cmp(&a.1.dst_port)),
-                _ => {}
-            },
-            '4' => match direction {
-                'L' => sorted.sort_by(|a, b| a.1.protocols.cmp(&b.1.protocols)),
-                'G' => sorted.sort_by(|a, b| b.1.protocols.cmp(&a.1.protocols)),
-                _ => {}
-            },
-            '5' => match direction {
-                'L' => sorted.sort_by(|a, b| a.1.bytes.cmp(&b.1.bytes)),
-                'G' => sorted.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes)),
-                _ => {}
-            },
-            '6' => match direction {
-                'L' => sorted.sort_by(|a, b| a.1.npackets.cmp(&b.1.npackets)),
-                'G' => sorted.sort_by(|a, b| b.1.npackets.cmp(&a.1.npackets)),
-                _ => {}
-            },
-            '7' => match direction {
-                'L' => sorted.sort_by(|a, b| a.1.first_ts.cmp(&b.1.first_ts)),
-                'G' => sorted.sort_by(|a, b| b.1.first_ts.cmp(&a.1.first_ts)),
-                _ => {}
-            },
-            '8' => match direction {
-                'L' => sorted.sort_by(|a, b| a.1.last_ts.cmp(&b.1.last_ts)),
-                'G' => sorted.sort_by(|a, b| b.1.last_ts.cmp(&a.1.last_ts)),
-                _ => {}
-            },
-            _ => {}
-        }
+    // Records are sorted by the chosen keys; ties are broken by the aggregation key
+    // so that the order does not depend on the HashMap and is the same between writes
+    fn sort<'a>(&self, mut sorted: Vec<(&'a String, &'a TrafficDetail)>) -> Vec<(&'a String, &'a TrafficDetail)> {
+        sorted.sort_by(|a, b| self.sorting.compare(a.1, b.1).then_with(|| a.0.cmp(b.0)));
         
         sorted
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detail(src_port: &str, payload_bytes: usize) -> TrafficDetail {
+        let mut detail = TrafficDetail::new();
+        detail.src_ip = String::from("10.0.0.1");
+        detail.dst_ip = String::from("10.0.0.2");
+        detail.src_port = String::from(src_port);
+        detail.dst_port = String::from("80");
+        detail.protocols = BTreeSet::from([String::from("TCP")]);
+        detail.payload_bytes = payload_bytes;
+        detail.first_ts = 1_700_000_000;
+        detail.last_ts = 1_700_000_000;
+        detail
+    }
+
+    #[test]
+    fn current_rates_are_computed_over_the_time_elapsed() {
+        let mut report = TrafficReport::new();
+        report.set_interval(60);
+        report.new_detail(detail("1000", 500));
+        report.new_detail(detail("1000", 500));
+
+        // The interval has just started: rates are not spread over the configured 60 seconds
+        let snapshot = report.snapshot();
+        assert!(snapshot.elapsed < 1.0);
+        assert_eq!(snapshot.records[0].cur_bps(snapshot.elapsed), 8000.0);
+        assert_eq!(snapshot.records[0].cur_pps(snapshot.elapsed), 2.0);
+
+        assert_eq!(snapshot.records[0].cur_bps(2.5), 3200.0);
+        assert_eq!(snapshot.records[0].cur_pps(4.0), 0.5);
+
+        // Rolling the interval restarts the clock and the counters
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let before = report.snapshot().elapsed;
+        report.roll_interval();
+        assert!(report.snapshot().elapsed < before);
+        assert_eq!(report.snapshot().records[0].cur_bps(1.0), 0.0);
+    }
+    #[test]
+    fn columns_already_listed_are_rejected() {
+        let mut report = TrafficReport::new();
+        assert!(!report.set_columns(vec![Field::Bytes]));
+        assert!(!report.set_columns(vec![Field::Duration, Field::SrcIp]));
+        assert!(!report.set_columns(vec![Field::Duration, Field::Duration]));
+        assert!(report.set_columns(vec![Field::Duration, Field::Interface]));
+
+        // Keys depend on the aggregation: subnets are listed as addresses, the protocols always are
+        report.set_aggregation(Aggregation::parse("src_subnet,interface").unwrap());
+        assert!(!report.set_columns(vec![Field::SrcIp]));
+        assert!(!report.set_columns(vec![Field::Interface]));
+        assert!(!report.set_columns(vec![Field::Protocols]));
+        assert!(report.set_columns(vec![Field::DstIp, Field::SrcPort]));
+
+        let names: Vec<&str> = report.snapshot().columns.iter().map(|c| c.name()).collect();
+        assert_eq!(names, vec!["src_subnet", "interface", "protocols", "bytes", "packets", "first_ts", "last_ts",
+                "dst_ip", "src_port"]);
+    }
+    #[test]
+    fn records_beyond_the_limit_are_summed_up() {
+        let mut report = TrafficReport::new();
+        assert!(report.set_sorting(Some(String::from("bytes:desc"))));
+        report.set_limit(Some(2));
+        for (port, payload_bytes) in [("1000", 100), ("2000", 400), ("3000", 300), ("4000", 200)] {
+            report.new_detail(detail(port, payload_bytes));
+        }
+
+        let snapshot = report.snapshot();
+        let ports: Vec<&str> = snapshot.records.iter().map(|r| r.src_port.as_str()).collect();
+        assert_eq!(ports, vec!["2000", "3000"]);
+
+        let (count, others) = snapshot.others.as_ref().unwrap();
+        assert_eq!((*count, others.npackets, others.bytes), (2, 2, 300));
+        let (count, total) = &snapshot.total;
+        assert_eq!((*count, total.npackets, total.bytes), (4, 4, 1000));
+
+        let mut text = vec![];
+        crate::output::write(&snapshot, crate::output::OutputFormat::Text, false, &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("OTHERS (2 records)") && text.contains("TOTAL (4 records)"));
+
+        // No summary row when the limit isn't reached
+        report.set_limit(Some(4));
+        assert!(report.snapshot().others.is_none());
+    }
+    #[test]
+    fn delta_snapshots_hold_the_last_interval_only() {
+        let mut report = TrafficReport::new();
+        report.set_delta(true);
+        report.new_detail(detail("1000", 100));
+        report.new_detail(detail("2000", 200));
+        report.roll_interval();
+
+        report.new_detail(detail("1000", 300));
+        report.new_detail(detail("1000", 300));
+
+        // Records idle during the interval are left out, the others only count its packets
+        let snapshot = report.snapshot();
+        assert!(snapshot.delta);
+        let records: Vec<(&str, usize, usize)> = snapshot.records.iter()
+                .map(|r| (r.src_port.as_str(), r.npackets, r.bytes)).collect();
+        assert_eq!(records, vec![("1000", 2, 600)]);
+        assert_eq!((snapshot.total.0, snapshot.total.1.npackets, snapshot.total.1.bytes), (1, 2, 600));
+        assert!(snapshot.metadata.iter().any(|e| e.value.starts_with("delta")));
+
+        // The cumulated counters are kept all along
+        report.set_delta(false);
+        let snapshot = report.snapshot();
+        assert!(!snapshot.delta);
+        assert_eq!((snapshot.total.0, snapshot.total.1.npackets, snapshot.total.1.bytes), (2, 4, 900));
+    }
+    #[test]
+    fn bytes_are_counted_with_the_chosen_length() {
+        // A frame truncated by the snapshot length
+        let mut frame = detail("1000", 1400);
+        frame.frame_bytes = 1474;
+        frame.captured_bytes = 96;
+        frame.ip_bytes = 1460;
+
+        for (name, bytes) in [("frame", 1474), ("captured", 96), ("ip", 1460), ("payload", 1400)] {
+            let count = ByteCount::from_name(name).unwrap();
+            assert_eq!(count.name(), name);
+
+            let mut report = TrafficReport::new();
+            report.set_byte_count(count);
+            report.new_detail(frame.clone());
+            report.new_detail(frame.clone());
+
+            let snapshot = report.snapshot();
+            assert_eq!((snapshot.records[0].bytes, snapshot.records[0].interval_bytes), (2 * bytes, 2 * bytes), "{}", name);
+            assert_eq!(snapshot.records[0].frame_bytes, 2 * 1474);
+        }
+
+        assert!(ByteCount::from_name("wire").is_none());
+    }
+}
@@ -0,0 +1,280 @@
+use super::output::{self, OutputFormat, Snapshot};
+use super::destination::{self, Destination};
+use std::io::{Error, Write};
+
+// suffix of the file holding the metadata of machine readable reports, when separated
+const SIDECAR_SUFFIX: &str = ".meta.csv";
+
+/// Destination of the report, receiving a consistent view of the traffic table at the end of
+/// each interval. The snapshot carries the metadata describing how the capture is configured.
+/// 
+/// #Example
+/// ```
+/// use snifferlib::{ReportSink, Snapshot};
+/// use std::io::Error;
+/// 
+/// // Keeps track of the number of records seen at each interval
+/// struct Counter {
+///     history: Vec<usize>
+/// }
+/// 
+/// impl ReportSink for Counter {
+///     fn write(&mut self, snapshot: &Snapshot) -> Result<(), Error> {
+///         self.history.push(snapshot.total.0);
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait ReportSink: Send {
+    /// Writes the given snapshot of the report
+    fn write(&mut self, snapshot: &Snapshot) -> Result<(), Error>;
+
+    /// Describes the sink in the metadata of the report
+    fn description(&self) -> String {
+        String::from("custom sink")
+    }
+}
+
+/// Writes the report to a file, in the given format. The file is replaced atomically at each
+/// interval, unless the format is meant for streaming (e.g. NDJSON), in which case each snapshot
+/// is appended to it.
+pub struct FileSink {
+    path: String,
+    format: OutputFormat,
+    sidecar: bool
+}
+
+impl FileSink {
+    pub fn new(path: &str, format: OutputFormat) -> Self {
+        Self {
+            path: String::from(path),
+            format,
+            sidecar: false
+        }
+    }
+
+    /// Sets whether the metadata is written to a sidecar file, named after the file with the
+    /// ".meta.csv" suffix, rather than along with the records. Only applies to machine readable formats.
+    pub fn sidecar(mut self, sidecar: bool) -> Self {
+        self.sidecar = sidecar && self.format.machine_readable();
+        self
+    }
+}
+
+impl ReportSink for FileSink {
+    fn write(&mut self, snapshot: &Snapshot) -> Result<(), Error> {
+        let mut content = vec![];
+        output::write(snapshot, self.format, !self.sidecar, &mut content)?;
+        Destination::File(String::from(&self.path)).write(&content, self.format.appends())?;
+
+        if self.sidecar {
+            let mut metadata = vec![];
+            output::write_metadata(snapshot, &mut metadata)?;
+            destination::replace(&format!("{}{}", self.path, SIDECAR_SUFFIX), &metadata)?;
+        }
+
+        Ok(())
+    }
+
+    fn description(&self) -> String {
+        format!("{} to {}", self.format.name(), self.path)
+    }
+}
+
+/// Writes the report to the standard output, in the given format
+pub struct StdoutSink {
+    format: OutputFormat
+}
+
+impl StdoutSink {
+    pub fn new(format: OutputFormat) -> Self {
+        Self { format }
+    }
+}
+
+impl ReportSink for StdoutSink {
+    fn write(&mut self, snapshot: &Snapshot) -> Result<(), Error> {
+        let mut content = vec![];
+        output::write(snapshot, self.format, true, &mut content)?;
+        Destination::Stdout.write(&content, true)
+    }
+
+    fn description(&self) -> String {
+        format!("{} to standard output", self.format.name())
+    }
+}
+
+/// Writes the report to an arbitrary writer, in the given format. Each snapshot is written at once,
+/// followed by a flush.
+pub struct WriterSink {
+    destination: Destination,
+    format: OutputFormat
+}
+
+impl WriterSink {
+    pub fn new(writer: Box<dyn Write + Send>, format: OutputFormat) -> Self {
+        Self {
+            destination: Destination::Writer(writer),
+            format
+        }
+    }
+}
+
+impl ReportSink for WriterSink {
+    fn write(&mut self, snapshot: &Snapshot) -> Result<(), Error> {
+        let mut content = vec![];
+        output::write(snapshot, self.format, true, &mut content)?;
+        self.destination.write(&content, true)
+    }
+
+    fn description(&self) -> String {
+        format!("{} to writer", self.format.name())
+    }
+}
+
+/// Writes the report to a CSV file, with raw counters and ISO 8601 timestamps
+pub struct CsvSink {
+    file: FileSink
+}
+
+impl CsvSink {
+    pub fn new(path: &str) -> Self {
+        Self { file: FileSink::new(path, OutputFormat::Csv) }
+    }
+
+    /// Sets whether the metadata is written to a sidecar file rather than as comment lines
+    pub fn sidecar(mut self, sidecar: bool) -> Self {
+        self.file = self.file.sidecar(sidecar);
+        self
+    }
+}
+
+impl ReportSink for CsvSink {
+    fn write(&mut self, snapshot: &Snapshot) -> Result<(), Error> {
+        self.file.write(snapshot)
+    }
+
+    fn description(&self) -> String {
+        self.file.description()
+    }
+}
+
+/// Writes the report to a JSON file, either as a single document replaced at each interval
+/// or as NDJSON lines appended at each interval
+pub struct JsonSink {
+    file: FileSink
+}
+
+impl JsonSink {
+    /// Returns a sink writing a single JSON document
+    pub fn new(path: &str) -> Self {
+        Self { file: FileSink::new(path, OutputFormat::Json) }
+    }
+
+    /// Returns a sink appending one JSON object per line and per record
+    pub fn lines(path: &str) -> Self {
+        Self { file: FileSink::new(path, OutputFormat::Ndjson) }
+    }
+}
+
+impl ReportSink for JsonSink {
+    fn write(&mut self, snapshot: &Snapshot) -> Result<(), Error> {
+        self.file.write(snapshot)
+    }
+
+    fn description(&self) -> String {
+        self.file.description()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{TrafficDetail, TrafficReport};
+    use std::{collections::BTreeSet, fs, sync::{Arc, Mutex}};
+
+    // Keeps the number of records of each snapshot, or fails
+    struct Counter {
+        history: Arc<Mutex<Vec<usize>>>,
+        fail: bool
+    }
+
+    impl ReportSink for Counter {
+        fn write(&mut self, snapshot: &Snapshot) -> Result<(), Error> {
+            self.history.lock().unwrap().push(snapshot.total.0);
+            if self.fail { Err(Error::other("full")) } else { Ok(()) }
+        }
+    }
+
+    // Writer whose content can be read while it is owned by a sink
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    fn detail() -> TrafficDetail {
+        let mut detail = TrafficDetail::new();
+        detail.src_ip = String::from("10.0.0.1");
+        detail.dst_ip = String::from("10.0.0.2");
+        detail.src_port = String::from("1000");
+        detail.dst_port = String::from("80");
+        detail.protocols = BTreeSet::from([String::from("TCP")]);
+        detail.payload_bytes = 100;
+        detail
+    }
+
+    #[test]
+    fn every_sink_is_given_the_snapshot() {
+        let history = Arc::new(Mutex::new(vec![]));
+        let shared = Shared::default();
+
+        let mut report = TrafficReport::new();
+        report.add_sink(Box::new(Counter { history: Arc::clone(&history), fail: true }));
+        report.add_sink(Box::new(WriterSink::new(Box::new(shared.clone()), OutputFormat::Ndjson)));
+        report.add_sink(Box::new(Counter { history: Arc::clone(&history), fail: false }));
+        report.new_detail(detail());
+
+        // The first error is returned once all the sinks have been written
+        assert_eq!(report.write().unwrap_err().to_string(), "full");
+        assert_eq!(*history.lock().unwrap(), vec![1, 1]);
+
+        let content = String::from_utf8(shared.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        assert!(content.contains("\"outputs\":\"custom sink, ndjson to writer, custom sink\""));
+    }
+
+    #[test]
+    fn metadata_can_be_written_aside() {
+        let path = std::env::temp_dir().join(format!("rsniffer-sink-{}.csv", std::process::id()));
+        let path = path.to_str().unwrap();
+        let sidecar = format!("{}{}", path, SIDECAR_SUFFIX);
+
+        let mut report = TrafficReport::new();
+        report.new_detail(detail());
+        let snapshot = report.snapshot();
+
+        CsvSink::new(path).sidecar(true).write(&snapshot).unwrap();
+        let content = fs::read_to_string(path).unwrap();
+        let metadata = fs::read_to_string(&sidecar).unwrap();
+        assert!(content.starts_with("src_ip,") && content.lines().count() == 2);
+        assert!(metadata.starts_with("name,value\n") && metadata.contains("\nlast_update,"));
+
+        CsvSink::new(path).write(&snapshot).unwrap();
+        assert!(fs::read_to_string(path).unwrap().starts_with("# "));
+
+        // Human readable formats always hold their metadata
+        let sink = FileSink::new(path, OutputFormat::Html).sidecar(true);
+        assert!(!sink.sidecar);
+
+        fs::remove_file(path).ok();
+        fs::remove_file(&sidecar).ok();
+    }
+}
@@ -15,22 +15,116 @@ mod capture;
 #[doc(hidden)]
 mod state_handler;
 
+#[doc(hidden)]
+mod aggregation;
+
+#[doc(hidden)]
+mod field;
+
+#[doc(hidden)]
+mod sorting;
+
+#[doc(hidden)]
+mod expiration;
+
+#[doc(hidden)]
+mod output;
+
+#[doc(hidden)]
+mod destination;
+
+#[doc(hidden)]
+mod sink;
+
+#[doc(hidden)]
+mod netflow;
+
+#[doc(hidden)]
+mod metrics;
+
+#[doc(hidden)]
+mod sqlite;
+
+#[doc(hidden)]
+mod condition;
+
+#[doc(hidden)]
+mod input;
+
+#[doc(hidden)]
+mod query;
+
+#[doc(hidden)]
+mod diff;
+
+#[doc(hidden)]
+mod pcapng;
+
+#[doc(hidden)]
+mod layer2;
+
+#[doc(hidden)]
+mod oui;
+
+pub use sink::{ReportSink, FileSink, StdoutSink, WriterSink, CsvSink, JsonSink};
+pub use output::{Snapshot, MetaEntry, Column, Row, OutputFormat};
+pub use report::TrafficDetail;
+pub use netflow::{FlowExporter, FlowVersion};
+pub use sqlite::SqliteSink;
+pub use query::Query;
+pub use diff::{Diff, Change, DiffField, DiffRow, diff, diff_by};
+pub use layer2::{Layer2Traffic, Layer2Frame, ArpMessage, ArpOperation};
+
+use aggregation::Aggregation;
+use field::{Field, parse_fields};
+use expiration::{Expiration, Eviction};
 use capture::CaptureWrapper;
 use parser::{parse, parse_device};
+use pcapng::PcapngWriter;
+use oui::OuiDatabase;
 use state_handler::{State, StateHandler};
-use report::{TrafficReport, DEFAULT_INTERVAL, DEFAULT_OUT};
+use report::{TrafficReport, ByteCount, DEFAULT_INTERVAL, DEFAULT_OUT};
 
 use core::time;
+use chrono::Local;
 use pcap::{Device, Error};
-use std::{sync::{Arc, Mutex}, thread::{self, JoinHandle}, io::Error as IOError, fmt};
+use std::{collections::BTreeSet, sync::{Arc, Mutex}, thread::{self, JoinHandle}, time::{Duration, Instant}, io::{BufWriter, Error as IOError, Write}, fmt, fs::{File, OpenOptions}};
+
+// name used for the standard output in place of the output file
+const STDOUT_NAME: &str = "-";
+
+// time between updates of the statistics of the capture
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
 
 /// Builder used to configure, build and start a Sniffer
 pub struct SnifferBuilder {
     device: String,
     out: Option<String>,
+    writer: Option<Box<dyn Write + Send>>,
+    sinks: Vec<Box<dyn ReportSink>>,
+    collector: Option<String>,
+    flow_version: Option<String>,
+    metrics: Option<String>,
+    database: Option<String>,
+    save: Option<String>,
     filter: Option<String>,
     interval: u64,
-    sorting: Option<String>
+    sorting: Option<String>,
+    aggregation: Option<String>,
+    groups: Option<String>,
+    limit: Option<usize>,
+    idle_timeout: Option<u64>,
+    active_timeout: Option<u64>,
+    max_flows: Option<usize>,
+    eviction: Option<String>,
+    closed_flows: Option<String>,
+    delta: bool,
+    timeseries: Option<String>,
+    columns: Option<String>,
+    oui: Option<String>,
+    bytes: Option<String>,
+    format: Option<String>,
+    sidecar: bool
 }
 
 impl SnifferBuilder {
@@ -82,6 +176,9 @@ impl SnifferBuilder {
     }
 
     /// Sets the output file name on the builder itself, and returns the builder by value.
+    /// The report is written to a temporary file in the same directory, which is then renamed
+    /// over the output file, so that readers never see a truncated report.
+    /// If "-" is given, the report is written to the standard output instead.
     ///  
     /// #Example
     /// ```
@@ -97,6 +194,85 @@ impl SnifferBuilder {
         self
     }
 
+    /// Sets a writer the report is written to, in place of the output file, on the builder
+    /// itself, and returns the builder by value. At each interval, the whole report is written
+    /// to the writer at once, followed by a flush.
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// use std::io;
+    /// 
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.writer(Box::new(io::stderr())).format(Some(String::from("ndjson")));
+    /// ```
+    pub fn writer(mut self, writer: Box<dyn Write + Send>) -> SnifferBuilder {
+        self.writer = Some(writer);
+        self
+    }
+
+    /// Registers a sink the report is written to at the end of each interval, on the builder itself,
+    /// and returns the builder by value. Several sinks can be registered, each one receiving the same
+    /// snapshot of the report. If no sink nor database is registered, the report is written to the output
+    /// file (or writer) in the chosen format; if an output file is explicitly set, it is written to as well.
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::{Sniffer, FileSink, JsonSink, OutputFormat};
+    /// 
+    /// // Text report for humans and NDJSON lines for machines
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.sink(Box::new(FileSink::new("report.txt", OutputFormat::Text)))
+    ///                  .sink(Box::new(JsonSink::lines("report.ndjson")));
+    /// ```
+    pub fn sink(mut self, sink: Box<dyn ReportSink>) -> SnifferBuilder {
+        self.sinks.push(sink);
+        self
+    }
+
+    /// Sets the output format on the builder itself, and returns the builder by value.
+    /// The format is one of:
+    ///  - "text": a header followed by an ASCII table (the default one)
+    ///  - "csv": a header row followed by one row per record, with raw byte counts and ISO 8601 timestamps
+    ///  - "json": a single document with the configuration of the capture and an array of records
+    ///  - "ndjson": one JSON object per line and per record, appended to the output file at each interval
+    ///  - "html": a self-contained page with the configuration of the capture, charts of the top talkers
+    ///    and of the protocols, and a sortable and filterable table of the records
+    ///  - "markdown" (or "md"): a bullet list with the configuration followed by a GitHub-flavoured table,
+    ///    ready to be pasted into tickets and wikis
+    /// 
+    /// In machine readable formats, the configuration of the capture is written along with the records
+    /// (as comment lines in CSV), unless a sidecar file is requested with [`SnifferBuilder::sidecar`].
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.format(Some(String::from("csv"))).out(Some(String::from("report.csv")));
+    /// ```
+    pub fn format(mut self, format: Option<String>) -> SnifferBuilder {
+        self.format = format;
+        self
+    }
+
+    /// Sets whether the configuration of the capture is written to a sidecar file, on the builder
+    /// itself, and returns the builder by value. The sidecar file is named after the output file
+    /// with the ".meta.csv" suffix, and it is only written along with machine readable formats.
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// // Writes records to report.csv and the configuration to report.csv.meta.csv
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.format(Some(String::from("csv"))).out(Some(String::from("report.csv"))).sidecar(true);
+    /// ```
+    pub fn sidecar(mut self, sidecar: bool) -> SnifferBuilder {
+        self.sidecar = sidecar;
+        self
+    }
+
     /// Sets the filter on the builder itself, and returns the builder by value.
     ///  
     /// #Example
@@ -114,8 +290,23 @@ impl SnifferBuilder {
     }
 
     /// Sets the sorting criteria on the builder itself, and returns the builder by value.
+    /// The criteria is a comma separated list of keys, each one given as field[:asc|:desc],
+    /// where field is one of src_ip, dst_ip, src_port, dst_port, protocols, bytes, packets,
+    /// first_ts, last_ts, duration, avg_bps, avg_pps, cur_bps, cur_pps, frame_bytes, captured_bytes,
+    /// ip_bytes and payload_bytes. Keys are applied in the given order, and remaining ties are
+    /// broken by the fields identifying the record, so that the order is always the same.
+    /// The legacy XY syntax (e.g. "5G") is still accepted.
     ///  
-    /// #Example
+    /// #Examples
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// let mut sorting = Some(String::from("bytes:desc,dst_ip:asc"));
+    /// 
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.sort(sorting);
+    /// ```
+    /// 
     /// ```
     /// use snifferlib::Sniffer;
     /// 
@@ -130,6 +321,322 @@ impl SnifferBuilder {
         self
     }
 
+    /// Sets the additional columns of the report on the builder itself, and returns the builder by value.
+    /// Columns are given as a comma separated list chosen among duration (time elapsed between the first
+    /// and the last packet), avg_bps and avg_pps (average bits and packets per second over the lifetime
+    /// of the record), cur_bps and cur_pps (bits and packets per second over the last interval),
+    /// frame_bytes, captured_bytes, ip_bytes and payload_bytes (see [`SnifferBuilder::bytes`]),
+    /// interface and vlan (the interfaces the packets have been captured on, and their VLAN IDs),
+    /// ext_headers (IPv6 extension headers found before the transport layer header),
+    /// src_mac and dst_mac (Ethernet addresses of the frames), and src_vendor and dst_vendor (vendors
+    /// of the network interfaces, see [`SnifferBuilder::oui`]).
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// let mut columns = Some(String::from("duration,avg_bps,cur_bps"));
+    /// 
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.columns(columns).sort(Some(String::from("cur_bps:desc")));
+    /// ```
+    pub fn columns(mut self, columns: Option<String>) -> SnifferBuilder {
+        self.columns = columns;
+        self
+    }
+
+    /// Sets the OUI database used to resolve the vendors of the network interfaces from their MAC
+    /// addresses, on the builder itself, and returns the builder by value. The database is either the
+    /// manuf file shipped with Wireshark or the oui.txt registry published by the IEEE. If not set and
+    /// the src_vendor or dst_vendor columns are requested, the usual places are searched for one of them
+    /// (e.g. /usr/share/wireshark/manuf or /usr/share/ieee-data/oui.txt).
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.columns(Some(String::from("src_mac,src_vendor"))).oui(Some(String::from("manuf")));
+    /// ```
+    pub fn oui(mut self, path: Option<String>) -> SnifferBuilder {
+        self.oui = path;
+        self
+    }
+
+    /// Sets the length counted by the BYTES column on the builder itself, and returns the builder by value.
+    /// The length is one of "frame" (original length of the frame on the wire), "captured" (length of the
+    /// captured part of the frame), "ip" (total length of the IP packet) or "payload" (length of the
+    /// transport layer payload, the default one). All of them are available as additional columns anyway.
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// // Count bytes as interface counters do
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.bytes(Some(String::from("frame")));
+    /// ```
+    pub fn bytes(mut self, bytes: Option<String>) -> SnifferBuilder {
+        self.bytes = bytes;
+        self
+    }
+
+    /// Sets the aggregation criteria on the builder itself, and returns the builder by value.
+    /// The criteria is a comma separated list of the fields that identify a record of the report,
+    /// chosen among src_ip, dst_ip, src_port, dst_port, protocol, src_subnet, dst_subnet (/24 for IPv4,
+    /// /64 for IPv6), src_group and dst_group (see [`SnifferBuilder::groups`]), interface and vlan
+    /// (VLAN IDs, the outer one first when tags are stacked, e.g. "100.20").
+    /// If not set, traffic is aggregated by src_ip, dst_ip, src_port and dst_port.
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// // Top talkers by host
+    /// let mut aggregation = Some(String::from("src_ip"));
+    /// 
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.aggregate(aggregation);
+    /// ```
+    pub fn aggregate(mut self, aggregation: Option<String>) -> SnifferBuilder {
+        self.aggregation = aggregation;
+        self
+    }
+
+    /// Sets the groups used by the src_group and dst_group aggregation criteria on the builder itself,
+    /// and returns the builder by value. Groups are given as a comma separated list of name=CIDR;
+    /// addresses not belonging to any group are reported as "other".
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// let mut groups = Some(String::from("lan=192.168.1.0/24,vpn=10.8.0.0/16"));
+    /// 
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.aggregate(Some(String::from("dst_group,protocol"))).groups(groups);
+    /// ```
+    pub fn groups(mut self, groups: Option<String>) -> SnifferBuilder {
+        self.groups = groups;
+        self
+    }
+
+    /// Sets the maximum number of records written to the report on the builder itself, and returns
+    /// the builder by value. Only the top records according to the sorting criteria are listed,
+    /// followed by a row summing up all the others. A row with the totals of the whole capture is
+    /// always written at the end of the report.
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// // Top 10 records by transmitted bytes
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.sort(Some(String::from("bytes:desc"))).limit(Some(10));
+    /// ```
+    pub fn limit(mut self, limit: Option<usize>) -> SnifferBuilder {
+        self.limit = limit;
+        self
+    }
+
+    /// Sets the idle timeout (in seconds) on the builder itself, and returns the builder by value.
+    /// Records for which no packet has been captured for the given time are removed from the report.
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.idle_timeout(Some(15));
+    /// ```
+    pub fn idle_timeout(mut self, timeout: Option<u64>) -> SnifferBuilder {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Sets the active timeout (in seconds) on the builder itself, and returns the builder by value.
+    /// Records lasting for the given time are removed from the report, even if still active;
+    /// further packets will be accounted in a new record.
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.active_timeout(Some(1800));
+    /// ```
+    pub fn active_timeout(mut self, timeout: Option<u64>) -> SnifferBuilder {
+        self.active_timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum number of records tracked by the report on the builder itself, and
+    /// returns the builder by value. When the limit is reached, records are evicted according
+    /// to the eviction policy (see [`SnifferBuilder::eviction`]). The limit must be greater than 0.
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.max_flows(Some(100_000));
+    /// ```
+    pub fn max_flows(mut self, max: Option<usize>) -> SnifferBuilder {
+        self.max_flows = max;
+        self
+    }
+
+    /// Sets the eviction policy on the builder itself, and returns the builder by value.
+    /// The policy is one of "lru" (records idle for the longest time, the default one),
+    /// "oldest" (records seen first) or "smallest" (records with the fewest bytes).
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.max_flows(Some(100_000)).eviction(Some(String::from("smallest")));
+    /// ```
+    pub fn eviction(mut self, eviction: Option<String>) -> SnifferBuilder {
+        self.eviction = eviction;
+        self
+    }
+
+    /// Sets the file to which records removed from the report (because expired or evicted)
+    /// are appended, on the builder itself, and returns the builder by value.
+    /// If not set, such records are discarded.
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.idle_timeout(Some(15)).closed_flows(Some(String::from("closed.txt")));
+    /// ```
+    pub fn closed_flows(mut self, path: Option<String>) -> SnifferBuilder {
+        self.closed_flows = path;
+        self
+    }
+
+    /// Sets the delta mode on the builder itself, and returns the builder by value.
+    /// In delta mode, each report lists only the records active during the last interval,
+    /// with bytes and packets counted over that interval, rather than the totals since the
+    /// start of the capture.
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.delta(true).interval(10);
+    /// ```
+    pub fn delta(mut self, delta: bool) -> SnifferBuilder {
+        self.delta = delta;
+        self
+    }
+
+    /// Sets the time series file on the builder itself, and returns the builder by value.
+    /// At the end of each interval, a timestamped line with the bytes, packets, new records
+    /// and active records of that interval is appended to the file (in CSV format).
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.timeseries(Some(String::from("rsniffer_timeseries.csv")));
+    /// ```
+    pub fn timeseries(mut self, path: Option<String>) -> SnifferBuilder {
+        self.timeseries = path;
+        self
+    }
+
+    /// Sets the address of the collector flows are exported to, on the builder itself, and returns
+    /// the builder by value. Records are sent over UDP when they are closed, and when the capture is
+    /// stopped. Records are only closed during the capture when timeouts are set (see
+    /// [`SnifferBuilder::idle_timeout`] and [`SnifferBuilder::active_timeout`], usually 15 seconds and
+    /// 30 minutes for flow exporters), which remove them from the report as well.
+    /// Bytes are exported as IP lengths, whatever length is counted by the report.
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.collector(Some(String::from("127.0.0.1:2055")));
+    /// ```
+    pub fn collector(mut self, collector: Option<String>) -> SnifferBuilder {
+        self.collector = collector;
+        self
+    }
+
+    /// Sets the version of the protocol used to export flows on the builder itself, and returns the
+    /// builder by value. The version is one of "v5" (NetFlow v5, IPv4 flows only), "v9" (NetFlow v9,
+    /// the default one) or "ipfix".
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.collector(Some(String::from("127.0.0.1:4739"))).flow_version(Some(String::from("ipfix")));
+    /// ```
+    pub fn flow_version(mut self, version: Option<String>) -> SnifferBuilder {
+        self.flow_version = version;
+        self
+    }
+
+    /// Sets the address of the HTTP endpoint serving metrics in the Prometheus text format, on the
+    /// builder itself, and returns the builder by value. Metrics are served at /metrics, and include
+    /// packets and bytes by protocol, counters of the top records, parser errors, packets dropped by
+    /// pcap and the state of the sniffer.
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.metrics(Some(String::from("127.0.0.1:9184")));
+    /// ```
+    pub fn metrics(mut self, address: Option<String>) -> SnifferBuilder {
+        self.metrics = address;
+        self
+    }
+
+    /// Sets the SQLite database the report is stored into, on the builder itself, and returns the builder
+    /// by value. At each interval, the records updated during the interval are stored into the database
+    /// (including the ones beyond the limit of the report), along with the totals of the interval (see
+    /// [`SqliteSink`] for the schema). The report is only written to the output file (or writer) if one is
+    /// explicitly set.
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.database(Some(String::from("rsniffer.db")));
+    /// ```
+    pub fn database(mut self, path: Option<String>) -> SnifferBuilder {
+        self.database = path;
+        self
+    }
+
+    /// Sets the file the captured packets are saved to, in pcapng format, on the builder itself, and returns
+    /// the builder by value. The name of the device and the BPF filter are recorded in the description of
+    /// the interface, and the file is flushed every second, so that it can be read while capturing.
+    ///  
+    /// #Example
+    /// ```
+    /// use snifferlib::Sniffer;
+    /// 
+    /// let mut builder = Sniffer::builder();
+    /// builder = builder.filter(Some(String::from("tcp port 443"))).save(Some(String::from("https.pcapng")));
+    /// ```
+    pub fn save(mut self, path: Option<String>) -> SnifferBuilder {
+        self.save = path;
+        self
+    }
+
     /// Builds the Sniffer as previously configured, starts the capture, and returns a
     /// Result containing either the Sniffer or a SnifferError (in case something goes
     /// wrong while creating the sniffer or starting the capture)
@@ -170,17 +677,100 @@ impl SnifferBuilder {
     /// assert!(sniffer.is_err(), "Device does not exist!");
     /// ```
     pub fn capture(self) -> Result<Sniffer, SnifferError> {
-        let mut report =  match &self.out {
-            Some(file_path) => TrafficReport::new(String::from(file_path)),
-            None => TrafficReport::default()
+        let mut report = TrafficReport::new();
+
+        // Set sorting, aggregation, limit and columns for report
+        set_layout(&mut report, &self.sorting, &self.aggregation, &self.groups, self.limit, &self.columns)?;
+        set_oui(&mut report, &self.oui, &self.columns)?;
+
+        // Set sinks for report, the output file (or writer) being the default one
+        let format = parse_format(&self.format)?;
+
+        if self.writer.is_some() || self.out.is_some() || (self.sinks.is_empty() && self.database.is_none()) {
+            let sink: Box<dyn ReportSink> = match (self.writer, self.out.as_deref()) {
+                (Some(writer), _) => Box::new(WriterSink::new(writer, format)),
+                (None, Some(STDOUT_NAME)) => Box::new(StdoutSink::new(format)),
+                (None, out) => Box::new(FileSink::new(out.unwrap_or(DEFAULT_OUT), format).sidecar(self.sidecar))
+            };
+            report.add_sink(sink);
+        }
+
+        for sink in self.sinks {
+            report.add_sink(sink);
+        }
+
+        if let Some(path) = &self.database {
+            match SqliteSink::new(path) {
+                Ok(sink) => report.add_sink(Box::new(sink)),
+                Err(e) => { return Err(SnifferError::new(format!("Couldn't open database {}. {}", path, e))); }
+            }
+        }
+
+        // Set length counted by report
+        if let Some(bytes) = &self.bytes {
+            match ByteCount::from_name(bytes) {
+                Some(count) => report.set_byte_count(count),
+                None => { return Err(SnifferError::new(String::from("Invalid bytes length."))); }
+            }
+        }
+
+        // Set expiration criteria for report
+        if self.max_flows == Some(0) {
+            return Err(SnifferError::new(String::from("The maximum number of records must be greater than 0.")));
+        }
+
+        let eviction = match &self.eviction {
+            Some(e) => match Eviction::from_name(e) {
+                Some(ev) => ev,
+                None => { return Err(SnifferError::new(String::from("Invalid eviction policy."))); }
+            },
+            None => Eviction::Lru
         };
 
-        // Set sorting criteria for report
-        if self.sorting.is_some() && report.set_sorting(self.sorting) == false {
-            let msg = String::from("Invalid sorting criteria.");
-            return Err(SnifferError::new(msg));
+        // Set flow exporter, which relies on expiration to close flows
+        let mut exporter = None;
+        if let Some(collector) = &self.collector {
+            let version = match &self.flow_version {
+                Some(v) => match FlowVersion::from_name(v) {
+                    Some(version) => version,
+                    None => { return Err(SnifferError::new(String::from("Invalid flow export version."))); }
+                },
+                None => FlowVersion::V9
+            };
+
+            match FlowExporter::new(collector, version) {
+                Ok(e) => {
+                    report.set_flow_export(Some(e.description()));
+                    exporter = Some(e);
+                },
+                Err(e) => { return Err(SnifferError::new(format!("Couldn't export flows to {}. {}", collector, e))); }
+            }
         }
 
+        report.set_expiration(Expiration {
+            idle_timeout: self.idle_timeout,
+            active_timeout: self.active_timeout,
+            max_records: self.max_flows,
+            eviction
+        });
+
+        check_appendable(&self.closed_flows)?;
+        report.set_closed_path(self.closed_flows.clone());
+
+        let savefile = match &self.save {
+            Some(path) => match File::create(path) {
+                Ok(file) => Some(file),
+                Err(e) => { return Err(SnifferError::new(format!("Couldn't create savefile {}. {}", path, e))); }
+            },
+            None => None
+        };
+        report.set_savefile(self.save.clone());
+
+        // Set interval mode for report
+        check_appendable(&self.timeseries)?;
+        report.set_delta(self.delta);
+        report.set_timeseries_path(self.timeseries.clone());
+
         // Give to report details about capture configuration
         let filter = match &self.filter {
             Some(f) => Some(String::from(f)),
@@ -200,14 +790,25 @@ impl SnifferBuilder {
             Err(e) => { return Err(SnifferError::new(e.to_string())); }
         };
 
+        let listener = match &self.metrics {
+            Some(address) => match metrics::bind(address) {
+                Ok(l) => Some(l),
+                Err(e) => { return Err(SnifferError::new(format!("Couldn't serve metrics at {}. {}", address, e))); }
+            },
+            None => None
+        };
+
         let mut sniffer = Sniffer {
             device: self.device,
             interval: self.interval,
             filter: self.filter,
             report: Arc::new(Mutex::new(report)), 
+            exporter,
+            savefile,
             state: Arc::new(StateHandler::new()),
             report_thread: None,
-            capture_thread: None
+            capture_thread: None,
+            metrics_thread: None
         };
 
         if let Err(e) = sniffer.start_capture() {
@@ -223,6 +824,10 @@ impl SnifferBuilder {
                 Please check that a valid path was specified and that you have write permissions for the target directory.", out);
             return Err(SnifferError::new(message)); 
         }
+
+        if let Some(l) = listener {
+            sniffer.metrics_thread = Some(metrics::serve(l, Arc::clone(&sniffer.report), Arc::clone(&sniffer.state)));
+        }
         
         Ok(sniffer)
     }
@@ -233,10 +838,13 @@ pub struct Sniffer {
     interval: u64,
     device: String,
     filter: Option<String>,
+    savefile: Option<File>,
     state: Arc<StateHandler>,
     report: Arc<Mutex<TrafficReport>>,
+    exporter: Option<FlowExporter>,
     report_thread: Option<JoinHandle<()>>,
-    capture_thread: Option<JoinHandle<()>>
+    capture_thread: Option<JoinHandle<()>>,
+    metrics_thread: Option<JoinHandle<()>>
 }
 
 impl Sniffer {
@@ -257,7 +865,29 @@ impl Sniffer {
             filter: None,
             interval: DEFAULT_INTERVAL,
             sorting: None,
-            out: None
+            aggregation: None,
+            groups: None,
+            limit: None,
+            idle_timeout: None,
+            active_timeout: None,
+            max_flows: None,
+            eviction: None,
+            closed_flows: None,
+            delta: false,
+            timeseries: None,
+            columns: None,
+            oui: None,
+            bytes: None,
+            format: None,
+            sidecar: false,
+            out: None,
+            writer: None,
+            sinks: vec![],
+            collector: None,
+            flow_version: None,
+            metrics: None,
+            database: None,
+            save: None
         }
     }
 
@@ -302,7 +932,22 @@ impl Sniffer {
         report_handler.set_filter(capture.filter());
         drop(report_handler);
 
+        // An invalid filter is ignored by the capture, hence not recorded in the savefile
+        let mut savefile = match self.savefile.take() {
+            Some(file) => {
+                let applied = self.filter.as_deref().filter(|f| capture.filter().as_deref() == Some(*f));
+                match PcapngWriter::new(BufWriter::new(file), capture.datalink(), &capture.device(), applied) {
+                    Ok(writer) => Some(writer),
+                    Err(e) => { return Err(Error::IoError(e.kind())); }
+                }
+            },
+            None => None
+        };
+        let device = capture.device();
+
         self.capture_thread = Some(thread::spawn(move || {
+            let mut stats_update = Instant::now();
+
             loop {
                 match sh_capture.state() {
                     State::Running => match capture.start_capture() {
@@ -314,6 +959,7 @@ impl Sniffer {
                         }
                     },
                     State::Pausing | State::Paused => {
+                        if let Some(writer) = savefile.as_mut() { writer.flush().ok(); }
                         capture.stop_capture();
                         sh_capture.set_state(State::Paused);
                     },
@@ -324,11 +970,27 @@ impl Sniffer {
                 }
     
                 if capture.active() {
+                    let linktype = capture.datalink();
                     if let Ok(packet) = capture.next() { // handle errors
-                        let parsed = parse(&packet);
+                        if let Some(writer) = savefile.as_mut() {
+                            let ts = packet.header.ts.tv_sec as u64 * 1_000_000 + packet.header.ts.tv_usec as u64;
+                            writer.write_packet(ts, packet.header.len as usize, packet.data).ok();
+                        }
+
+                        let mut parsed = parse(linktype, &packet);
+                        parsed.interface = BTreeSet::from([String::from(&device)]);
                         let mut rh = rh_capture.lock().unwrap();
                         rh.new_detail(parsed);
                     }
+
+                    // Statistics of the capture are only needed by the metrics endpoint, now and then
+                    if stats_update.elapsed() >= STATS_INTERVAL {
+                        let stats = capture.stats();
+                        rh_capture.lock().unwrap().set_capture_stats(stats);
+                        stats_update = Instant::now();
+
+                        if let Some(writer) = savefile.as_mut() { writer.flush().ok(); }
+                    }
                 }
             }
         }));
@@ -340,6 +1002,7 @@ impl Sniffer {
         let sh_report = Arc::clone(&self.state);
         let rh_report = Arc::clone(&self.report);
         let interval = self.interval;
+        let mut exporter = self.exporter.take();
 
         let res = self.report.lock().unwrap().write();
         if res.is_err() {
@@ -354,12 +1017,24 @@ impl Sniffer {
 
                 match sh_report.state() {
                     State::Pausing | State::Paused => sh_report.set_state(State::Paused),
-                    State::Stopped => break,
+                    State::Stopped => {
+                        let flows = rh_report.lock().unwrap().pending_flows(true);
+                        if let Some(exporter) = exporter.as_mut() { exporter.export(&flows).ok(); }
+                        break;
+                    },
                     _ => {}
                 }
     
-                let mut rh = rh_report.lock().unwrap();
-                rh.write().ok();
+                let flows = {
+                    let mut rh = rh_report.lock().unwrap();
+                    rh.expire(Local::now().timestamp());
+                    rh.write().ok();
+                    rh.roll_interval();
+                    rh.pending_flows(false)
+                };
+
+                // Flows are encoded and sent once the report is released, not to hold up the capture
+                if let Some(exporter) = exporter.as_mut() { exporter.export(&flows).ok(); }
             }
         }));
 
@@ -408,9 +1083,99 @@ impl Sniffer {
         if let Some(capture_thread) = self.capture_thread {
             capture_thread.join().ok();
         }
+
+        if let Some(metrics_thread) = self.metrics_thread {
+            metrics_thread.join().ok();
+        }
     }
 }
 
+// Sets the criteria shaping the report: sorting, aggregation (with groups), limit and additional columns
+fn set_layout(report: &mut TrafficReport, sorting: &Option<String>, aggregation: &Option<String>, groups: &Option<String>,
+              limit: Option<usize>, columns: &Option<String>) -> Result<(), SnifferError> {
+    if sorting.is_some() && !report.set_sorting(sorting.clone()) {
+        return Err(SnifferError::new(String::from("Invalid sorting criteria.")));
+    }
+
+    let mut aggr = match aggregation {
+        Some(a) => match Aggregation::parse(a) {
+            Some(aggr) => aggr,
+            None => { return Err(SnifferError::new(String::from("Invalid aggregation criteria."))); }
+        },
+        None => Aggregation::default()
+    };
+
+    if let Some(groups) = groups {
+        if !aggr.set_groups(groups) {
+            return Err(SnifferError::new(String::from("Invalid groups. Please use the name=CIDR syntax.")));
+        }
+    }
+    report.set_aggregation(aggr);
+    report.set_limit(limit);
+
+    if let Some(columns) = columns {
+        match parse_fields(columns) {
+            Some(fields) => if !report.set_columns(fields) {
+                return Err(SnifferError::new(String::from("Invalid columns. The keys of the records, their bytes, \
+                        packets, first_ts and last_ts are always listed, and each column can be added once.")));
+            },
+            None => { return Err(SnifferError::new(String::from("Invalid columns."))); }
+        }
+    }
+
+    Ok(())
+}
+
+// Sets the OUI database resolving vendors, if given, or if vendors are requested and one is found
+fn set_oui(report: &mut TrafficReport, path: &Option<String>, columns: &Option<String>) -> Result<(), SnifferError> {
+    let database = match path {
+        Some(p) => match OuiDatabase::load(p) {
+            Ok(database) => Some(database),
+            Err(e) => { return Err(SnifferError::new(format!("Couldn't load OUI database {}. {}", p, e))); }
+        },
+        None => {
+            let vendors = columns.as_deref().and_then(parse_fields).unwrap_or_default().iter()
+                    .any(|f| matches!(f, Field::SrcVendor | Field::DstVendor));
+            if !vendors {
+                return Ok(());
+            }
+
+            match OuiDatabase::find() {
+                Some(database) => Some(database),
+                None => { return Err(SnifferError::new(String::from("No OUI database found. Please point to one \
+                        (the Wireshark manuf file or the IEEE oui.txt registry) to resolve vendors."))); }
+            }
+        }
+    };
+
+    report.set_oui(database);
+    Ok(())
+}
+
+// Parses the name of an output format, text being the default one
+fn parse_format(format: &Option<String>) -> Result<OutputFormat, SnifferError> {
+    match format {
+        Some(f) => match OutputFormat::from_name(f) {
+            Some(format) => Ok(format),
+            None => Err(SnifferError::new(String::from("Invalid output format.")))
+        },
+        None => Ok(OutputFormat::Text)
+    }
+}
+
+// Makes sure that the given file, if any, can be opened to append content to it
+fn check_appendable(path: &Option<String>) -> Result<(), SnifferError> {
+    if let Some(p) = path {
+        if OpenOptions::new().create(true).append(true).open(p).is_err() {
+            let message = format!("Something went wrong trying to open {}. \
+                Please check that a valid path was specified and that you have write permissions for the target directory.", p);
+            return Err(SnifferError::new(message));
+        }
+    }
+
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct SnifferError {
     msg: String
@@ -0,0 +1,149 @@
+use super::field::Field;
+use super::report::TrafficDetail;
+use std::cmp::Ordering;
+
+pub struct SortKey {
+    field: Field,
+    descending: bool
+}
+
+impl SortKey {
+    /// Parses a key given as name[:asc|:desc] (ascending if no direction is specified)
+    fn parse(spec: &str) -> Option<SortKey> {
+        let (name, direction) = match spec.split_once(':') {
+            Some((n, d)) => (n, d.trim()),
+            None => (spec, "asc")
+        };
+
+        let descending = match direction {
+            "asc" => false,
+            "desc" => true,
+            _ => { return None; }
+        };
+
+        Field::from_name(name).map(|field| SortKey { field, descending })
+    }
+
+    /// Parses a key given in the XY legacy syntax, where X is the index of the field
+    /// and Y is either 'G' (Greater to Lower) or 'L' (Lower to Greater)
+    fn parse_legacy(spec: &str) -> Option<SortKey> {
+        let mut chars = spec.chars();
+        let field = Field::from_index(chars.next()?.to_digit(10)?)?;
+
+        let descending = match chars.next()? {
+            'L' => false,
+            'G' => true,
+            _ => { return None; }
+        };
+
+        if chars.next().is_some() { return None; }
+        Some(SortKey { field, descending })
+    }
+
+    fn compare(&self, a: &TrafficDetail, b: &TrafficDetail) -> Ordering {
+        let ord = self.field.compare(a, b);
+        if self.descending { ord.reverse() } else { ord }
+    }
+}
+
+/// List of keys used to sort the report, applied in the given order
+#[derive(Default)]
+pub struct Sorting {
+    keys: Vec<SortKey>
+}
+
+impl Sorting {
+    /// Parses a comma separated list of keys, e.g. "bytes:desc,dst_ip:asc".
+    /// The XY legacy syntax (e.g. "5G") is accepted as well.
+    pub fn parse(spec: &str) -> Option<Sorting> {
+        if let Some(key) = SortKey::parse_legacy(spec.trim()) {
+            return Some(Sorting { keys: vec![key] });
+        }
+
+        let mut keys = vec![];
+        for key in spec.split(',') {
+            keys.push(SortKey::parse(key.trim())?);
+        }
+
+        Some(Sorting { keys })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn compare(&self, a: &TrafficDetail, b: &TrafficDetail) -> Ordering {
+        for key in &self.keys {
+            let ord = key.compare(a, b);
+            if ord != Ordering::Equal {
+                return ord;
+            }
+        }
+
+        Ordering::Equal
+    }
+
+    pub fn description(&self) -> String {
+        let keys: Vec<String> = self.keys.iter()
+                .map(|k| format!("{}:{}", k.field.name(), if k.descending { "desc" } else { "asc" }))
+                .collect();
+        keys.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::TrafficReport;
+    use std::collections::BTreeSet;
+
+    fn detail(dst_ip: &str, src_port: &str, payload_bytes: usize, npackets: usize) -> TrafficDetail {
+        let mut detail = TrafficDetail::new();
+        detail.src_ip = String::from("10.0.0.1");
+        detail.dst_ip = String::from(dst_ip);
+        detail.src_port = String::from(src_port);
+        detail.dst_port = String::from("80");
+        detail.protocols = BTreeSet::from([String::from("TCP")]);
+        detail.bytes = payload_bytes;
+        detail.payload_bytes = payload_bytes;
+        detail.npackets = npackets;
+        detail
+    }
+
+    #[test]
+    fn keys_are_parsed_in_both_syntaxes() {
+        assert_eq!(Sorting::parse("bytes:desc, dst_ip").unwrap().description(), "bytes:desc, dst_ip:asc");
+        assert_eq!(Sorting::parse("5G").unwrap().description(), "bytes:desc");
+        assert_eq!(Sorting::parse("0L").unwrap().description(), "src_ip:asc");
+
+        for spec in ["bytes:down", "9G", "5X", "5GG", "nothing", "bytes,"] {
+            assert!(Sorting::parse(spec).is_none(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn later_keys_break_the_ties_of_the_former() {
+        let sorting = Sorting::parse("bytes:desc,dst_ip:asc").unwrap();
+        let mut details = [detail("10.0.0.10", "1", 100, 1), detail("10.0.0.9", "1", 100, 1),
+                           detail("10.0.0.2", "1", 50, 1), detail("10.0.0.3", "1", 200, 1)];
+        details.sort_by(|a, b| sorting.compare(a, b));
+
+        // Addresses are compared as such, not as text
+        let order: Vec<&str> = details.iter().map(|d| d.dst_ip.as_str()).collect();
+        assert_eq!(order, vec!["10.0.0.3", "10.0.0.9", "10.0.0.10", "10.0.0.2"]);
+    }
+
+    #[test]
+    fn records_left_equal_are_listed_by_key() {
+        let mut report = TrafficReport::new();
+        assert!(report.set_sorting(Some(String::from("packets:desc"))));
+        for (port, packets) in [("3000", 1), ("1000", 1), ("4000", 2), ("2000", 1)] {
+            report.new_detail(detail("10.0.0.2", port, 100, packets));
+        }
+
+        for _ in 0..3 {
+            let ports: Vec<String> = report.snapshot().records.iter().map(|r| String::from(&r.src_port)).collect();
+            assert_eq!(ports, vec!["4000", "1000", "2000", "3000"]);
+        }
+    }
+}
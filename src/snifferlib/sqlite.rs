@@ -0,0 +1,297 @@
+use super::output::Snapshot;
+use super::report::format_list;
+use super::sink::ReportSink;
+use rusqlite::{params, Connection};
+use std::io::Error;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS captures (
+    id          INTEGER PRIMARY KEY,
+    started_at  INTEGER NOT NULL,
+    delta       INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS capture_metadata (
+    capture_id  INTEGER NOT NULL REFERENCES captures(id),
+    name        TEXT NOT NULL,
+    value       TEXT NOT NULL,
+    PRIMARY KEY (capture_id, name)
+);
+
+CREATE TABLE IF NOT EXISTS snapshots (
+    id          INTEGER PRIMARY KEY,
+    capture_id  INTEGER NOT NULL REFERENCES captures(id),
+    taken_at    INTEGER NOT NULL,
+    records     INTEGER NOT NULL,
+    bytes       INTEGER NOT NULL,
+    packets     INTEGER NOT NULL,
+    others      INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS flows (
+    snapshot_id     INTEGER NOT NULL REFERENCES snapshots(id),
+    capture_id      INTEGER REFERENCES captures(id),
+    flow_key        TEXT,
+    src_ip          TEXT,
+    dst_ip          TEXT,
+    src_port        INTEGER,
+    dst_port        INTEGER,
+    protocols       TEXT,
+    interface       TEXT,
+    vlan            TEXT,
+    ext_headers     TEXT,
+    src_mac         TEXT,
+    dst_mac         TEXT,
+    src_vendor      TEXT,
+    dst_vendor      TEXT,
+    bytes           INTEGER NOT NULL,
+    packets         INTEGER NOT NULL,
+    frame_bytes     INTEGER NOT NULL,
+    captured_bytes  INTEGER NOT NULL,
+    ip_bytes        INTEGER NOT NULL,
+    payload_bytes   INTEGER NOT NULL,
+    first_ts        INTEGER NOT NULL,
+    last_ts         INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS snapshots_taken_at ON snapshots (taken_at);
+CREATE INDEX IF NOT EXISTS flows_snapshot ON flows (snapshot_id);
+CREATE INDEX IF NOT EXISTS flows_key ON flows (capture_id, flow_key);
+CREATE INDEX IF NOT EXISTS flows_src_ip ON flows (src_ip);
+CREATE INDEX IF NOT EXISTS flows_dst_ip ON flows (dst_ip);
+CREATE INDEX IF NOT EXISTS flows_src_port ON flows (src_port);
+CREATE INDEX IF NOT EXISTS flows_dst_port ON flows (dst_port);
+CREATE INDEX IF NOT EXISTS flows_time ON flows (first_ts, last_ts);
+";
+
+/// Stores each snapshot of the report into a SQLite database, so that past captures can be
+/// queried with SQL after the sniffer stops. The database is created if missing, and each
+/// capture is added to the ones already stored. Timestamps are seconds since epoch.
+/// 
+/// The schema is made of the following tables:
+///  - captures (id, started_at, delta): one row per capture, telling whether its flows hold the counters
+///    of each interval (delta mode) rather than cumulated ones
+///  - capture_metadata (capture_id, name, value): the configuration of each capture
+///  - snapshots (id, capture_id, taken_at, records, bytes, packets, others): one row per interval,
+///    with the number of records and their totals, and the number of records beyond the limit
+///  - flows (snapshot_id, capture_id, flow_key, src_ip, dst_ip, src_port, dst_port, protocols, interface, vlan,
+///    ext_headers, src_mac, dst_mac, src_vendor, dst_vendor, bytes, packets, frame_bytes, captured_bytes, ip_bytes,
+///    payload_bytes, first_ts, last_ts): the records of the capture, lists of values being separated by ", "
+/// 
+/// All the records updated during an interval are stored, including the ones beyond the limit of the report
+/// and the ones closed (expired or evicted) during the interval.
+/// In cumulative mode, each record has a single row, identified by its key within the capture and updated
+/// with the last counters (snapshot_id being the last snapshot that updated it). In delta mode, each interval
+/// adds a row per active record, with the counters of that interval.
+/// 
+/// Flows are indexed by addresses, ports and time, while snapshots are indexed by time.
+/// Fields that are not part of the aggregation key, or that are empty, are NULL.
+/// 
+/// #Example
+/// ```no_run
+/// use snifferlib::{Sniffer, SqliteSink};
+/// 
+/// let sink = SqliteSink::new("rsniffer.db").unwrap();
+/// let mut builder = Sniffer::builder();
+/// builder = builder.sink(Box::new(sink));
+/// ```
+pub struct SqliteSink {
+    path: String,
+    connection: Connection,
+    capture_id: Option<i64>
+}
+
+impl SqliteSink {
+    pub fn new(path: &str) -> Result<Self, Error> {
+        let connection = Connection::open(path).map_err(Error::other)?;
+        connection.execute_batch(SCHEMA).map_err(Error::other)?;
+
+        Ok(Self {
+            path: String::from(path),
+            connection,
+            capture_id: None
+        })
+    }
+
+    fn store(&mut self, snapshot: &Snapshot) -> Result<(), rusqlite::Error> {
+        let tx = self.connection.transaction()?;
+
+        // The capture is stored along with its first snapshot
+        let capture_id = match self.capture_id {
+            Some(id) => id,
+            None => {
+                tx.execute("INSERT INTO captures (started_at, delta) VALUES (?1, ?2)",
+                        params![snapshot.timestamp.timestamp(), snapshot.delta])?;
+                let id = tx.last_insert_rowid();
+
+                for entry in &snapshot.metadata {
+                    tx.execute("INSERT INTO capture_metadata (capture_id, name, value) VALUES (?1, ?2, ?3)",
+                            params![id, entry.name, entry.value])?;
+                }
+                id
+            }
+        };
+
+        let (nrecords, total) = &snapshot.total;
+        let others = snapshot.others.as_ref().map(|o| o.0).unwrap_or(0);
+        tx.execute("INSERT INTO snapshots (capture_id, taken_at, records, bytes, packets, others) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![capture_id, snapshot.timestamp.timestamp(), *nrecords as i64, total.bytes as i64, total.npackets as i64, others as i64])?;
+        let snapshot_id = tx.last_insert_rowid();
+
+        {
+            let mut update = tx.prepare("UPDATE flows SET snapshot_id = ?1, bytes = ?2, packets = ?3, frame_bytes = ?4, \
+                    captured_bytes = ?5, ip_bytes = ?6, payload_bytes = ?7, last_ts = ?8, protocols = ?9, interface = ?10, \
+                    vlan = ?11, ext_headers = ?12, src_mac = ?13, dst_mac = ?14, src_vendor = ?15, dst_vendor = ?16 \
+                    WHERE capture_id = ?17 AND flow_key = ?18")?;
+            let mut insert = tx.prepare("INSERT INTO flows (snapshot_id, capture_id, flow_key, src_ip, dst_ip, src_port, dst_port, \
+                    protocols, interface, vlan, ext_headers, src_mac, dst_mac, src_vendor, dst_vendor, bytes, packets, \
+                    frame_bytes, captured_bytes, ip_bytes, payload_bytes, first_ts, last_ts) \
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23)")?;
+
+            for d in &snapshot.active {
+                let key = snapshot.key(d);
+                let lists = [&d.protocols, &d.interface, &d.vlan, &d.ext_headers, &d.src_mac, &d.dst_mac,
+                             &d.src_vendor, &d.dst_vendor].map(format_list);
+                let [protocols, interface, vlan, ext_headers, src_mac, dst_mac, src_vendor, dst_vendor] =
+                        lists.each_ref().map(|l| non_empty(l));
+
+                // In cumulative mode, records already stored only get their counters (and lists) updated
+                if !snapshot.delta && update.execute(params![snapshot_id, d.bytes as i64, d.npackets as i64, d.frame_bytes as i64,
+                        d.captured_bytes as i64, d.ip_bytes as i64, d.payload_bytes as i64, d.last_ts, protocols, interface,
+                        vlan, ext_headers, src_mac, dst_mac, src_vendor, dst_vendor, capture_id, key])? > 0 {
+                    continue;
+                }
+
+                insert.execute(params![snapshot_id, capture_id, key, non_empty(&d.src_ip), non_empty(&d.dst_ip),
+                        d.src_port.parse::<u16>().ok(), d.dst_port.parse::<u16>().ok(), protocols, interface, vlan,
+                        ext_headers, src_mac, dst_mac, src_vendor, dst_vendor, d.bytes as i64, d.npackets as i64,
+                        d.frame_bytes as i64, d.captured_bytes as i64, d.ip_bytes as i64, d.payload_bytes as i64,
+                        d.first_ts, d.last_ts])?;
+            }
+        }
+
+        tx.commit()?;
+        self.capture_id = Some(capture_id);
+        Ok(())
+    }
+}
+
+impl ReportSink for SqliteSink {
+    fn write(&mut self, snapshot: &Snapshot) -> Result<(), Error> {
+        self.store(snapshot).map_err(Error::other)
+    }
+
+    fn description(&self) -> String {
+        format!("sqlite to {}", self.path)
+    }
+}
+
+fn non_empty(value: &str) -> Option<&str> {
+    if value.is_empty() { None } else { Some(value) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{TrafficDetail, TrafficReport};
+    use crate::expiration::Expiration;
+    use crate::aggregation::Aggregation;
+    use crate::input::load_records;
+    use std::collections::BTreeSet;
+
+    fn detail(src_port: &str, ts: i64) -> TrafficDetail {
+        let mut detail = TrafficDetail::new();
+        detail.src_ip = String::from("10.0.0.1");
+        detail.dst_ip = String::from("10.0.0.2");
+        detail.src_port = String::from(src_port);
+        detail.dst_port = String::from("80");
+        detail.protocols = BTreeSet::from([String::from("TCP")]);
+        detail.payload_bytes = 100;
+        detail.first_ts = ts;
+        detail.last_ts = ts;
+        detail
+    }
+
+    fn flows(path: &str) -> Vec<(String, i64, i64)> {
+        let connection = Connection::open(path).unwrap();
+        let mut select = connection.prepare("SELECT src_port, packets, bytes FROM flows ORDER BY src_port").unwrap();
+        select.query_map([], |row| Ok((row.get::<_, i64>(0)?.to_string(), row.get(1)?, row.get(2)?)))
+                .unwrap().collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn cumulative_flows_are_stored_once_whatever_the_limit() {
+        let path = std::env::temp_dir().join(format!("rsniffer-sqlite-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::remove_file(path).ok();
+
+        let mut report = TrafficReport::new();
+        report.set_limit(Some(1));
+        let mut sink = SqliteSink::new(path).unwrap();
+
+        report.new_detail(detail("1000", 1));
+        report.new_detail(detail("2000", 1));
+        sink.write(&report.snapshot()).unwrap();
+        report.roll_interval();
+
+        report.new_detail(detail("1000", 2));
+        sink.write(&report.snapshot()).unwrap();
+
+        assert_eq!(flows(path), vec![(String::from("1000"), 2, 200), (String::from("2000"), 1, 100)]);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn layer2_keys_are_stored_and_read_back() {
+        let path = std::env::temp_dir().join(format!("rsniffer-sqlite-vlan-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::remove_file(path).ok();
+
+        let mut report = TrafficReport::new();
+        report.set_aggregation(Aggregation::parse("vlan").unwrap());
+        let mut sink = SqliteSink::new(path).unwrap();
+
+        for (vlan, interface) in [("100", "eth0"), ("200", "eth1")] {
+            let mut detail = detail("1000", 1);
+            detail.vlan = BTreeSet::from([String::from(vlan)]);
+            detail.interface = BTreeSet::from([String::from(interface)]);
+            report.new_detail(detail);
+        }
+        sink.write(&report.snapshot()).unwrap();
+
+        let mut records: Vec<(String, String, usize)> = load_records(path).unwrap().iter()
+                .map(|r| (format_list(&r.vlan), format_list(&r.interface), r.npackets))
+                .collect();
+        records.sort();
+        assert_eq!(records, vec![(String::from("100"), String::from("eth0"), 1), (String::from("200"), String::from("eth1"), 1)]);
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn expired_flows_keep_their_last_counters() {
+        let path = std::env::temp_dir().join(format!("rsniffer-sqlite-expired-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::remove_file(path).ok();
+
+        let mut report = TrafficReport::new();
+        report.set_expiration(Expiration { idle_timeout: Some(10), ..Expiration::default() });
+        let mut sink = SqliteSink::new(path).unwrap();
+
+        report.new_detail(detail("1000", 1));
+        sink.write(&report.snapshot()).unwrap();
+        report.roll_interval();
+
+        // The record gets a packet and expires before the end of the interval
+        report.new_detail(detail("1000", 5));
+        report.expire(100);
+        assert_eq!(report.len(), 0);
+        sink.write(&report.snapshot()).unwrap();
+        report.roll_interval();
+
+        assert_eq!(flows(path), vec![(String::from("1000"), 2, 200)]);
+
+        // Once written, the closed record is forgotten
+        assert!(report.snapshot().active.is_empty());
+        std::fs::remove_file(path).ok();
+    }
+}